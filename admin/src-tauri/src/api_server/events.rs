@@ -0,0 +1,289 @@
+// src/api_server/events.rs
+
+use futures_util::stream::StreamExt;
+use mongodb::{
+    bson::{Bson, Document},
+    change_stream::event::{ChangeStreamEvent, OperationType},
+};
+use serde::Serialize;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::api_server::services::lifecycle_service::is_protected;
+use crate::mongodb_manager::MongoDbState;
+
+// Number of events a slow subscriber may fall behind before it is told to resync
+const EVENT_BUS_CAPACITY: usize = 256;
+
+// How long to wait before retrying the change stream (no connection yet, stream error)
+const CHANGE_STREAM_RETRY: Duration = Duration::from_secs(5);
+
+// Name of the event mirrored to the Tauri frontend
+pub const TAURI_CHANGE_EVENT: &str = "collection-change";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Insert,
+    Update,
+    Delete,
+    Archive,
+    Recover,
+    Pin,
+    Unpin,
+    // Every document of the collection went away; these carry no document ids
+    Truncate,
+    Drop,
+}
+
+// A change to one or more documents of a collection
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub collection: String,
+    pub action: ChangeAction,
+    pub document_ids: Vec<String>,
+    pub user_id: Option<String>,
+    // "api" for events published by the handlers, "change_stream" for MongoDB change streams
+    pub source: &'static str,
+    pub timestamp: String,
+}
+
+// In-process broadcast bus for document changes.
+// The document handlers publish after every successful write. When a MongoDB change
+// stream is running it becomes the only source, so handler events are dropped to
+// avoid delivering every change twice.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChangeEvent>,
+    change_stream_active: Arc<AtomicBool>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self {
+            sender,
+            change_stream_active: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+
+    // Publish a change made by one of the API handlers
+    pub fn publish(
+        &self,
+        collection: &str,
+        action: ChangeAction,
+        document_ids: Vec<String>,
+        user_id: Option<String>,
+    ) {
+        if document_ids.is_empty() || self.change_stream_active.load(Ordering::Relaxed) {
+            return;
+        }
+
+        self.send(ChangeEvent {
+            collection: collection.to_string(),
+            action,
+            document_ids,
+            user_id,
+            source: "api",
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        });
+    }
+
+    // Publish a change to a whole collection (truncate, drop)
+    pub fn publish_collection(&self, collection: &str, action: ChangeAction) {
+        if self.change_stream_active.load(Ordering::Relaxed) {
+            return;
+        }
+
+        self.send(ChangeEvent {
+            collection: collection.to_string(),
+            action,
+            document_ids: Vec::new(),
+            user_id: None,
+            source: "api",
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        });
+    }
+
+    fn send(&self, event: ChangeEvent) {
+        // An error only means nobody is subscribed right now
+        let _ = self.sender.send(event);
+    }
+}
+
+// Forward every bus event to the Tauri frontend as "collection-change"
+pub fn forward_to_frontend(app_handle: AppHandle, bus: EventBus) {
+    let mut receiver = bus.subscribe();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    if let Err(e) = app_handle.emit(TAURI_CHANGE_EVENT, &event) {
+                        warn!("Failed to emit change event to frontend: {}", e);
+                    }
+                },
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Frontend event forwarder skipped {} change events", skipped);
+                },
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+// Watch the database with a change stream when MongoDB runs as a replica set.
+// Standalone servers don't support change streams, in which case the handlers
+// remain the only source of events.
+pub fn spawn_change_stream_watcher(mongodb_state: MongoDbState, bus: EventBus) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let db = match mongodb_state.get_database().await {
+                Ok(db) => db,
+                Err(_) => {
+                    tokio::time::sleep(CHANGE_STREAM_RETRY).await;
+                    continue;
+                }
+            };
+
+            let is_replica_set = db.run_command(mongodb::bson::doc! { "hello": 1 }, None)
+                .await
+                .map(|hello| hello.contains_key("setName"))
+                .unwrap_or(false);
+            if !is_replica_set {
+                info!("MongoDB is not a replica set, change events come from the API handlers only");
+                return;
+            }
+
+            let mut stream = match db.watch(None, None).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to open change stream: {}", e);
+                    tokio::time::sleep(CHANGE_STREAM_RETRY).await;
+                    continue;
+                }
+            };
+
+            info!("Watching database '{}' for changes", db.name());
+            bus.change_stream_active.store(true, Ordering::Relaxed);
+
+            while let Some(result) = stream.next().await {
+                match result {
+                    Ok(change) => {
+                        if let Some(event) = change_to_event(change) {
+                            bus.send(event);
+                        }
+                    },
+                    Err(e) => {
+                        warn!("Change stream error: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            bus.change_stream_active.store(false, Ordering::Relaxed);
+            tokio::time::sleep(CHANGE_STREAM_RETRY).await;
+        }
+    });
+}
+
+// A document _id as events carry it: ObjectIds as hex, strings as they are
+pub fn id_text(id: &Bson) -> String {
+    match id {
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// Translate a raw change stream event into the same shape the handlers publish
+fn change_to_event(change: ChangeStreamEvent<Document>) -> Option<ChangeEvent> {
+    let collection = change.ns.as_ref()?.coll.clone()?;
+    // Sessions, users, migrations and snapshots aren't table data
+    if is_protected(&collection) {
+        return None;
+    }
+
+    let (action, user_id) = match change.operation_type {
+        OperationType::Insert => (ChangeAction::Insert, None),
+        OperationType::Replace => (ChangeAction::Update, None),
+        OperationType::Delete => (ChangeAction::Delete, None),
+        OperationType::Drop => (ChangeAction::Drop, None),
+        OperationType::Update => {
+            let updated = change.update_description.as_ref().map(|d| &d.updated_fields);
+            updated.and_then(classify_update).unwrap_or((ChangeAction::Update, None))
+        },
+        _ => return None,
+    };
+    let document_ids = match change.document_key.as_ref() {
+        Some(key) => vec![id_text(key.get("_id")?)],
+        None if action == ChangeAction::Drop => Vec::new(),
+        None => return None,
+    };
+
+    Some(ChangeEvent {
+        collection,
+        action,
+        document_ids,
+        user_id,
+        source: "change_stream",
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+// Archive/recover and pin/unpin show up as updates; recognise them by the history
+// entry the handlers push alongside the flag change.
+fn classify_update(updated_fields: &Document) -> Option<(ChangeAction, Option<String>)> {
+    for history in ["archive_history", "pinned_history"] {
+        let entry = match last_history_entry(updated_fields, history) {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        let action = match entry.get_str("action").ok()? {
+            "archive" => ChangeAction::Archive,
+            "recover" => ChangeAction::Recover,
+            "pin" => ChangeAction::Pin,
+            "unpin" => ChangeAction::Unpin,
+            _ => continue,
+        };
+        let user_id = match entry.get("user_id") {
+            Some(Bson::ObjectId(oid)) => Some(oid.to_hex()),
+            Some(Bson::String(s)) => Some(s.clone()),
+            _ => None,
+        };
+        return Some((action, user_id));
+    }
+    None
+}
+
+// A $push shows up as "<field>.<index>", or as the whole array when it was created
+fn last_history_entry<'a>(updated_fields: &'a Document, field: &str) -> Option<&'a Document> {
+    let prefix = format!("{}.", field);
+    updated_fields.iter()
+        .filter_map(|(key, value)| {
+            if key == field {
+                value.as_array()?.last()?.as_document()
+            } else if key.starts_with(&prefix) {
+                value.as_document()
+            } else {
+                None
+            }
+        })
+        .last()
+}
@@ -42,6 +42,8 @@ pub async fn auth_login_handler(
                 success: true,
                 data: Some(LoginResponse { token }),
                 error: None,
+                field_errors: None,
+                error_code: None,
             }))
         },
         Err(e) => {
@@ -141,6 +143,8 @@ pub async fn auth_get_me_handler(
             email: user.get_str("email").unwrap_or_default().to_string(),
         }),
         error: None,
+        field_errors: None,
+        error_code: None,
     }))
 }
 
@@ -159,6 +163,8 @@ pub async fn auth_register_handler(
                 success: true,
                 data: None,
                 error: None,
+                field_errors: None,
+                error_code: None,
             }))
         },
         Err(e) => {
@@ -184,5 +190,7 @@ pub async fn auth_check_session_handler(
         success: true,
         data: Some(SessionCheckResponse { valid }),
         error: None,
+        field_errors: None,
+        error_code: None,
     }))
 }
@@ -3,8 +3,9 @@
 use axum::{
     http::StatusCode,
     Json,
-    extract::{State, Path},
-    response::IntoResponse,
+    body::Bytes,
+    extract::{State, Path, Query},
+    response::{IntoResponse, Response},
 };
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -12,14 +13,25 @@ use tokio::sync::Mutex;
 use mongodb::Database;
 use mongodb::bson::{doc, Document};
 use anyhow::Result;
-use std::collections::HashSet;
-use futures_util::stream::StreamExt;
+use std::collections::{HashMap, HashSet};
 
 use crate::api_server::state::ApiServerState;
-use crate::api_server::models::{ApiResponse, error_response};
-use crate::api_server::services::database_service::get_database;
-use crate::api_server::services::get_collection_schema_with_ui;
+use crate::api_server::models::{ApiResponse, AddFieldPayload, LifecyclePayload, UpdateFieldPayload, error_response};
+use crate::api_server::services::database_service::{get_admin_database, get_database};
 use crate::api_server::services::update_ui_metadata;
+use crate::api_server::services::schema_editor_service::{add_field, remove_field, update_field};
+use crate::api_server::services::collection_service::{create_collection, CollectionDefinition, IndexDefinition};
+use crate::api_server::services::validation_service::{violation_report, violations_csv, ViolationReport};
+use crate::api_server::services::inference_service::{infer_from_collection, DEFAULT_SAMPLE_SIZE, MAX_SAMPLE_SIZE};
+use crate::api_server::services::lifecycle_service::{
+    confirm, execute, request_confirmation, ConfirmationRequest, LifecycleOperation, LifecycleOutcome,
+};
+use crate::api_server::services::snapshot_service::is_snapshot_collection;
+use crate::api_server::services::index_service::{create_index, drop_index, list_indexes, unique_keys, IndexInfo, IndexOverview, UniqueKey};
+
+// Ids quoted per field/rule in a violation report, unless `samples` asks for another number
+const DEFAULT_VIOLATION_SAMPLES: usize = 10;
+const MAX_VIOLATION_SAMPLES: usize = 100;
 
 // Collection handlers
 pub async fn list_collections_handler(
@@ -30,11 +42,15 @@ pub async fn list_collections_handler(
     match get_database(mongodb_state).await {
         Ok(db) => {
             match db.list_collection_names(None).await {
-                Ok(collections) => {
+                Ok(mut collections) => {
+                    // Snapshots are listed under /api/snapshots, not as collections
+                    collections.retain(|name| !is_snapshot_collection(name));
                     (StatusCode::OK, Json(ApiResponse {
                         success: true,
                         data: Some(collections),
                         error: None,
+                        field_errors: None,
+                        error_code: None,
                     }))
                 },
                 Err(e) => error_response::<Vec<String>>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
@@ -44,74 +60,101 @@ pub async fn list_collections_handler(
     }
 }
 
+// Create a collection from a declarative definition: validator, indexes and ui_metadata
+pub async fn create_collection_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Json(definition): Json<CollectionDefinition>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<Document>(status, e),
+    };
 
-pub async fn get_required_and_unique_fields(db: &Database, coll_name: &str) -> Result<Vec<String>, mongodb::error::Error> {
-    // Retrieve collection information to extract required fields
-    let coll_info = db.run_command(doc! {
-        "listCollections": 1,
-        "filter": { "name": coll_name },
-        "nameOnly": false,
-    }, None).await?;
-
-    let required_fields = extract_required_fields(&coll_info).unwrap_or_default();
+    // A lookup before the collection existed may have cached "not found"
+    let result = create_collection(&db, &definition).await;
+    state.schema_cache.invalidate(&definition.name);
+    match result {
+        Ok(schema) => (StatusCode::CREATED, Json(ApiResponse {
+            success: true,
+            data: Some(schema),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err((status, e)) => error_response::<Document>(status, e),
+    }
+}
 
-    // Retrieve all indexes for the collection
-    let mut cursor = db.collection::<Document>(coll_name).list_indexes(None).await?;
+// Fields that alone identify a document: single-field unique indexes on required fields.
+// Fields of compound unique indexes don't count; together they form the natural key instead.
+pub async fn get_required_and_unique_fields(db: &Database, coll_name: &str, schema: &Document) -> Result<Vec<String>, mongodb::error::Error> {
+    let keys = unique_keys(&db.collection::<Document>(coll_name)).await?;
+    Ok(required_and_unique_fields(&keys, schema))
+}
 
-    // Collect all fields from unique indexes
-    let mut unique_fields = HashSet::new();
-    
-    // Use StreamExt to iterate over the cursor asynchronously
-    while let Some(index_result) = cursor.next().await {
-        if let Ok(index) = index_result {
-            // Check if this index is unique
-            if index.options.as_ref().and_then(|opts| opts.unique).unwrap_or(false) {
-                // Extract the key fields from this unique index
-                for (field, _) in index.keys.iter() {
-                    unique_fields.insert(field.clone());
-                }
-            }
-        }
-    }
+fn required_and_unique_fields(keys: &[UniqueKey], schema: &Document) -> Vec<String> {
+    let unique_fields: HashSet<&String> = keys.iter()
+        .filter(|key| key.fields.len() == 1 && key.partial_filter.is_none())
+        .flat_map(|key| &key.fields)
+        .collect();
 
     // Find intersection of required and unique fields
-    let result: Vec<String> = required_fields.into_iter()
+    required_fields(schema).into_iter()
         .filter(|field| unique_fields.contains(field))
-        .collect();
+        .collect()
+}
 
-    Ok(result)
+// Get the fields of the collection's natural key, in index order.
+// Picks the first unique index whose fields are all required, otherwise the first
+// unique index at all, so compound keys like attendance's (school_id, time_in_date) work.
+pub async fn get_natural_key_fields(db: &Database, coll_name: &str, schema: &Document) -> Result<Vec<String>, mongodb::error::Error> {
+    let keys = unique_keys(&db.collection::<Document>(coll_name)).await?;
+    Ok(natural_key_fields(&keys, schema))
 }
 
-fn extract_required_fields(coll_info: &Document) -> Option<Vec<String>> {
-    let cursor = coll_info.get_document("cursor").ok()?;
-    let first_batch = cursor.get_array("firstBatch").ok()?;
-    let coll_doc = first_batch.first()?.as_document()?;
-    let options = coll_doc.get_document("options").ok()?;
-    let validator = options.get_document("validator").ok()?;
-    let json_schema = validator.get_document("$jsonSchema").ok()?;
-    let required = json_schema.get_array("required").ok()?;
+fn natural_key_fields(keys: &[UniqueKey], schema: &Document) -> Vec<String> {
+    let required_fields: HashSet<String> = required_fields(schema).into_iter().collect();
 
-    Some(required.iter()
-        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-        .collect())
+    // A partial index only constrains some documents, so it can't identify any of them
+    let mut candidates = keys.iter().filter(|key| key.partial_filter.is_none()).map(|key| &key.fields);
+    let fully_required = candidates.clone()
+        .find(|fields| fields.iter().all(|f| required_fields.contains(f)));
+
+    fully_required
+        .or_else(|| candidates.next())
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn required_fields(schema: &Document) -> Vec<String> {
+    schema.get_array("required")
+        .map(|required| required.iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect())
+        .unwrap_or_default()
 }
 
 pub async fn get_collection_schema_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path(collection_name): Path<String>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
     
     match get_database(mongodb_state).await {
         Ok(db) => {
             // Get the schema with UI metadata
-            let schema_result = get_collection_schema_with_ui(&db, &collection_name).await;
+            let schema_result = state.schema_cache.schema_with_ui(&db, &collection_name).await;
             
-            // Get the required and unique fields
-            let required_unique_result = get_required_and_unique_fields(&db, &collection_name).await;
+            // The unique indexes give the primary key, natural key and unique keys
+            let keys_result = match &schema_result {
+                Ok(_) => unique_keys(&db.collection::<Document>(&collection_name)).await,
+                Err(_) => Ok(Vec::new()),
+            };
             
-            match (schema_result, required_unique_result) {
-                (Ok(mut merged_schema), Ok(required_unique_fields)) => {
+            match (schema_result, keys_result) {
+                (Ok(mut merged_schema), Ok(keys)) => {
                     // Add _id field to properties
                     match merged_schema.get_document_mut("properties") {
                         Ok(properties) => {
@@ -133,11 +176,19 @@ pub async fn get_collection_schema_handler(
                     }
                     
                     // Add the first required and unique field to the schema (if any exists)
-                    let primary_key = required_unique_fields.first().cloned();
+                    let primary_key = required_and_unique_fields(&keys, &merged_schema).into_iter().next();
                     
                     // Insert the primary key into the merged schema
                     merged_schema.insert("primaryKey", bson::to_bson(&primary_key).unwrap_or(bson::Bson::Null));
-                    
+
+                    // Expose the full (possibly compound) natural key used by the by-key lookup
+                    let natural_key = natural_key_fields(&keys, &merged_schema);
+                    merged_schema.insert("naturalKey", natural_key);
+
+                    // Every unique constraint, as field lists, for clients checking values before saving
+                    let unique_fields: Vec<Vec<String>> = keys.into_iter().map(|key| key.fields).collect();
+                    merged_schema.insert("uniqueKeys", unique_fields);
+
                     // Convert merged schema to JSON
                     match bson::from_bson(bson::Bson::Document(merged_schema)) {
                         Ok(merged_schema_json) => {
@@ -145,6 +196,8 @@ pub async fn get_collection_schema_handler(
                                 success: true,
                                 data: Some(merged_schema_json),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<serde_json::Value>(
@@ -155,7 +208,7 @@ pub async fn get_collection_schema_handler(
                 },
                 (Ok(_), Err(e)) => error_response::<serde_json::Value>(
                     StatusCode::INTERNAL_SERVER_ERROR, 
-                    format!("Failed to get required and unique fields: {}", e)
+                    format!("Failed to read unique indexes: {}", e)
                 ),
                 (Err(e), _) => error_response::<serde_json::Value>(
                     StatusCode::INTERNAL_SERVER_ERROR, 
@@ -172,8 +225,8 @@ pub async fn update_ui_metadata_handler(
     Path(collection_name): Path<String>,
     Json(payload): Json<serde_json::Value>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
-    let db = match get_database(mongodb_state).await {
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
         Ok(db) => db,
         Err((status, e)) => return error_response::<()>(status, e),
     };
@@ -187,12 +240,364 @@ pub async fn update_ui_metadata_handler(
         ),
     };
 
-    match update_ui_metadata(&db, &collection_name, &ui_update).await {
+    let result = update_ui_metadata(&db, &collection_name, &ui_update).await;
+    state.schema_cache.invalidate(&collection_name);
+    match result {
         Ok(_) => (StatusCode::OK, Json(ApiResponse {
             success: true,
             data: None,
             error: None,
+            field_errors: None,
+            error_code: None,
         })),
         Err(e) => error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e),
     }
-}
\ No newline at end of file
+}
+
+// Schema editor: the response carries the collection's new $jsonSchema.
+// Changes that documents already in the collection would violate are refused
+// with 409 unless `force=true` is passed.
+pub async fn add_schema_field_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(payload): Json<AddFieldPayload>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<Document>(status, e),
+    };
+
+    let result = add_field(&db, &collection_name, payload.name.trim(), &payload.definition, is_forced(&params)).await;
+    state.schema_cache.invalidate(&collection_name);
+    schema_change_response(result)
+}
+
+pub async fn update_schema_field_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path((collection_name, field)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(payload): Json<UpdateFieldPayload>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<Document>(status, e),
+    };
+
+    let result = update_field(
+        &db,
+        &collection_name,
+        &field,
+        payload.rename_to.as_deref(),
+        &payload.definition,
+        is_forced(&params),
+    ).await;
+    state.schema_cache.invalidate(&collection_name);
+    schema_change_response(result)
+}
+
+pub async fn remove_schema_field_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path((collection_name, field)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<Document>(status, e),
+    };
+
+    let result = remove_field(&db, &collection_name, &field, is_forced(&params)).await;
+    state.schema_cache.invalidate(&collection_name);
+    schema_change_response(result)
+}
+
+fn is_forced(params: &HashMap<String, String>) -> bool {
+    params.get("force").map(|v| v == "true" || v == "1").unwrap_or(false)
+}
+
+fn schema_change_response(result: Result<Document, (StatusCode, String)>) -> (StatusCode, Json<ApiResponse<Document>>) {
+    match result {
+        Ok(schema) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(schema),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err((status, e)) => error_response::<Document>(status, e),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ViolationScanPayload {
+    // Proposed $jsonSchema to test instead of the collection's current one
+    pub schema: Document,
+}
+
+// Dry-run the validator over documents already stored: counts and sample ids of
+// violations grouped by field and rule. POST a proposed schema to try it before
+// applying; `format=csv` downloads every violating id with its errors instead.
+pub async fn schema_violations_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Bytes,
+) -> Response {
+    // An empty body scans against the current validator; anything else must be a valid payload
+    let payload = if body.iter().all(u8::is_ascii_whitespace) {
+        None
+    } else {
+        match serde_json::from_slice::<ViolationScanPayload>(&body) {
+            Ok(payload) => Some(payload),
+            Err(e) => return error_response::<ViolationReport>(StatusCode::BAD_REQUEST, format!("Invalid request body: {}", e)).into_response(),
+        }
+    };
+
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<ViolationReport>(status, e).into_response(),
+    };
+
+    let schema = match payload {
+        Some(payload) => payload.schema,
+        None => match state.schema_cache.schema(&db, &collection_name).await {
+            Ok(schema) => schema,
+            Err(e) => return error_response::<ViolationReport>(StatusCode::NOT_FOUND, e).into_response(),
+        },
+    };
+    let collection = db.collection::<Document>(&collection_name);
+
+    if params.get("format").map(String::as_str) == Some("csv") {
+        return match violations_csv(&collection, &schema).await {
+            Ok(csv) => {
+                let filename = format!("{}_violations.csv", collection_name);
+                (
+                    StatusCode::OK,
+                    [
+                        (axum::http::header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                        (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+                    ],
+                    csv,
+                ).into_response()
+            },
+            Err(e) => error_response::<ViolationReport>(StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        };
+    }
+
+    let samples = params.get("samples")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_VIOLATION_SAMPLES)
+        .min(MAX_VIOLATION_SAMPLES);
+    match violation_report(&collection, &schema, samples).await {
+        Ok(report) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(report),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })).into_response(),
+        Err(e) => error_response::<ViolationReport>(StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+// Propose a definition for an existing (possibly unvalidated) collection from a
+// random sample of its documents, in the shape POST /collections accepts.
+// `sample` sets how many documents are read.
+pub async fn infer_collection_schema_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> (StatusCode, Json<ApiResponse<CollectionDefinition>>) {
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<CollectionDefinition>(StatusCode::BAD_REQUEST, e),
+    };
+    let db = match get_database(mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<CollectionDefinition>(status, e),
+    };
+
+    let sample_size = params.get("sample")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_SAMPLE_SIZE)
+        .clamp(1, MAX_SAMPLE_SIZE);
+    let collection = db.collection::<Document>(&collection_name);
+    match infer_from_collection(&collection, sample_size, timezone).await {
+        Ok(definition) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(definition),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err(e) => error_response::<CollectionDefinition>(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+// Indexes of a collection with their sizes, plus any builds still running
+pub async fn list_indexes_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+) -> (StatusCode, Json<ApiResponse<IndexOverview>>) {
+    let mongodb_state = &state.lock().await.mongodb_state;
+    let (db, admin) = match (get_database(mongodb_state).await, get_admin_database(mongodb_state).await) {
+        (Ok(db), Ok(admin)) => (db, admin),
+        (Err((status, e)), _) | (_, Err((status, e))) => return error_response::<IndexOverview>(status, e),
+    };
+    match list_indexes(&db, &admin, &collection_name).await {
+        Ok(overview) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(overview),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err((status, e)) => error_response::<IndexOverview>(status, e),
+    }
+}
+
+// Build an index (single, compound, unique, TTL, text or partial). The response
+// arrives when the build is done; GET on the same path reports its progress.
+pub async fn create_index_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Json(definition): Json<IndexDefinition>,
+) -> (StatusCode, Json<ApiResponse<IndexInfo>>) {
+    // Don't hold the server state while the build runs, so progress can be polled
+    let db = {
+        let mongodb_state = &state.lock().await.mongodb_state;
+        match get_database(mongodb_state).await {
+            Ok(db) => db,
+            Err((status, e)) => return error_response::<IndexInfo>(status, e),
+        }
+    };
+    match create_index(&db, &collection_name, &definition).await {
+        Ok(index) => (StatusCode::CREATED, Json(ApiResponse {
+            success: true,
+            data: Some(index),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err((status, e)) => error_response::<IndexInfo>(status, e),
+    }
+}
+
+pub async fn drop_index_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path((collection_name, index_name)): Path<(String, String)>,
+) -> (StatusCode, Json<ApiResponse<()>>) {
+    let mongodb_state = &state.lock().await.mongodb_state;
+    let db = match get_database(mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<()>(status, e),
+    };
+    match drop_index(&db, &collection_name, &index_name).await {
+        Ok(()) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: None,
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err((status, e)) => error_response::<()>(status, e),
+    }
+}
+
+// Run a lifecycle operation in two steps: without a token, answer 202 with a
+// confirmation token and a summary of what will happen; with it, do it.
+async fn run_lifecycle(
+    state: &Arc<Mutex<ApiServerState>>,
+    operation: LifecycleOperation,
+    confirm_token: Option<String>,
+) -> Response {
+    // Don't hold the server state while copying or snapshotting
+    let (db, admin, confirmations, schema_cache, event_bus) = {
+        let state = state.lock().await;
+        let mongodb_state = &state.mongodb_state;
+        match (get_database(mongodb_state).await, get_admin_database(mongodb_state).await) {
+            (Ok(db), Ok(admin)) => (db, admin, state.confirmations.clone(), state.schema_cache.clone(), state.event_bus.clone()),
+            (Err((status, e)), _) | (_, Err((status, e))) => return error_response::<()>(status, e).into_response(),
+        }
+    };
+
+    let Some(token) = confirm_token else {
+        return match request_confirmation(&confirmations, &schema_cache, &db, operation).await {
+            Ok(request) => (StatusCode::ACCEPTED, Json(ApiResponse {
+                success: true,
+                data: Some(request),
+                error: None,
+                field_errors: None,
+                error_code: None,
+            })).into_response(),
+            Err((status, e)) => error_response::<ConfirmationRequest>(status, e).into_response(),
+        };
+    };
+
+    if let Err((status, e)) = confirm(&confirmations, &token, &operation).await {
+        return error_response::<LifecycleOutcome>(status, e).into_response();
+    }
+    let result = execute(&schema_cache, &db, &admin, &operation, &event_bus).await;
+    schema_cache.invalidate(operation.collection());
+    if let Some(target) = operation.target() {
+        schema_cache.invalidate(target);
+    }
+    match result {
+        Ok(outcome) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(outcome),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })).into_response(),
+        Err((status, e)) => error_response::<LifecycleOutcome>(status, e).into_response(),
+    }
+}
+
+fn missing_target() -> Response {
+    error_response::<()>(StatusCode::BAD_REQUEST, "Missing target name 'to'".to_string()).into_response()
+}
+
+pub async fn rename_collection_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Json(payload): Json<LifecyclePayload>,
+) -> Response {
+    let Some(to) = payload.to else { return missing_target() };
+    run_lifecycle(&state, LifecycleOperation::Rename { collection: collection_name, to }, payload.confirm_token).await
+}
+
+pub async fn clone_collection_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Json(payload): Json<LifecyclePayload>,
+) -> Response {
+    let Some(to) = payload.to else { return missing_target() };
+    let operation = LifecycleOperation::Clone { collection: collection_name, to, with_documents: payload.with_documents };
+    run_lifecycle(&state, operation, payload.confirm_token).await
+}
+
+pub async fn truncate_collection_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    payload: Option<Json<LifecyclePayload>>,
+) -> Response {
+    let confirm_token = payload.and_then(|Json(p)| p.confirm_token);
+    run_lifecycle(&state, LifecycleOperation::Truncate { collection: collection_name }, confirm_token).await
+}
+
+// DELETE /collections/:collection_name?confirm_token=...
+pub async fn drop_collection_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let confirm_token = params.get("confirm_token").cloned();
+    run_lifecycle(&state, LifecycleOperation::Drop { collection: collection_name }, confirm_token).await
+}
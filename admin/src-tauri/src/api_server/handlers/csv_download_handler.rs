@@ -5,23 +5,35 @@ use axum::{
     http::{StatusCode, HeaderMap, header::{CONTENT_TYPE, CONTENT_DISPOSITION}},
     response::IntoResponse,
 };
+use mongodb::bson::{Bson, Document};
 use rusqlite::{Connection, types::ValueRef};
 use std::{sync::Arc, path::PathBuf};
 use tokio::sync::Mutex;
-use csv::Writer;
 use serde::{Deserialize, Serialize};
 use crate::api_server::state::ApiServerState;
+use crate::api_server::services::database_service::get_database;
+use crate::api_server::services::export_service::{create_exporter, ExportColumn, ExportFormat};
+use crate::api_server::services::coercion_service::coerce_text;
+use crate::timezone::InstitutionTimezone;
 
 // Make the struct public and derive Serialize
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DownloadParams {
     pub ids: Option<String>,
+    // csv (default), xlsx, json, ndjson or extjson
+    pub format: Option<String>,
+    // Overrides the institution timezone for dates in the export
+    pub tz: Option<String>,
 }
 
 // Request body for POST endpoint
 #[derive(Debug, Deserialize)]
 pub struct DownloadJsonRequest {
     pub ids: Vec<String>,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub tz: Option<String>,
 }
 
 // GET endpoint with query parameters
@@ -30,9 +42,12 @@ pub async fn download_temp_csv(
     Path(collection): Path<String>,
     Query(params): Query<DownloadParams>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    // Debug statement to show when this handler is called
-    println!("download_temp_csv GET handler called for collection: {}", collection);
+    tracing::debug!("download_temp_csv GET handler called for collection: {}", collection);
     
+    let format = ExportFormat::parse(params.format.as_deref().unwrap_or("csv"))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let timezone = request_timezone(&state, params.tz.as_deref()).await?;
+
     // Get database path from state
     let db_path = super::csv_temp_handlers::get_db_path_from_state(&state, &collection)
         .await
@@ -43,8 +58,8 @@ pub async fn download_temp_csv(
         .map(|s| s.split(',').map(|s| s.to_string()).collect::<Vec<String>>())
         .unwrap_or_default();
 
-    // Process the CSV generation with the IDs
-    generate_csv(db_path, ids).await
+    let schema = staging_schema(&state, &collection).await;
+    generate_export(db_path, ids, format, collection, schema, timezone).await
 }
 
 // POST endpoint with JSON body
@@ -53,21 +68,77 @@ pub async fn download_temp_csv_post(
     Path(collection): Path<String>,
     Json(payload): Json<DownloadJsonRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    // Debug statement to show when this handler is called
-    println!("download_temp_csv POST handler called for collection: {} with {} IDs", 
-             collection, payload.ids.len());
+    tracing::debug!(
+        "download_temp_csv POST handler called for collection: {} with {} IDs",
+        collection, payload.ids.len()
+    );
     
+    let format = ExportFormat::parse(payload.format.as_deref().unwrap_or("csv"))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let timezone = request_timezone(&state, payload.tz.as_deref()).await?;
+
     // Get database path from state
     let db_path = super::csv_temp_handlers::get_db_path_from_state(&state, &collection)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Process the CSV generation with the IDs from the JSON body
-    generate_csv(db_path, payload.ids).await
+    let schema = staging_schema(&state, &collection).await;
+    generate_export(db_path, payload.ids, format, collection, schema, timezone).await
+}
+
+// Schema of the target collection, used to type the staged text values.
+// Without it (not connected, unknown collection) values are exported as text.
+async fn staging_schema(state: &Arc<Mutex<ApiServerState>>, collection: &str) -> Option<Document> {
+    let (mongodb_state, schema_cache) = {
+        let state = state.lock().await;
+        (state.mongodb_state.clone(), state.schema_cache.clone())
+    };
+    let db = get_database(&mongodb_state).await.ok()?;
+    schema_cache.schema(&db, collection).await.ok()
 }
 
-// Refactored common CSV generation logic - updated to accept PathBuf
-async fn generate_csv(db_path: PathBuf, ids: Vec<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+// The institution timezone, or the request's override of it
+async fn request_timezone(
+    state: &Arc<Mutex<ApiServerState>>,
+    tz: Option<&str>,
+) -> Result<InstitutionTimezone, (StatusCode, String)> {
+    match tz.filter(|tz| !tz.trim().is_empty()) {
+        Some(tz) => InstitutionTimezone::parse(tz).map_err(|e| (StatusCode::BAD_REQUEST, e)),
+        None => Ok(state.lock().await.timezone),
+    }
+}
+
+// Turn a staged SQLite value into BSON, typed by the schema when possible
+fn staged_value_to_bson(
+    value: ValueRef<'_>,
+    column: &str,
+    spec: Option<&Document>,
+    timezone: &InstitutionTimezone,
+) -> Bson {
+    match value {
+        ValueRef::Null => Bson::Null,
+        ValueRef::Integer(i) => Bson::Int64(i),
+        ValueRef::Real(f) => Bson::Double(f),
+        ValueRef::Text(t) => {
+            let text = String::from_utf8_lossy(t).to_string();
+            match spec {
+                Some(spec) => coerce_text(&text, spec, column, timezone).unwrap_or(Bson::String(text)),
+                None => Bson::String(text),
+            }
+        },
+        ValueRef::Blob(b) => Bson::String(format!("[blob:{}bytes]", b.len())),
+    }
+}
+
+// Common export logic for the staged valid rows
+async fn generate_export(
+    db_path: PathBuf,
+    ids: Vec<String>,
+    format: ExportFormat,
+    collection: String,
+    schema: Option<Document>,
+    timezone: InstitutionTimezone,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     // Execute in blocking task
     let task_result = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, String> {
         let conn = Connection::open(&db_path)
@@ -78,11 +149,16 @@ async fn generate_csv(db_path: PathBuf, ids: Vec<String>) -> Result<impl IntoRes
             .map_err(|e| format!("PRAGMA failed: {}", e))?;
         
         let columns: Vec<String> = stmt.query_map([], |row| {
-            Ok(row.get::<_, String>(1)?)
+            row.get::<_, String>(1)
         })
         .map_err(|e| format!("Column mapping failed: {}", e))?
         .collect::<Result<_, _>>()
         .map_err(|e| format!("Column collection failed: {}", e))?;
+
+        let properties = schema.as_ref().and_then(|s| s.get_document("properties").ok());
+        let column_specs: Vec<Option<&Document>> = columns.iter()
+            .map(|column| properties.and_then(|p| p.get_document(column).ok()))
+            .collect();
         
         // Build query with optional ID filtering
         let mut query = "SELECT * FROM valid_data".to_string();
@@ -100,60 +176,58 @@ async fn generate_csv(db_path: PathBuf, ids: Vec<String>) -> Result<impl IntoRes
             stmt.query(rusqlite::params_from_iter(ids.iter()))
         }.map_err(|e| format!("Query failed: {}", e))?;
 
-        let mut wtr = Writer::from_writer(vec![]);
-        
-        // Write headers using actual column names
-        wtr.write_record(&columns)
-            .map_err(|e| format!("CSV header error: {}", e))?;
+        let export_columns = columns.iter()
+            .map(|column| ExportColumn { field: column.clone(), header: column.clone(), width: None })
+            .collect();
+        let mut exporter = create_exporter(format, export_columns, &collection, timezone)?;
+        let mut output = Vec::new();
         
-        // Write data rows
         loop {
             match rows.next() {
                 Ok(Some(row)) => {
-                    let mut record = Vec::with_capacity(columns.len());
-                    for (i, _) in columns.iter().enumerate() {
+                    let mut doc = Document::new();
+                    for (i, column) in columns.iter().enumerate() {
                         let value = match row.get_ref(i) {
-                            Ok(ValueRef::Null) => "".to_string(),
-                            Ok(ValueRef::Integer(i)) => i.to_string(),
-                            Ok(ValueRef::Real(f)) => f.to_string(),
-                            Ok(ValueRef::Text(t)) => String::from_utf8_lossy(t).to_string(),
-                            Ok(ValueRef::Blob(b)) => format!("[blob:{}bytes]", b.len()),
-                            Err(_) => "".to_string(),
+                            Ok(value) => staged_value_to_bson(value, column, column_specs[i], &timezone),
+                            Err(_) => Bson::Null,
                         };
-                        record.push(value);
+                        doc.insert(column.clone(), value);
                     }
-                    
-                    wtr.write_record(&record)
-                        .map_err(|e| format!("CSV write error: {}", e))?;
+                    exporter.write_document(&doc)?;
+                    output.extend(exporter.take_output());
                 },
                 Ok(None) => break,
                 Err(e) => return Err(format!("Row iteration error: {}", e)),
             }
         }
 
-        wtr.flush()
-            .map_err(|e| format!("CSV flush error: {}", e))?;
-            
-        Ok(wtr.into_inner()
-            .map_err(|e| format!("CSV finalization error: {}", e))?)
+        exporter.finish()?;
+        loop {
+            let chunk = exporter.take_output();
+            if chunk.is_empty() {
+                break;
+            }
+            output.extend(chunk);
+        }
+        Ok(output)
     })
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Task failed: {}", e)))?;
 
     // Handle the Result from the task
-    let csv_data = task_result.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let data = task_result.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-    // Create response with CSV headers
+    // Create response with headers for the chosen format
     let mut headers = HeaderMap::new();
     headers.insert(
         CONTENT_TYPE,
-        "text/csv; charset=utf-8".parse().unwrap()
+        format.content_type().parse().unwrap()
     );
     headers.insert(
         CONTENT_DISPOSITION,
-        format!("attachment; filename=\"export_{}.csv\"", 
-                chrono::Local::now().format("%Y-%m-%d_%H-%M-%S")).parse().unwrap()
+        format!("attachment; filename=\"export_{}.{}\"", 
+                chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"), format.extension()).parse().unwrap()
     );
 
-    Ok((headers, csv_data))
-}
\ No newline at end of file
+    Ok((headers, data))
+}
@@ -4,21 +4,27 @@ use axum::{
     extract::{State, Json},
     http::StatusCode,
 };
-use bson::{doc, Document, Bson};
-use mongodb::{bson::oid::ObjectId, options::UpdateOptions};
+use bson::{doc, Bson, Document};
+use futures_util::TryStreamExt;
+use mongodb::options::FindOptions;
 use rusqlite::{Connection, ToSql};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::task;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use futures::executor::block_on;
 use anyhow::Result;
 
+use crate::api_server::events::{id_text, ChangeAction};
 use crate::api_server::state::ApiServerState;
 use crate::api_server::models::ApiResponse;
 use crate::api_server::services::database_service::get_database;
-use crate::api_server::services::schema_service::get_collection_schema_internal;
+use crate::api_server::services::reference_service::validate_references;
+use crate::api_server::services::coercion_service::{describe_errors, document_from_row};
+use crate::api_server::services::defaults_service::{prepare_insert, strip_server_managed};
+use crate::api_server::services::validation_service::describe_write_error;
 use crate::api_server::handlers::csv_temp_handlers::get_db_path_from_state;
+use crate::timezone::{InstitutionTimezone, TIMEZONE_PARAM};
 
 // Structure for the import summary response
 #[derive(serde::Serialize)]
@@ -28,72 +34,6 @@ pub struct ImportSummary {
     errors: Vec<String>,
 }
 
-// Helper function to convert string values to BSON based on schema type
-fn convert_str_to_bson(value: &str, bson_type: &str) -> Result<Bson, String> {
-    if value.is_empty() {
-        return Ok(Bson::Null);
-    }
-    
-    match bson_type {
-        "objectId" => ObjectId::parse_str(value)
-            .map(Bson::ObjectId)
-            .map_err(|e| format!("Invalid ObjectId: {}", e)),
-        "date" => {
-            match chrono::DateTime::parse_from_rfc3339(value) {
-                Ok(dt) => {
-                    let utc_dt = dt.to_utc();
-                    let system_time = utc_dt.into();
-                    Ok(Bson::DateTime(bson::DateTime::from_system_time(system_time)))
-                }
-                Err(_) => {
-                    match chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
-                        Ok(dt) => {
-                            let dt_utc = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(dt, chrono::Utc);
-                            let system_time = dt_utc.into();
-                            Ok(Bson::DateTime(bson::DateTime::from_system_time(system_time)))
-                        }
-                        Err(_) => {
-                            chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
-                                .map(|d| {
-                                    let dt = d.and_hms_opt(0, 0, 0).unwrap();
-                                    let dt_utc = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(dt, chrono::Utc);
-                                    let system_time = dt_utc.into();
-                                    Bson::DateTime(bson::DateTime::from_system_time(system_time))
-                                })
-                                .map_err(|e| format!("Invalid date format: {}", e))
-                        }
-                    }
-                }
-            }
-        }
-        "int" | "int32" => value
-            .parse::<i32>()
-            .map(Bson::Int32)
-            .map_err(|e| format!("Invalid integer: {}", e)),
-        "long" | "int64" => value
-            .parse::<i64>()
-            .map(Bson::Int64)
-            .map_err(|e| format!("Invalid long integer: {}", e)),
-        "double" | "number" => value
-            .parse::<f64>()
-            .map(Bson::Double)
-            .map_err(|e| format!("Invalid double: {}", e)),
-        "bool" | "boolean" => match value.to_lowercase().as_str() {
-            "true" | "yes" | "1" | "t" | "y" => Ok(Bson::Boolean(true)),
-            "false" | "no" | "0" | "f" | "n" => Ok(Bson::Boolean(false)),
-            _ => Err(format!("Invalid boolean value: {}", value)),
-        },
-        "string" => Ok(Bson::String(value.to_string())),
-        "array" => serde_json::from_str::<Vec<serde_json::Value>>(value)
-            .map(|arr| bson::to_bson(&arr).unwrap_or(Bson::Null))
-            .map_err(|e| format!("Invalid array: {}", e)),
-        "object" | "document" => serde_json::from_str::<serde_json::Value>(value)
-            .map(|obj| bson::to_bson(&obj).unwrap_or(Bson::Null))
-            .map_err(|e| format!("Invalid object: {}", e)),
-        _ => Ok(Bson::String(value.to_string())),
-    }
-}
-
 // Handler for importing validated CSV data
 pub async fn import_valid_csv_data_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
@@ -111,20 +51,32 @@ pub async fn import_valid_csv_data_handler(
     }
     let collection_name = &coll_vec[0];
 
+    // Optional per-import override of the institution timezone, e.g. "tz": ["+08:00"]
+    let timezone = match payload.get(TIMEZONE_PARAM).and_then(|v| v.first()) {
+        Some(tz) => InstitutionTimezone::parse(tz).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => state.lock().await.timezone,
+    };
+
+    let schema_cache = state.lock().await.schema_cache.clone();
     let db = get_database(&state.lock().await.mongodb_state).await
         .map_err(|(s, e)| (s, e))?;
-    let schema = get_collection_schema_internal(&db, collection_name).await
+    let schema = schema_cache.schema(&db, collection_name).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     let db_path = get_db_path_from_state(&state, collection_name).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let defaults = schema_cache.defaults(&db, collection_name).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
     let collection = db.collection::<Document>(collection_name);
 
     let ids_clone = ids.clone();
     let schema_clone = schema.clone();
     let path_clone = db_path.clone();
     let coll_clone = collection.clone();
+    let db_clone = db.clone();
 
-    let blocking = task::spawn_blocking(move || -> Result<(u64, u64, Vec<String>), anyhow::Error> {
+    // Ids of the inserted and of the modified documents, and the errors
+    type Outcome = (Vec<String>, Vec<String>, Vec<String>);
+    let blocking = task::spawn_blocking(move || -> Result<Outcome, anyhow::Error> {
         let conn = Connection::open(&path_clone)?;
         // Prepare query
         let placeholders = ids_clone.iter().map(|_| "?").collect::<Vec<_>>().join(",");
@@ -139,28 +91,20 @@ pub async fn import_valid_csv_data_handler(
         let mut docs = Vec::new();
         let mut errors = Vec::new();
         while let Some(row) = rows.next()? {
-            let mut doc = Document::new();
+            let mut cells = Vec::new();
             for (i, col) in column_names.iter().enumerate() {
                 if let Ok(Some(val)) = row.get::<_, Option<String>>(i) {
-                    // Determine BSON type, default to string
-                    let bson_type = match schema_clone.get_document("properties") {
-                        Ok(props) => match props.get_document(col) {
-                            Ok(field_schema) => match field_schema.get("bsonType") {
-                                Some(Bson::String(s)) => s.as_str(),
-                                _ => "string",
-                            },
-                            Err(_) => "string",
-                        },
-                        Err(_) => "string",
-                    };
-                    
-
-                    match convert_str_to_bson(&val, bson_type) {
-                        Ok(b) => { doc.insert(col, b); },
-                        Err(e) => { errors.push(format!("Field {}: {}", col, e)); },
-                    }
+                    cells.push((col.clone(), val));
                 }
             }
+            let mut doc = match document_from_row(&cells, &schema_clone, &timezone) {
+                Ok(doc) => doc,
+                Err(field_errors) => {
+                    errors.push(describe_errors(&field_errors));
+                    continue;
+                },
+            };
+            strip_server_managed(&mut doc);
             if doc.contains_key("_id") {
                 docs.push(doc);
             } else {
@@ -168,24 +112,46 @@ pub async fn import_valid_csv_data_handler(
             }
         }
 
-        // Upsert each document
-        let mut inserted = 0;
-        let mut modified = 0;
-        let upsert_opts = UpdateOptions::builder().upsert(true).build();
+        // Insert new documents and update existing ones. New documents get their
+        // missing defaults, nested ones included, and created_at; updates only $set
+        // the row's fields. A document created or removed by someone else meanwhile
+        // fails its row rather than being written the wrong way.
+        let mut inserted = Vec::new();
+        let mut modified = Vec::new();
         let fut = async {
-            for doc in docs {
+            let ids: Vec<Bson> = docs.iter().filter_map(|doc| doc.get("_id").cloned()).collect();
+            let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+            let existing: HashSet<String> = coll_clone.find(doc! { "_id": { "$in": ids } }, options)
+                .await?
+                .try_collect::<Vec<Document>>()
+                .await?
+                .iter()
+                .filter_map(|doc| doc.get("_id").map(id_text))
+                .collect();
+
+            for mut doc in docs {
                 let id = doc.get("_id").unwrap().clone();
-                let filter = doc! { "_id": id };
-                let update = doc! { "$set": doc.clone() };
-                match coll_clone.update_one(filter, update, Some(upsert_opts.clone())).await {
+                // Skip rows whose REF: fields point at missing documents
+                if let Err(e) = validate_references(&db_clone, &schema_clone, &doc).await {
+                    errors.push(format!("Document {}: {}", id, e));
+                    continue;
+                }
+                if !existing.contains(&id_text(&id)) {
+                    prepare_insert(&mut doc, &defaults);
+                    match coll_clone.insert_one(doc, None).await {
+                        Ok(_) => inserted.push(id_text(&id)),
+                        Err(e) => errors.push(format!("Document {}: {}", id, describe_write_error(&e))),
+                    }
+                    continue;
+                }
+                match coll_clone.update_one(doc! { "_id": id.clone() }, doc! { "$set": doc }, None).await {
+                    Ok(res) if res.matched_count == 0 => errors.push(format!("Document {}: no longer exists", id)),
                     Ok(res) => {
-                        if res.upserted_id.is_some() {
-                            inserted += 1;
-                        } else {
-                            modified += res.modified_count;
+                        if res.modified_count > 0 {
+                            modified.push(id_text(&id));
                         }
                     },
-                    Err(e) => errors.push(format!("update_one error: {}", e)),
+                    Err(e) => errors.push(format!("Document {}: {}", id, describe_write_error(&e))),
                 }
             }
             Ok((inserted, modified, errors))
@@ -198,6 +164,9 @@ pub async fn import_valid_csv_data_handler(
         format!("Internal error: {}", e)
     ))?;
 
-    let summary = ImportSummary { inserted_count: ins, modified_count: modif, errors: errs };
-    Ok(Json(ApiResponse { success: true, data: Some(summary), error: None }))
+    let summary = ImportSummary { inserted_count: ins.len() as u64, modified_count: modif.len() as u64, errors: errs };
+    let event_bus = state.lock().await.event_bus.clone();
+    event_bus.publish(collection_name, ChangeAction::Insert, ins, None);
+    event_bus.publish(collection_name, ChangeAction::Update, modif, None);
+    Ok(Json(ApiResponse { success: true, data: Some(summary), error: None, field_errors: None, error_code: None }))
 }
@@ -1,30 +1,37 @@
 // src/api_server/handlers/csv_temp_handlers.rs
 
-use axum::{extract::{Path, State, Query}, http::StatusCode, Json}; // [cite: 707]
+use axum::{extract::{Multipart, Path, State, Query}, http::StatusCode, Json}; // [cite: 707]
 use rusqlite::{Connection, params, types::{Null, ValueRef}}; // <<< Added Transaction, OptionalExtension, ValueRef
 use serde_json::{Value, Map, json}; // <<< Added json macro
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     fs,
     path::PathBuf,
     sync::Arc,
-    collections::{HashMap, HashSet}, // <<< Added HashMap, HashSet
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet}, // <<< Added HashMap, HashSet
 };
-use tokio::{sync::Mutex, task}; // [cite: 708]
+use tokio::{io::AsyncWriteExt, sync::Mutex, task}; // [cite: 708]
 use crate::api_server::state::ApiServerState; // [cite: 708]
 use std::env; // [cite: 708]
 use anyhow::{Result as AnyhowResult, anyhow, Result}; // [cite: 708]
 use mongodb::{ // <<< Added mongodb imports
     bson::{doc, Document, oid::ObjectId, Bson},
     Collection,
-    options::FindOptions
 };
 use futures_util::TryStreamExt; // <<< Added TryStreamExt for MongoDB cursor
 
 // <<< Add imports for schema service and database service >>>
 use crate::api_server::services::database_service::get_database;
-use crate::api_server::services::schema_service::get_collection_schema_internal;
 use crate::api_server::models::ApiResponse; // <<< Added ApiResponse, error_response
+use crate::api_server::services::coercion_service::{document_from_row, FieldError};
+use crate::api_server::services::validation_service::SchemaValidator;
+use crate::api_server::services::defaults_service::prepare_insert;
+use crate::api_server::services::index_service::{key_text, unique_keys, UniqueKey};
+use crate::api_server::services::collection_service::CollectionDefinition;
+use crate::api_server::services::inference_service::{document_from_text, SchemaInference};
+use crate::api_server::handlers::collection_handlers::get_required_and_unique_fields;
+use crate::timezone::InstitutionTimezone;
+use tracing::debug;
 
 
 // Structure for request parsing
@@ -34,6 +41,44 @@ pub struct CsvUpload { // [cite: 709]
     invalid: Vec<Value>, // [cite: 709]
 }
 
+// One problem with a staged row, kept as a JSON list in invalid_data's errors column:
+// the schema rule or staging check that failed, and the staged column whose cell
+// it concerns (None when the row as a whole is at fault, e.g. a missing field)
+#[derive(Debug, Clone, Serialize)]
+pub struct StagedError {
+    pub column: Option<String>,
+    #[serde(flatten)]
+    pub error: FieldError,
+}
+
+impl StagedError {
+    // Attach a schema error to the column it comes from: the column named after
+    // the field, or the one holding the object or array the field is inside
+    fn from_field_error(error: FieldError, columns: &[String]) -> Self {
+        let column = columns.iter()
+            .find(|c| error.field == **c || error.field.starts_with(&format!("{}.", c)))
+            .cloned();
+        Self { column, error }
+    }
+
+    // A row whose _id another staged row already holds; it is staged under a new one
+    fn duplicate_id(id: &str) -> Self {
+        Self::new(Some("_id"), "unique", "no other staged row with this _id", format!("Another staged row has _id '{}'; this one was staged under a new _id", id))
+    }
+
+    fn new(column: Option<&str>, rule: &str, expected: &str, message: String) -> Self {
+        Self {
+            column: column.map(String::from),
+            error: FieldError {
+                field: column.unwrap_or_default().to_string(),
+                rule: rule.to_string(),
+                expected: expected.to_string(),
+                message,
+            },
+        }
+    }
+}
+
 // Pagination query parameters
 #[derive(Debug, Deserialize)]
 pub struct PaginationQuery { // [cite: 709]
@@ -337,6 +382,16 @@ fn insert_valid_data(conn: &Connection, data: &[Value]) -> Result<()> { // [cite
 }
 
 
+// Add any of `columns` that invalid_data doesn't have yet, as TEXT
+fn ensure_invalid_columns(conn: &Connection, columns: &[String]) -> Result<()> {
+    let existing = get_table_columns(conn, "invalid_data")?;
+    for column in columns.iter().filter(|c| !existing.contains(c)) {
+        conn.execute(&format!("ALTER TABLE invalid_data ADD COLUMN \"{}\" TEXT", column.replace("\"", "\"\"")), [])
+            .map_err(|e| anyhow!("Failed to add column '{}' to invalid_data: {}", column, e))?;
+    }
+    Ok(())
+}
+
 // Insert invalid data with specified columns + errors
 fn insert_invalid_data_with_cols(
     conn: &Connection,
@@ -504,20 +559,23 @@ fn insert_invalid_data(conn: &Connection, data: &[Value], csv_columns: &[String]
     Ok(()) // [cite: 756]
 }
 
-pub async fn save_csv_temp(
-    State(state): State<Arc<Mutex<ApiServerState>>>,
-    Path(collection): Path<String>,
-    Json(data): Json<CsvUpload>,
-) -> Result<(), (StatusCode, String)> {
-    if data.valid.is_empty() && data.invalid.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "No data provided".to_string()));
+// The collection name becomes a directory under the app data dir, so it can't
+// name a path of its own
+fn check_staging_name(collection: &str) -> Result<(), (StatusCode, String)> {
+    if collection.trim().is_empty() || collection.contains(['/', '\\', '\0']) || collection.contains("..") {
+        return Err((StatusCode::BAD_REQUEST, format!("Invalid collection name '{}'", collection)));
     }
+    Ok(())
+}
 
+// Create the collection's staging directory and register its SQLite path in state
+async fn prepare_staging_path(state: &Arc<Mutex<ApiServerState>>, collection: &str) -> Result<PathBuf, (StatusCode, String)> {
+    check_staging_name(collection)?;
     // Get app data directory in a non-blocking way
     let app_data_dir = task::spawn_blocking(get_app_data_dir)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to spawn blocking task: {}", e)))?;
-    let temp_dir = app_data_dir.join("temp").join(&collection);
+    let temp_dir = app_data_dir.join("temp").join(collection);
     let db_path = temp_dir.join("temp_data.sqlite");
 
     // Create directories using blocking task
@@ -531,9 +589,24 @@ pub async fn save_csv_temp(
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Store path in state
-    add_path_to_state(&state, collection.clone(), db_path.clone())
+    add_path_to_state(state, collection.to_string(), db_path.clone())
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(db_path)
+}
+
+// Stage rows the client already parsed and split into valid and invalid.
+// Prefer uploading the file itself to /api/csv-temp/:collection/upload.
+pub async fn save_csv_temp(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection): Path<String>,
+    Json(data): Json<CsvUpload>,
+) -> Result<(), (StatusCode, String)> {
+    if data.valid.is_empty() && data.invalid.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No data provided".to_string()));
+    }
+
+    let db_path = prepare_staging_path(&state, &collection).await?;
     // Clone data and path for move into blocking task
     let data_clone = data;
     let db_path_clone = db_path.clone();
@@ -576,6 +649,299 @@ pub async fn save_csv_temp(
     Ok(())
 }
 
+// ==========================================================================
+// CSV Upload Handler
+// ==========================================================================
+
+// Uploads are written to disk as they arrive, so they get a larger body limit than the default
+pub const MAX_CSV_UPLOAD_BYTES: usize = 1024 * 1024 * 1024;
+
+// Name of the multipart field carrying the file
+const UPLOAD_FIELD: &str = "file";
+
+// Structure for the upload response
+#[derive(serde::Serialize, Debug)]
+pub struct StagingSummary {
+    rows: usize,
+    valid: usize,
+    invalid: usize,
+    // Schema fields staged in valid_data, in CSV order
+    columns: Vec<String>,
+    // CSV headers that match no schema field; kept only with invalid rows
+    unmapped_columns: Vec<String>,
+}
+
+type StagingResult<T> = Result<T, (StatusCode, String)>;
+
+fn staging_error(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Stage an uploaded CSV file (multipart field "file"). The server parses it and
+// splits the rows: those whose cells convert to their schema types and that have
+// every primary key field go to valid_data, the rest to invalid_data with their
+// errors. Headers may be field names or their short names. Query parameters:
+// `delimiter` (default ",") and `tz` for dates without an offset.
+pub async fn upload_csv_temp(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    mut multipart: Multipart,
+) -> StagingResult<Json<ApiResponse<StagingSummary>>> {
+    let delimiter = match params.get("delimiter").map(String::as_str) {
+        None | Some("") => b',',
+        Some("\\t") | Some("tab") => b'\t',
+        Some(d) if d.len() == 1 => d.as_bytes()[0],
+        Some(d) => return Err((StatusCode::BAD_REQUEST, format!("Invalid delimiter '{}'; use a single character", d))),
+    };
+
+    check_staging_name(&collection)?;
+
+    let (db, timezone, schema_cache) = {
+        let state = state.lock().await;
+        let timezone = state.timezone.with_override(&params).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let db = get_database(&state.mongodb_state).await?;
+        (db, timezone, state.schema_cache.clone())
+    };
+    let existing = db.list_collection_names(doc! { "name": collection.as_str() })
+        .await
+        .map_err(staging_error)?;
+    if existing.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("Collection '{}' not found", collection)));
+    }
+    // Without a schema every column is staged as text
+    let schema = schema_cache.schema_with_ui(&db, &collection).await.unwrap_or_default();
+    let key_fields = get_required_and_unique_fields(&db, &collection, &schema)
+        .await
+        .map_err(staging_error)?;
+
+    let db_path = prepare_staging_path(&state, &collection).await?;
+    // A file of its own per upload, removed when it is dropped
+    let upload = tempfile::Builder::new()
+        .prefix("upload-")
+        .suffix(".csv")
+        .tempfile_in(db_path.parent().unwrap_or(std::path::Path::new(".")))
+        .map_err(staging_error)?;
+    receive_upload(&mut multipart, upload.path()).await?;
+
+    let summary = task::spawn_blocking(move || {
+        stage_csv(upload.path(), &db_path, delimiter, &schema, &key_fields, &timezone)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Task join error: {}", e)))??;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(summary),
+        error: None,
+        field_errors: None,
+        error_code: None,
+    }))
+}
+
+// Write the file field of the upload to `path` chunk by chunk
+async fn receive_upload(multipart: &mut Multipart, path: &std::path::Path) -> StagingResult<()> {
+    let bad_upload = |e: axum::extract::multipart::MultipartError| (StatusCode::BAD_REQUEST, format!("Invalid upload: {}", e));
+    while let Some(mut field) = multipart.next_field().await.map_err(bad_upload)? {
+        if field.name() != Some(UPLOAD_FIELD) {
+            continue;
+        }
+        let mut file = tokio::fs::File::create(path).await.map_err(staging_error)?;
+        while let Some(chunk) = field.chunk().await.map_err(bad_upload)? {
+            file.write_all(&chunk).await.map_err(staging_error)?;
+        }
+        file.flush().await.map_err(staging_error)?;
+        return Ok(());
+    }
+    Err((StatusCode::BAD_REQUEST, format!("No '{}' field in the upload", UPLOAD_FIELD)))
+}
+
+// The schema field a CSV header stands for: the field itself or its short name.
+// Collections without a schema take every header as is.
+fn field_for_header(header: &str, schema: &Document) -> Option<String> {
+    let Ok(properties) = schema.get_document("properties") else {
+        return Some(header.to_string());
+    };
+    if properties.contains_key(header) {
+        return Some(header.to_string());
+    }
+    let short_names = schema.get_document("ui").and_then(|ui| ui.get_document("short_names")).ok()?;
+    short_names.iter()
+        .find(|(field, short_name)| short_name.as_str() == Some(header) && properties.contains_key(field.as_str()))
+        .map(|(field, _)| field.clone())
+}
+
+// Parse the CSV at `csv_path` into fresh valid_data and invalid_data tables
+fn stage_csv(
+    csv_path: &std::path::Path,
+    db_path: &std::path::Path,
+    delimiter: u8,
+    schema: &Document,
+    key_fields: &[String],
+    timezone: &InstitutionTimezone,
+) -> StagingResult<StagingSummary> {
+    let bad_csv = |e: csv::Error| (StatusCode::BAD_REQUEST, format!("Invalid CSV: {}", e));
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(csv_path)
+        .map_err(bad_csv)?;
+    let headers: Vec<String> = reader.headers().map_err(bad_csv)?.iter().map(|h| h.trim().to_string()).collect();
+
+    if headers.iter().all(String::is_empty) {
+        return Err((StatusCode::BAD_REQUEST, "The CSV has no header row".to_string()));
+    }
+    let mut seen = HashSet::new();
+    for header in &headers {
+        if header.is_empty() || header == "errors" || !seen.insert(header.as_str()) {
+            return Err((StatusCode::BAD_REQUEST, format!("Invalid CSV header '{}': headers must be unique, non-empty and not 'errors'", header)));
+        }
+    }
+
+    // Column index, field per header; _id is the row's id rather than a field
+    let id_column = headers.iter().position(|h| h == "_id");
+    let mut mapped: Vec<(usize, String)> = Vec::new();
+    let mut unmapped_columns = Vec::new();
+    for (i, header) in headers.iter().enumerate() {
+        if Some(i) == id_column {
+            continue;
+        }
+        match field_for_header(header, schema) {
+            Some(field) if mapped.iter().any(|(_, f)| *f == field) => {
+                return Err((StatusCode::BAD_REQUEST, format!("More than one column is for field '{}'", field)));
+            },
+            Some(field) => mapped.push((i, field)),
+            None => unmapped_columns.push(header.clone()),
+        }
+    }
+    let columns: Vec<String> = mapped.iter().map(|(_, field)| field.clone()).collect();
+    let missing_keys: Vec<&String> = key_fields.iter().filter(|f| !columns.contains(f)).collect();
+    if !missing_keys.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, format!(
+            "Missing primary key column(s): {}",
+            missing_keys.iter().map(|f| f.as_str()).collect::<Vec<_>>().join(", ")
+        )));
+    }
+
+    // Invalid rows keep every column: mapped ones under their field name, so rows
+    // moved over from valid_data by validation line up with them, the rest as headed
+    let invalid_headers: Vec<String> = headers.iter().enumerate()
+        .map(|(i, header)| mapped.iter().find(|(j, _)| *j == i).map_or(header, |(_, field)| field).clone())
+        .collect();
+
+    let mut conn = Connection::open(db_path).map_err(staging_error)?;
+
+    let quoted = |names: &[String]| names.iter().map(|n| format!("\"{}\"", n.replace('"', "\"\""))).collect::<Vec<_>>().join(", ");
+    let placeholders = |n: usize| vec!["?"; n].join(", ");
+    let invalid_columns: Vec<String> = std::iter::once("_id".to_string())
+        .chain(invalid_headers.iter().filter(|h| *h != "_id").cloned())
+        .chain(std::iter::once("errors".to_string()))
+        .collect();
+    let valid_columns: Vec<String> = std::iter::once("_id".to_string()).chain(columns.iter().cloned()).collect();
+
+    let (mut rows, mut valid, mut invalid) = (0, 0, 0);
+    // The tables are replaced inside the transaction, so a failed upload leaves the
+    // previous staging as it was
+    let tx = conn.transaction().map_err(staging_error)?;
+    create_valid_table_with_columns(&tx, &columns).map_err(staging_error)?;
+    create_invalid_table(&tx, &invalid_headers).map_err(staging_error)?;
+    // _ids are the staging keys; a repeated one is staged under a new _id
+    let mut staged_ids: HashSet<String> = HashSet::new();
+    {
+        let mut insert_valid = tx.prepare(&format!(
+            "INSERT INTO valid_data ({}) VALUES ({})", quoted(&valid_columns), placeholders(valid_columns.len())
+        )).map_err(staging_error)?;
+        let mut insert_invalid = tx.prepare(&format!(
+            "INSERT INTO invalid_data ({}) VALUES ({})", quoted(&invalid_columns), placeholders(invalid_columns.len())
+        )).map_err(staging_error)?;
+
+        for record in reader.byte_records() {
+            let record = record.map_err(bad_csv)?;
+            // Cells that aren't UTF-8 are kept (lossily decoded) with the invalid rows
+            let not_utf8: Vec<usize> = (0..record.len())
+                .filter(|i| std::str::from_utf8(&record[*i]).is_err())
+                .collect();
+            let record: Vec<String> = record.iter().map(|cell| String::from_utf8_lossy(cell).into_owned()).collect();
+            if record.iter().all(|cell| cell.trim().is_empty()) {
+                continue;
+            }
+            rows += 1;
+            let cell = |i: usize| record.get(i).map(|c| c.trim()).filter(|c| !c.is_empty());
+            let mut errors: Vec<StagedError> = Vec::new();
+            for i in &not_utf8 {
+                let column = invalid_headers.get(*i).map(String::as_str);
+                errors.push(StagedError::new(column, "encoding", "UTF-8 text", "Cell is not valid UTF-8 text".to_string()));
+            }
+
+            if record.len() != headers.len() {
+                let expected = format!("{} cells", headers.len());
+                errors.push(StagedError::new(None, "rowLength", &expected, format!("Row has {} cells; the header has {}", record.len(), headers.len())));
+            }
+            let row_id = match id_column.and_then(cell) {
+                Some(id) => match ObjectId::parse_str(id) {
+                    Ok(oid) if staged_ids.insert(oid.to_hex()) => oid.to_hex(),
+                    Ok(_) => {
+                        errors.push(StagedError::duplicate_id(id));
+                        ObjectId::new().to_hex()
+                    },
+                    Err(_) => {
+                        errors.push(StagedError::new(Some("_id"), "bsonType", "objectId", format!("Invalid _id '{}'", id)));
+                        ObjectId::new().to_hex()
+                    },
+                },
+                None => ObjectId::new().to_hex(),
+            };
+            for (i, field) in &mapped {
+                if key_fields.contains(field) && cell(*i).is_none() {
+                    errors.push(StagedError::new(Some(field), "required", "true", format!("Missing primary key field: {}", field)));
+                }
+            }
+            let cells: Vec<(String, String)> = mapped.iter()
+                .filter_map(|(i, field)| cell(*i).map(|c| (field.clone(), c.to_string())))
+                .collect();
+            if let Err(field_errors) = document_from_row(&cells, schema, timezone) {
+                errors.extend(field_errors.into_iter().map(|e| StagedError::from_field_error(e, &columns)));
+            }
+
+            if errors.is_empty() {
+                let values: Vec<Option<&str>> = std::iter::once(Some(row_id.as_str()))
+                    .chain(mapped.iter().map(|(i, _)| cell(*i)))
+                    .collect();
+                insert_valid.execute(rusqlite::params_from_iter(values)).map_err(staging_error)?;
+                valid += 1;
+            } else {
+                let errors_json = serde_json::to_string(&errors).map_err(staging_error)?;
+                let values: Vec<Option<&str>> = std::iter::once(Some(row_id.as_str()))
+                    .chain((0..headers.len()).filter(|i| Some(*i) != id_column).map(|i| record.get(i).map(String::as_str)))
+                    .chain(std::iter::once(Some(errors_json.as_str())))
+                    .collect();
+                insert_invalid.execute(rusqlite::params_from_iter(values)).map_err(staging_error)?;
+                invalid += 1;
+            }
+        }
+    }
+    tx.commit().map_err(staging_error)?;
+
+    if rows == 0 {
+        return Err((StatusCode::BAD_REQUEST, "The CSV has no data rows".to_string()));
+    }
+    Ok(StagingSummary { rows, valid, invalid, columns, unmapped_columns })
+}
+
+// Create valid_data with _id as primary key and one TEXT column per field
+fn create_valid_table_with_columns(conn: &Connection, columns: &[String]) -> Result<()> {
+    conn.execute("DROP TABLE IF EXISTS valid_data", [])
+        .map_err(|e| anyhow!("Failed to drop valid_data table: {}", e))?;
+    let mut create_sql = "CREATE TABLE valid_data (\"_id\" TEXT PRIMARY KEY".to_string();
+    for column in columns {
+        create_sql.push_str(&format!(", \"{}\" TEXT", column.replace('"', "\"\"")));
+    }
+    create_sql.push(')');
+    conn.execute(&create_sql, [])
+        .map_err(|e| anyhow!("Failed to create valid_data table: {}", e))?;
+    Ok(())
+}
+
 // Build paginated response
 fn build_pagination_response( // [cite: 768]
     data: Vec<Value>, // [cite: 768]
@@ -832,12 +1198,19 @@ pub async fn delete_csv_temp( // [cite: 809]
 pub struct ValidationSummary {
     validated_count: usize,
     conflicts_found: usize,
+    // Rows (counted in conflicts_found) the collection's validator would reject
+    schema_violations: usize,
     remaining_valid: usize,
+    // Rows moved to invalid_data per failed rule ("bsonType", "required", "enum", "unique", ...);
+    // a row failing several rules counts under each
+    errors_by_rule: BTreeMap<String, usize>,
 }
 
+// Dates are read in the institution timezone, or the one in `tz`, as on upload and import
 pub async fn validate_csv_temp_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path(collection_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<ApiResponse<ValidationSummary>>, (StatusCode, String)> {
     println!("[VALIDATE] Starting validation for collection: {}", collection_name);
 
@@ -851,12 +1224,16 @@ pub async fn validate_csv_temp_handler(
          return Err((StatusCode::NOT_FOUND, "Temporary data not found. Please upload again.".to_string()));
     }
 
-    let state_guard = state.lock().await; // Lock state once
-    let mongodb_state = &state_guard.mongodb_state;
-    let mongo_db = match get_database(mongodb_state).await {
-         Ok(db) => db,
-         Err((status, e)) => return Err((status, format!("MongoDB connection error: {}", e))),
-     };
+    // Don't hold the server state while every row is checked
+    let (mongo_db, timezone, schema_cache) = {
+        let state_guard = state.lock().await;
+        let timezone = state_guard.timezone.with_override(&params).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let mongo_db = match get_database(&state_guard.mongodb_state).await {
+            Ok(db) => db,
+            Err((status, e)) => return Err((status, format!("MongoDB connection error: {}", e))),
+        };
+        (mongo_db, timezone, state_guard.schema_cache.clone())
+    };
     println!("[VALIDATE] MongoDB connection successful");
 
 
@@ -873,31 +1250,24 @@ pub async fn validate_csv_temp_handler(
         let mut conn = Connection::open(&db_path_clone)
             .map_err(|e| anyhow!("Failed to open SQLite database: {}", e))?;
 
-        // 3. Fetch Schema & Identify Unique Fields (Inside blocking task is fine for schema fetch)
+        // 3. Fetch Schema & Unique Keys (Inside blocking task is fine for schema fetch)
         println!("[VALIDATE Task] Fetching MongoDB schema for {}", collection_name_clone);
-        let schema = futures::executor::block_on(get_collection_schema_internal(&mongo_db_clone, &collection_name_clone))
+        let schema = futures::executor::block_on(schema_cache.schema(&mongo_db_clone, &collection_name_clone))
              .map_err(|e| anyhow!("Failed to fetch MongoDB schema: {}", e))?;
+        let defaults = futures::executor::block_on(schema_cache.defaults(&mongo_db_clone, &collection_name_clone))
+             .map_err(|e| anyhow!("Failed to fetch field defaults: {}", e))?;
         println!("[VALIDATE Task] Schema fetched successfully");
 
-        let unique_fields = schema
-            .get_document("properties")?
-            .iter()
-            .filter_map(|(key, value)| {
-                if let Bson::Document(props) = value {
-                    if props.get_bool("unique").unwrap_or(false) {
-                        return Some(key.clone());
-                    }
-                }
-                None
-            })
-            .collect::<HashSet<String>>();
-        println!("[VALIDATE Task] Identified unique fields: {:?}", unique_fields);
+        let mongo_coll: Collection<Document> = mongo_db_clone.collection(&collection_name_clone);
+        let unique_keys = futures::executor::block_on(unique_keys(&mongo_coll))
+             .map_err(|e| anyhow!("Failed to read unique indexes: {}", e))?;
+        debug!("[VALIDATE Task] Identified unique keys: {:?}", unique_keys.iter().map(UniqueKey::label).collect::<Vec<_>>());
 
 
         // 4. Read `valid_data` table - use scoping to limit the borrow
         println!("[VALIDATE Task] Reading data from valid_data table");
+        let valid_columns = get_table_columns(&conn, "valid_data")?;
         let valid_data_sqlite: Vec<HashMap<String, Option<String>>> = {
-            let valid_columns = get_table_columns(&conn, "valid_data")?;
             let mut stmt = conn.prepare("SELECT * FROM valid_data")?;
             let valid_rows_iter = stmt.query_map([], |row| {
                 let mut map = HashMap::new();
@@ -923,15 +1293,28 @@ pub async fn validate_csv_temp_handler(
              return Ok(ValidationSummary {
                  validated_count: 0,
                  conflicts_found: 0,
+                 schema_violations: 0,
                  remaining_valid: 0,
+                 errors_by_rule: BTreeMap::new(),
              });
         }
 
 
+        // Each row as the import would write it, so key values compare with stored ones by type
+        let typed_rows: Vec<Result<Document, Vec<FieldError>>> = valid_data_sqlite.iter()
+            .map(|row_map| {
+                let cells: Vec<(String, String)> = row_map.iter()
+                    .filter_map(|(column, value)| value.clone().map(|v| (column.clone(), v)))
+                    .collect();
+                document_from_row(&cells, &schema, &timezone)
+            })
+            .collect();
+
+
         // 5. Perform MongoDB Checks (Batched)
-        let mongo_coll: Collection<Document> = mongo_db_clone.collection(&collection_name_clone);
          let mut existing_ids = HashSet::new();
-         let mut existing_unique_values: HashMap<String, HashSet<String>> = HashMap::new();
+         let mut existing_key_values: HashMap<String, HashSet<String>> = HashMap::new();
+         let mut staged_key_counts: HashMap<String, HashMap<String, usize>> = HashMap::new();
 
          // --- Batch ID Check ---
          let ids_to_check: Vec<String> = valid_data_sqlite.iter()
@@ -964,71 +1347,115 @@ pub async fn validate_csv_temp_handler(
          }
 
 
-         // --- Batch Unique Field Checks ---
-         for field in &unique_fields {
-             let values_to_check: Vec<String> = valid_data_sqlite.iter()
-                 .filter_map(|row| row.get(field).cloned().flatten())
-                 .filter(|v| !v.is_empty()) // Don't check empty strings for uniqueness
-                 .collect::<HashSet<_>>() // Check only distinct values from SQLite
-                 .into_iter()
-                 .collect();
-
-             if !values_to_check.is_empty() {
-                 println!("[VALIDATE Task] Checking {} unique values for field '{}' against MongoDB", values_to_check.len(), field);
-                 let filter = doc! { field: { "$in": values_to_check } };
-                 // Optimization: Only project the necessary field and _id
-                 let options = FindOptions::builder().projection(doc! { field: 1 }).build();
-                 let cursor = futures::executor::block_on(mongo_coll.find(filter, options))?;
-                 let existing_docs: Vec<Document> = futures::executor::block_on(cursor.try_collect())?;
-
-                 let found_values: HashSet<String> = existing_docs.iter()
-                    .filter_map(|doc| doc.get_str(field).ok().map(String::from))
-                    .collect();
+         // --- Batch Unique Key Checks (single and compound) ---
+         for key in &unique_keys {
+             let mut candidates: Vec<Vec<Bson>> = Vec::new();
+             let counts = staged_key_counts.entry(key.name.clone()).or_default();
+             // Empty cells count as null, as they do in the index; rows that a sparse or
+             // partial index may leave out aren't checked
+             for values in typed_rows.iter().filter_map(|row| row.as_ref().ok()).filter_map(|doc| key.values_in(doc)) {
+                 let count = counts.entry(key_text(&values)).or_insert(0);
+                 if *count == 0 {
+                     candidates.push(values);
+                 }
+                 *count += 1;
+             }
 
-                 existing_unique_values.insert(field.clone(), found_values);
-                 println!("[VALIDATE Task] Found {} existing values for field '{}'", existing_unique_values.get(field).map_or(0, |s| s.len()), field);
+             if !candidates.is_empty() {
+                 debug!("[VALIDATE Task] Checking {} values of unique key ({}) against MongoDB", candidates.len(), key.label());
+                 let found_values = futures::executor::block_on(key.existing(&mongo_coll, &candidates))?;
+                 debug!("[VALIDATE Task] Found {} existing values of unique key ({})", found_values.len(), key.label());
+                 existing_key_values.insert(key.name.clone(), found_values);
              }
          }
 
 
+        // _ids already in invalid_data; a row moved there under one of them gets a new _id
+        let mut invalid_ids: HashSet<String> = {
+            let mut stmt = conn.prepare("SELECT \"_id\" FROM invalid_data")?;
+            let ids = stmt.query_map([], |row| row.get::<_, Option<String>>(0))?;
+            ids.filter_map(|id| id.transpose()).collect::<Result<_, _>>()?
+        };
+
         // 6. Identify Conflicts & Prepare Data for Update
         let mut conflicting_rows_to_move: Vec<Map<String, Value>> = Vec::new();
         let mut ids_to_delete_from_valid: Vec<String> = Vec::new();
+        let mut schema_violations = 0;
+        let mut errors_by_rule: BTreeMap<String, usize> = BTreeMap::new();
 
         println!("[VALIDATE Task] Identifying conflicting rows...");
-        for row_map in valid_data_sqlite {
+        let validator = SchemaValidator::new(&schema);
+        for (row_map, typed_row) in valid_data_sqlite.into_iter().zip(&typed_rows) {
             let row_id = row_map.get("_id").cloned().flatten().unwrap_or_default();
-             let mut conflict_errors: Vec<String> = Vec::new();
+             let mut conflict_errors: Vec<StagedError> = Vec::new();
+
+             // Check the document the import would insert (server-managed fields dropped,
+             // defaults and created_at added) against the collection's validator
+             let field_errors = match typed_row {
+                 Ok(doc) => {
+                     let mut doc = doc.clone();
+                     prepare_insert(&mut doc, &defaults);
+                     validator.validate(&doc)
+                 },
+                 Err(errors) => errors.clone(),
+             };
+             if !field_errors.is_empty() {
+                 schema_violations += 1;
+                 conflict_errors.extend(field_errors.into_iter().map(|e| StagedError::from_field_error(e, &valid_columns)));
+             }
 
              // Check _id conflict
              if existing_ids.contains(&row_id) {
-                 conflict_errors.push("Existing document with the same _id".to_string());
+                 conflict_errors.push(StagedError::new(Some("_id"), "unique", "no stored document with this _id", "Existing document with the same _id".to_string()));
              }
 
-             // Check unique fields
-             for field in &unique_fields {
-                 if let Some(Some(value)) = row_map.get(field) {
-                    if !value.is_empty() { // Only check non-empty values
-                         if let Some(existing_values) = existing_unique_values.get(field) {
-                             if existing_values.contains(value) {
-                                 conflict_errors.push(format!("Duplicate value for unique field '{}'", field));
-                             }
-                         }
-                    }
+             // Check unique keys, against stored documents and the other staged rows
+             if let Ok(doc) = typed_row {
+                 for key in &unique_keys {
+                     let Some(values) = key.values_in(doc) else { continue };
+                     let text = key_text(&values);
+                     let message = if existing_key_values.get(&key.name).is_some_and(|found| found.contains(&text)) {
+                         format!("Duplicate value for unique key ({})", key.label())
+                     } else if staged_key_counts.get(&key.name).and_then(|counts| counts.get(&text)).is_some_and(|n| *n > 1) {
+                         format!("Value of unique key ({}) repeats within the file", key.label())
+                     } else {
+                         continue;
+                     };
+                     // One error per key field, so each offending cell is marked
+                     for field in &key.fields {
+                         let column = valid_columns.iter().find(|c| *c == field).map(String::as_str);
+                         let mut error = StagedError::new(column, "unique", &format!("unique ({})", key.label()), message.clone());
+                         error.error.field = field.clone();
+                         conflict_errors.push(error);
+                     }
                  }
              }
 
              // If conflicts found, prepare to move the row
              if !conflict_errors.is_empty() {
                  ids_to_delete_from_valid.push(row_id.clone());
+                 let moved_id = if invalid_ids.insert(row_id.clone()) {
+                     row_id.clone()
+                 } else {
+                     conflict_errors.push(StagedError::duplicate_id(&row_id));
+                     let new_id = ObjectId::new().to_hex();
+                     invalid_ids.insert(new_id.clone());
+                     new_id
+                 };
+
+                 let rules: BTreeSet<&str> = conflict_errors.iter().map(|e| e.error.rule.as_str()).collect();
+                 for rule in rules {
+                     *errors_by_rule.entry(rule.to_string()).or_insert(0) += 1;
+                 }
 
                  // Convert HashMap<String, Option<String>> back to serde_json::Map<String, Value>
                  let mut json_map = Map::new();
                  for (key, opt_val) in row_map {
                      json_map.insert(key, opt_val.map_or(Value::Null, Value::String));
                  }
+                 json_map.insert("_id".to_string(), Value::String(moved_id));
 
-                 // Add errors (as a JSON array string for simplicity with existing structure)
+                 // Add errors as a JSON array, in the same form the upload stores
                  json_map.insert("errors".to_string(), Value::String(serde_json::to_string(&conflict_errors)?));
                  conflicting_rows_to_move.push(json_map);
              }
@@ -1044,7 +1471,9 @@ pub async fn validate_csv_temp_handler(
         // Move conflicting rows to invalid_data
         if !conflicting_rows_to_move.is_empty() {
             println!("[VALIDATE Task] Moving {} rows to invalid_data", conflicts_found_count);
-             // Get columns of invalid_data table to ensure compatibility
+             // invalid_data may lack some staged columns (older uploads named them by
+             // CSV header); add them so the moved cells are kept
+             ensure_invalid_columns(&tx, &valid_columns)?;
              let invalid_columns = get_table_columns(&tx, "invalid_data")?;
              insert_invalid_data_with_cols(&tx, &conflicting_rows_to_move, &invalid_columns)?;
         }
@@ -1067,7 +1496,9 @@ pub async fn validate_csv_temp_handler(
         Ok(ValidationSummary {
             validated_count: initial_valid_count,
             conflicts_found: conflicts_found_count,
+            schema_violations,
             remaining_valid: remaining_valid_count,
+            errors_by_rule,
         })
 
     }).await
@@ -1081,5 +1512,120 @@ pub async fn validate_csv_temp_handler(
         success: true,
         data: Some(validation_result),
         error: None,
+        field_errors: None,
+        error_code: None,
     }))
-}
\ No newline at end of file
+}
+
+// Read every staged row (valid and invalid) as (column, cell) pairs in column order,
+// leaving out the staging-only _id and errors columns
+fn read_staged_rows(conn: &Connection) -> Result<Vec<Vec<(String, String)>>> {
+    let mut rows = Vec::new();
+    for table in ["valid_data", "invalid_data"] {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+            params![table],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            continue;
+        }
+        let columns = get_table_columns(conn, table)?;
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {}", table))?;
+        let table_rows = stmt.query_map([], |row| {
+            let mut cells = Vec::new();
+            for (i, column) in columns.iter().enumerate() {
+                if column == "_id" || column == "errors" {
+                    continue;
+                }
+                let value: Option<String> = row.get(i)?;
+                cells.push((column.clone(), value.unwrap_or_default()));
+            }
+            Ok(cells)
+        })?;
+        for cells in table_rows {
+            rows.push(cells?);
+        }
+    }
+    Ok(rows)
+}
+
+// Propose a collection definition from the staged CSV: types, nullability, unique
+// and enum candidates are guessed from the cell text. The result can be sent to
+// POST /collections as-is to create the collection before importing.
+pub async fn infer_csv_temp_schema_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<ApiResponse<CollectionDefinition>>, (StatusCode, String)> {
+    let timezone = state.lock().await.timezone
+        .with_override(&params)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let db_path = get_db_path_from_state(&state, &collection)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("No staged CSV for '{}': {}", collection, e)))?;
+
+    let definition = task::spawn_blocking(move || -> AnyhowResult<CollectionDefinition> {
+        let conn = Connection::open(&db_path)
+            .map_err(|e| anyhow!("Failed to open SQLite database: {}", e))?;
+        let rows = read_staged_rows(&conn)?;
+        if rows.is_empty() {
+            return Err(anyhow!("The staged CSV has no rows to infer a schema from"));
+        }
+
+        let mut inference = SchemaInference::new(timezone);
+        for cells in &rows {
+            inference.observe(&document_from_text(cells, &timezone));
+        }
+        Ok(inference.into_definition(&collection))
+    }).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Task join error: {}", e)))?
+    .map_err(|e| (StatusCode::BAD_REQUEST, format!("Schema inference failed: {}", e)))?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(definition),
+        error: None,
+        field_errors: None,
+        error_code: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(csv: &[u8]) -> (StagingSummary, Vec<(String, String)>) {
+        let dir = tempfile::tempdir().unwrap();
+        let csv_path = dir.path().join("upload.csv");
+        let db_path = dir.path().join("staging.db");
+        fs::write(&csv_path, csv).unwrap();
+        let schema = doc! { "bsonType": "object", "properties": { "title": { "bsonType": "string" } } };
+
+        let summary = stage_csv(&csv_path, &db_path, b',', &schema, &[], &InstitutionTimezone::Local).unwrap();
+        let conn = Connection::open(&db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT \"_id\", errors FROM invalid_data").unwrap();
+        let invalid = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .collect::<Result<Vec<(String, String)>, _>>().unwrap();
+        (summary, invalid)
+    }
+
+    #[test]
+    fn stages_a_repeated_id_under_a_new_one() {
+        let id = "65f0a1b2c3d4e5f6a7b8c9d0";
+        let csv = format!("_id,title\n{id},a\n{},b\n,c\n", id.to_uppercase());
+        let (summary, invalid) = stage(csv.as_bytes());
+
+        assert_eq!((summary.rows, summary.valid, summary.invalid), (3, 2, 1));
+        assert_ne!(invalid[0].0, id);
+        assert!(invalid[0].1.contains("\"unique\""), "errors {}", invalid[0].1);
+    }
+
+    #[test]
+    fn stages_a_row_that_is_not_utf8_as_invalid() {
+        let (summary, invalid) = stage(b"title\nok\nbad \xff\n");
+
+        assert_eq!((summary.rows, summary.valid, summary.invalid), (2, 1, 1));
+        assert!(invalid[0].1.contains("\"encoding\""), "errors {}", invalid[0].1);
+    }
+}
@@ -11,26 +11,35 @@ use axum_extra::{
     TypedHeader,
 };
 use mongodb::{
-    bson::{doc, Document, oid::ObjectId}, 
-    Cursor
+    bson::{doc, Bson, Document, oid::ObjectId}, 
+    Collection, Cursor
 };
 use mongodb::options::{FindOptions, FindOneAndUpdateOptions, ReturnDocument};
 use crate::api_server::models::PaginatedDocuments;
 use serde_json::json;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use futures_util::StreamExt;
+use futures_util::{StreamExt, TryStreamExt};
 use std::collections::HashMap;
 use chrono;
-use crate::api_server::services::get_collection_schema_with_ui;
-use crate::api_server::services::schema_service::get_collection_schema_internal;
 use crate::api_server::state::ApiServerState;
+use crate::timezone::{format_output_fields, InstitutionTimezone};
+use crate::api_server::events::ChangeAction;
+use crate::api_server::services::export_service::{
+    array_lengths, array_paths, create_exporter, flatten_columns, stream_export, ExportColumn, ExportFormat
+};
 use crate::api_server::models::{
     ApiResponse, InsertResponse, UpdateResponse, DeleteResponse, 
-    error_response
+    error_response, field_errors_response, reference_policy_response, write_error_response,
+    COERCION_FAILED, VALIDATION_FAILED
 };
-use crate::api_server::services::database_service::{
-    get_database, process_document_fields
+use crate::api_server::services::database_service::get_database;
+use crate::api_server::services::coercion_service::coerce_document;
+use crate::api_server::services::defaults_service::{prepare_insert, strip_server_managed};
+use crate::api_server::services::validation_service::{validate_document, SchemaValidator};
+use crate::api_server::handlers::collection_handlers::get_natural_key_fields;
+use crate::api_server::services::reference_service::{
+    plan_removal, validate_document_references, RemovalKind
 };
 
 // Document handlers
@@ -39,7 +48,12 @@ pub async fn find_documents_handler(
     Path(collection_name): Path<String>,
     Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<PaginatedDocuments>(StatusCode::BAD_REQUEST, e),
+    };
     
     let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
     let filter: Document = match serde_json::from_str(&filter_str) {
@@ -73,7 +87,7 @@ pub async fn find_documents_handler(
             
             match collection.find(filter, Some(options)).await {
                 Ok(cursor) => {
-                    match process_cursor(cursor).await {
+                    match process_cursor(cursor, &timezone).await {
                         Ok(items) => {
                             let paginated_data = PaginatedDocuments {
                                 items,
@@ -85,6 +99,8 @@ pub async fn find_documents_handler(
                                 success: true,
                                 data: Some(paginated_data),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<PaginatedDocuments>(StatusCode::INTERNAL_SERVER_ERROR, e),
@@ -102,7 +118,12 @@ pub async fn find_archived_documents_handler(
     Path(collection_name): Path<String>,
     Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<PaginatedDocuments>(StatusCode::BAD_REQUEST, e),
+    };
     
     let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
     let mut filter: Document = match serde_json::from_str(&filter_str) {
@@ -113,7 +134,7 @@ pub async fn find_archived_documents_handler(
         ),
     };
     
-    filter.insert("is_archive", true);
+    filter.extend(archived_filter());
     
     let page = params.get("page").and_then(|p| p.parse::<u64>().ok()).unwrap_or(1);
     let page_size = params.get("page_size").and_then(|ps| ps.parse::<u64>().ok()).unwrap_or(10);
@@ -138,7 +159,7 @@ pub async fn find_archived_documents_handler(
             
             match collection.find(filter, Some(options)).await {
                 Ok(cursor) => {
-                    match process_cursor(cursor).await {
+                    match process_cursor(cursor, &timezone).await {
                         Ok(items) => {
                             let paginated_data = PaginatedDocuments {
                                 items,
@@ -150,6 +171,8 @@ pub async fn find_archived_documents_handler(
                                 success: true,
                                 data: Some(paginated_data),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<PaginatedDocuments>(StatusCode::INTERNAL_SERVER_ERROR, e),
@@ -167,7 +190,12 @@ pub async fn find_recovered_documents_handler(
     Path(collection_name): Path<String>,
     Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<PaginatedDocuments>(StatusCode::BAD_REQUEST, e),
+    };
     
     let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
     let mut filter: Document = match serde_json::from_str(&filter_str) {
@@ -178,13 +206,7 @@ pub async fn find_recovered_documents_handler(
         ),
     };
     
-    filter.insert("is_archive", false);
-    filter.insert("$expr", doc! {
-        "$eq": [
-            { "$arrayElemAt": ["$archive_history.action", -1] },
-            "recover"
-        ]
-    });
+    filter.extend(recovered_filter());
     
     let page = params.get("page").and_then(|p| p.parse::<u64>().ok()).unwrap_or(1);
     let page_size = params.get("page_size").and_then(|ps| ps.parse::<u64>().ok()).unwrap_or(10);
@@ -209,7 +231,7 @@ pub async fn find_recovered_documents_handler(
             
             match collection.find(filter, Some(options)).await {
                 Ok(cursor) => {
-                    match process_cursor(cursor).await {
+                    match process_cursor(cursor, &timezone).await {
                         Ok(items) => {
                             let paginated_data = PaginatedDocuments {
                                 items,
@@ -221,6 +243,8 @@ pub async fn find_recovered_documents_handler(
                                 success: true,
                                 data: Some(paginated_data),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<PaginatedDocuments>(StatusCode::INTERNAL_SERVER_ERROR, e),
@@ -238,7 +262,12 @@ pub async fn find_empty_archive_history_handler(
     Path(collection_name): Path<String>,
     Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<Vec<Document>>(StatusCode::BAD_REQUEST, e),
+    };
     
     // Extract filter from query parameters
     let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
@@ -264,12 +293,14 @@ pub async fn find_empty_archive_history_handler(
             
             match collection.find(filter, None).await {
                 Ok(cursor) => {
-                    match process_cursor(cursor).await {
+                    match process_cursor(cursor, &timezone).await {
                         Ok(documents) => {
                             (StatusCode::OK, Json(ApiResponse {
                                 success: true,
                                 data: Some(documents),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<Vec<Document>>(StatusCode::INTERNAL_SERVER_ERROR, e),
@@ -287,7 +318,12 @@ pub async fn find_empty_or_recovered_documents_handler(
     Path(collection_name): Path<String>,
     Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<PaginatedDocuments>(StatusCode::BAD_REQUEST, e),
+    };
     
     let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
     let mut filter: Document = match serde_json::from_str(&filter_str) {
@@ -298,23 +334,7 @@ pub async fn find_empty_or_recovered_documents_handler(
         ),
     };
     
-    filter.insert("$or", vec![
-        doc! {
-            "$or": [
-                { "archive_history": { "$exists": false } },
-                { "archive_history": { "$size": 0 } }
-            ]
-        },
-        doc! {
-            "archive_history.0": { "$exists": true },
-            "$expr": {
-                "$eq": [
-                    { "$arrayElemAt": ["$archive_history.action", -1] },
-                    "recover"
-                ]
-            }
-        }
-    ]);
+    filter.extend(empty_or_recovered_filter());
     
     // Update to handle both "limit" and "page_size" parameters for compatibility
     let page = params.get("page").and_then(|p| p.parse::<u64>().ok()).unwrap_or(1);
@@ -341,7 +361,7 @@ pub async fn find_empty_or_recovered_documents_handler(
             
             match collection.find(filter, Some(options)).await {
                 Ok(cursor) => {
-                    match process_cursor(cursor).await {
+                    match process_cursor(cursor, &timezone).await {
                         Ok(items) => {
                             let paginated_data = PaginatedDocuments {
                                 items,
@@ -353,6 +373,8 @@ pub async fn find_empty_or_recovered_documents_handler(
                                 success: true,
                                 data: Some(paginated_data),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<PaginatedDocuments>(StatusCode::INTERNAL_SERVER_ERROR, e),
@@ -396,7 +418,12 @@ pub async fn find_pinned_documents_handler(
         ),
     };
     
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<Vec<Document>>(StatusCode::BAD_REQUEST, e),
+    };
     
     let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
     
@@ -414,7 +441,7 @@ pub async fn find_pinned_documents_handler(
     match get_database(mongodb_state).await {
         Ok(db) => {
             // Check if the collection supports pinning
-            let schema = match get_collection_schema_internal(&db, &collection_name).await {
+            let schema = match state.schema_cache.schema(&db, &collection_name).await {
                 Ok(s) => s,
                 Err(e) => return error_response::<Vec<Document>>(
                     StatusCode::INTERNAL_SERVER_ERROR, 
@@ -433,12 +460,14 @@ pub async fn find_pinned_documents_handler(
             
             match collection.find(filter, None).await {
                 Ok(cursor) => {
-                    match process_cursor(cursor).await {
+                    match process_cursor(cursor, &timezone).await {
                         Ok(documents) => {
                             (StatusCode::OK, Json(ApiResponse {
                                 success: true,
                                 data: Some(documents),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<Vec<Document>>(StatusCode::INTERNAL_SERVER_ERROR, e),
@@ -451,12 +480,171 @@ pub async fn find_pinned_documents_handler(
     }
 }
 
+pub async fn get_document_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path((collection_name, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<Document>(StatusCode::BAD_REQUEST, e),
+    };
+
+    let object_id = match ObjectId::parse_str(&id) {
+        Ok(oid) => oid,
+        Err(e) => return error_response::<Document>(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid ObjectId: {}", e)
+        ),
+    };
+
+    match get_database(mongodb_state).await {
+        Ok(db) => {
+            let collection = db.collection::<Document>(&collection_name);
+
+            match collection.find_one(doc! { "_id": object_id }, None).await {
+                Ok(Some(mut document)) => {
+                    format_output_fields(&mut document, &timezone);
+                    (StatusCode::OK, Json(ApiResponse {
+                        success: true,
+                        data: Some(document),
+                        error: None,
+                        field_errors: None,
+                        error_code: None,
+                    }))
+                },
+                Ok(None) => error_response::<Document>(StatusCode::NOT_FOUND, "Document not found".into()),
+                Err(e) => error_response::<Document>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        },
+        Err((status, e)) => error_response::<Document>(status, e),
+    }
+}
+
+// Look a document up by the collection's natural key. The first key field comes from
+// the path, further fields of a compound key from query parameters named after them,
+// e.g. /collections/attendance/by-key/2021-0001?time_in_date=2024-06-03
+pub async fn get_document_by_key_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path((collection_name, value)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<Document>(StatusCode::BAD_REQUEST, e),
+    };
+
+    match get_database(mongodb_state).await {
+        Ok(db) => {
+            // Without a schema, key values are matched as plain strings
+            let schema = state.schema_cache.schema(&db, &collection_name).await.unwrap_or_default();
+
+            let key_fields = match get_natural_key_fields(&db, &collection_name, &schema).await {
+                Ok(fields) => fields,
+                Err(e) => return error_response::<Document>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            };
+
+            if key_fields.is_empty() {
+                return error_response::<Document>(
+                    StatusCode::BAD_REQUEST,
+                    format!("Collection '{}' has no unique natural key", collection_name)
+                );
+            }
+
+            let mut filter = Document::new();
+            for (i, field) in key_fields.iter().enumerate() {
+                let raw = if i == 0 { Some(&value) } else { params.get(field) };
+                let raw = match raw {
+                    Some(r) => r,
+                    None => return error_response::<Document>(
+                        StatusCode::BAD_REQUEST,
+                        format!("Missing value for key field '{}' (key: {})", field, key_fields.join(" + "))
+                    ),
+                };
+
+                match natural_key_condition(&schema, field, raw, &timezone) {
+                    Ok(condition) => { filter.insert(field, condition); },
+                    Err(e) => return error_response::<Document>(StatusCode::BAD_REQUEST, e),
+                }
+            }
+
+            let collection = db.collection::<Document>(&collection_name);
+            let options = FindOptions::builder().limit(2).build();
+
+            let documents = match collection.find(filter, Some(options)).await {
+                Ok(cursor) => match process_cursor(cursor, &timezone).await {
+                    Ok(docs) => docs,
+                    Err(e) => return error_response::<Document>(StatusCode::INTERNAL_SERVER_ERROR, e),
+                },
+                Err(e) => return error_response::<Document>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            };
+
+            match documents.len() {
+                0 => error_response::<Document>(StatusCode::NOT_FOUND, "Document not found".into()),
+                1 => (StatusCode::OK, Json(ApiResponse {
+                    success: true,
+                    data: documents.into_iter().next(),
+                    error: None,
+                    field_errors: None,
+                    error_code: None,
+                })),
+                _ => error_response::<Document>(
+                    StatusCode::CONFLICT,
+                    format!("Key matches more than one document (key: {})", key_fields.join(" + "))
+                ),
+            }
+        },
+        Err((status, e)) => error_response::<Document>(status, e),
+    }
+}
+
+// Build the match condition for one natural key field from its URL value
+fn natural_key_condition(schema: &Document, field: &str, raw: &str, timezone: &InstitutionTimezone) -> Result<Bson, String> {
+    let bson_type = schema.get_document("properties").ok()
+        .and_then(|props| props.get_document(field).ok())
+        .and_then(|spec| spec.get_str("bsonType").ok())
+        .unwrap_or("string");
+
+    match bson_type {
+        "date" => {
+            // A bare date matches the whole day in the institution timezone
+            if let Ok(day) = chrono::NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+                let (start, end) = timezone.day_bounds(day)?;
+                return Ok(Bson::Document(doc! { "$gte": start, "$lt": end }));
+            }
+            timezone.parse_datetime(raw)
+                .map(Bson::DateTime)
+                .map_err(|e| format!("Invalid date for key field '{}': {}", field, e))
+        },
+        "int" => raw.parse::<i32>()
+            .map(Bson::Int32)
+            .map_err(|e| format!("Invalid integer for key field '{}': {}", field, e)),
+        "long" => raw.parse::<i64>()
+            .map(Bson::Int64)
+            .map_err(|e| format!("Invalid long for key field '{}': {}", field, e)),
+        "objectId" => ObjectId::parse_str(raw)
+            .map(Bson::ObjectId)
+            .map_err(|e| format!("Invalid ObjectId for key field '{}': {}", field, e)),
+        _ => Ok(Bson::String(raw.to_string())),
+    }
+}
+
 pub async fn insert_document_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path(collection_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
     Json(document): Json<serde_json::Value>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<InsertResponse>(StatusCode::BAD_REQUEST, e),
+    };
     
     match get_database(mongodb_state).await {
         Ok(db) => {
@@ -464,26 +652,32 @@ pub async fn insert_document_handler(
             let doc_result = mongodb::bson::to_document(&document);
             match doc_result {
                 Ok(mut doc) => {
-                    // Remove any client-provided timestamp fields
-                    doc.remove("created_at");
-                    doc.remove("updated_at");
+                    let schema = state.schema_cache.schema(&db, &collection_name).await.unwrap_or_default();
                     
-                    // For attendance collection, also handle time_in_date
-                    if collection_name == "attendance" {
-                        doc.remove("time_in_date");
-                    }
-                    
-                    // Add server-managed timestamp fields
-                    let current_time = mongodb::bson::DateTime::now();
-                    doc.insert("created_at", current_time.clone());
+                    // Replace client-provided server-managed fields, stamp created_at and
+                    // fill in missing fields from their defaults
+                    let defaults = state.schema_cache.defaults(&db, &collection_name).await.unwrap_or_default();
+                    prepare_insert(&mut doc, &defaults);
                     
                     // For attendance collection, also set time_in_date
                     if collection_name == "attendance" {
-                        doc.insert("time_in_date", current_time);
+                        if let Ok(created_at) = doc.get_datetime("created_at") {
+                            doc.insert("time_in_date", *created_at);
+                        }
                     }
                     
-                    // Process fields according to schema types (dates, integers, etc.)
-                    if let Err(e) = process_document_fields(&db, &collection_name, &mut doc).await {
+                    // Convert fields to their schema types (dates, numbers, ids, nested values),
+                    // then check the result against the validator before writing
+                    if let Err(errors) = coerce_document(&mut doc, &schema, &timezone) {
+                        return field_errors_response::<InsertResponse>(COERCION_FAILED, errors);
+                    }
+                    let errors = validate_document(&doc, &schema);
+                    if !errors.is_empty() {
+                        return field_errors_response::<InsertResponse>(VALIDATION_FAILED, errors);
+                    }
+                    
+                    // Make sure REF: fields point at existing documents
+                    if let Err(e) = validate_document_references(&state.schema_cache, &db, &collection_name, &doc).await {
                         return error_response::<InsertResponse>(StatusCode::BAD_REQUEST, e);
                     }
                     
@@ -493,10 +687,13 @@ pub async fn insert_document_handler(
                         Ok(result) => {
                             match result.inserted_id.as_object_id() {
                                 Some(id) => {
+                                    state.event_bus.publish(&collection_name, ChangeAction::Insert, vec![id.to_hex()], None);
                                     (StatusCode::CREATED, Json(ApiResponse {
                                         success: true,
                                         data: Some(InsertResponse { id: id.to_hex() }),
                                         error: None,
+                                        field_errors: None,
+                                        error_code: None,
                                     }))
                                 },
                                 None => error_response::<InsertResponse>(
@@ -505,7 +702,7 @@ pub async fn insert_document_handler(
                                 ),
                             }
                         },
-                        Err(e) => error_response::<InsertResponse>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                        Err(e) => write_error_response::<InsertResponse>(e),
                     }
                 },
                 Err(e) => error_response::<InsertResponse>(
@@ -521,9 +718,15 @@ pub async fn insert_document_handler(
 pub async fn update_document_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path((collection_name, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
     Json(update): Json<Document>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<UpdateResponse>(StatusCode::BAD_REQUEST, e),
+    };
     
     match get_database(mongodb_state).await {
         Ok(db) => {
@@ -536,8 +739,8 @@ pub async fn update_document_handler(
                     // Process fields in the update document according to the schema
                     let mut update_doc = update.clone();
                     
-                    // Remove any attempts to modify timestamp fields
-                    update_doc.remove("created_at");
+                    // Remove any attempts to modify server-managed fields
+                    strip_server_managed(&mut update_doc);
                     
                     // Check if the update contains only row_height
                     // Count keys in update_doc and check if row_height is the only one
@@ -550,7 +753,23 @@ pub async fn update_document_handler(
                         update_doc.insert("updated_at", current_time);
                     }
                     
-                    if let Err(e) = process_document_fields(&db, &collection_name, &mut update_doc).await {
+                    let schema = state.schema_cache.schema(&db, &collection_name).await.unwrap_or_default();
+                    if let Err(errors) = coerce_document(&mut update_doc, &schema, &timezone) {
+                        return field_errors_response::<UpdateResponse>(COERCION_FAILED, errors);
+                    }
+                    
+                    // findAndModify doesn't say which rule a rejected update broke, so check
+                    // the fields being set first; anything else is left to the server
+                    if !schema.is_empty() {
+                        if let Ok(Some(current)) = collection.find_one(filter.clone(), None).await {
+                            let errors = SchemaValidator::new(&schema).validate_update(&current, &update_doc);
+                            if !errors.is_empty() {
+                                return field_errors_response::<UpdateResponse>(VALIDATION_FAILED, errors);
+                            }
+                        }
+                    }
+                    
+                    if let Err(e) = validate_document_references(&state.schema_cache, &db, &collection_name, &update_doc).await {
                         return error_response::<UpdateResponse>(StatusCode::BAD_REQUEST, e);
                     }
                     
@@ -564,7 +783,9 @@ pub async fn update_document_handler(
                     match collection.find_one_and_update(filter, update_bson, options).await {
                         Ok(Some(mut updated_doc)) => {
                             // Format the date fields for proper JSON serialization
-                            format_date_fields(&mut updated_doc);
+                            format_output_fields(&mut updated_doc, &timezone);
+                            
+                            state.event_bus.publish(&collection_name, ChangeAction::Update, vec![id.clone()], None);
                             
                             (StatusCode::OK, Json(ApiResponse {
                                 success: true,
@@ -574,16 +795,15 @@ pub async fn update_document_handler(
                                     document: Some(updated_doc),
                                 }),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Ok(None) => error_response::<UpdateResponse>(
                             StatusCode::NOT_FOUND, 
                             "Document not found".into()
                         ),
-                        Err(e) => error_response::<UpdateResponse>(
-                            StatusCode::INTERNAL_SERVER_ERROR, 
-                            e.to_string()
-                        ),
+                        Err(e) => write_error_response::<UpdateResponse>(e),
                     }
                 },
                 Err(e) => error_response::<UpdateResponse>(
@@ -600,7 +820,8 @@ pub async fn delete_document_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path((collection_name, id)): Path<(String, String)>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
     
     match get_database(mongodb_state).await {
         Ok(db) => {
@@ -610,8 +831,20 @@ pub async fn delete_document_handler(
                     let collection = db.collection::<Document>(&collection_name);
                     let filter = doc! { "_id": object_id };
                     
+                    // Enforce the ON_DELETE policies of documents referencing this one
+                    let removal = match plan_removal(&state.schema_cache, &db, &collection_name, &[object_id], RemovalKind::Delete).await {
+                        Ok(plan) => plan,
+                        Err(e) => return error_response::<DeleteResponse>(e.status_code(), e.message()),
+                    };
+                    
                     match collection.delete_one(filter, None).await {
                         Ok(result) => {
+                            if result.deleted_count > 0 {
+                                state.event_bus.publish(&collection_name, ChangeAction::Delete, vec![id.clone()], None);
+                                if let Err(e) = removal.apply(&db, None, &state.event_bus).await {
+                                    return reference_policy_response::<DeleteResponse>(result.deleted_count, e);
+                                }
+                            }
                             (StatusCode::OK, Json(ApiResponse {
                                 success: true,
                                 data: Some(DeleteResponse {
@@ -619,6 +852,8 @@ pub async fn delete_document_handler(
                                     deleted_count: result.deleted_count,
                                 }),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Err(e) => error_response::<DeleteResponse>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
@@ -639,7 +874,8 @@ pub async fn batch_delete_documents_handler(
     Path(collection_name): Path<String>,
     Json(payload): Json<HashMap<String, Vec<String>>>,
 ) -> impl IntoResponse {
-    let mongodb_state = &state.lock().await.mongodb_state;
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
     
     let ids = match payload.get("ids") {
         Some(ids) => ids,
@@ -664,10 +900,28 @@ pub async fn batch_delete_documents_handler(
     match get_database(mongodb_state).await {
         Ok(db) => {
             let collection = db.collection::<Document>(&collection_name);
+            
+            // Only ids that exist are deleted, and only their references are updated
+            let object_ids = match matching_ids(&collection, doc! { "_id": { "$in": object_ids } }).await {
+                Ok(ids) => ids,
+                Err(e) => return error_response::<DeleteResponse>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            };
+            let removal = match plan_removal(&state.schema_cache, &db, &collection_name, &object_ids, RemovalKind::Delete).await {
+                Ok(plan) => plan,
+                Err(e) => return error_response::<DeleteResponse>(e.status_code(), e.message()),
+            };
+            let ids: Vec<String> = object_ids.iter().map(|oid| oid.to_hex()).collect();
+            
             let filter = doc! { "_id": { "$in": object_ids } };
             
             match collection.delete_many(filter, None).await {
                 Ok(result) => {
+                    if result.deleted_count > 0 {
+                        state.event_bus.publish(&collection_name, ChangeAction::Delete, ids, None);
+                        if let Err(e) = removal.apply(&db, None, &state.event_bus).await {
+                            return reference_policy_response::<DeleteResponse>(result.deleted_count, e);
+                        }
+                    }
                     (StatusCode::OK, Json(ApiResponse {
                         success: true,
                         data: Some(DeleteResponse {
@@ -675,6 +929,8 @@ pub async fn batch_delete_documents_handler(
                             deleted_count: result.deleted_count,
                         }),
                         error: None,
+                        field_errors: None,
+                        error_code: None,
                     }))
                 },
                 Err(e) => error_response::<DeleteResponse>(
@@ -687,6 +943,13 @@ pub async fn batch_delete_documents_handler(
     }
 }
 
+// The _ids of the documents matching `filter`
+async fn matching_ids(collection: &Collection<Document>, filter: Document) -> Result<Vec<ObjectId>, mongodb::error::Error> {
+    let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+    let docs: Vec<Document> = collection.find(filter, options).await?.try_collect().await?;
+    Ok(docs.iter().filter_map(|d| d.get_object_id("_id").ok()).collect())
+}
+
 // archive handlers
 
 pub async fn archive_document_handler(
@@ -725,6 +988,12 @@ pub async fn archive_document_handler(
                 Err(e) => return error_response::<()>(StatusCode::BAD_REQUEST, format!("Invalid document ID: {}", e)),
             };
 
+            // Active documents referencing this one may block the archive
+            let removal = match plan_removal(&state.schema_cache, &db, &collection_name, &[doc_id], RemovalKind::Archive).await {
+                Ok(plan) => plan,
+                Err(e) => return error_response::<()>(e.status_code(), e.message()),
+            };
+
             // Create timestamp
             let now = mongodb::bson::DateTime::now();
 
@@ -756,15 +1025,23 @@ pub async fn archive_document_handler(
                                     success: true,
                                     data: Some(()),
                                     error: None,
+                                    field_errors: None,
+                                    error_code: None,
                                 }))
                             },
                             _ => error_response::<()>(StatusCode::NOT_FOUND, "Document not found".into()),
                         }
                     } else {
+                        state.event_bus.publish(&collection_name, ChangeAction::Archive, vec![id.clone()], Some(user_id.clone()));
+                        if let Err(e) = removal.apply(&db, Some(user_oid), &state.event_bus).await {
+                            return reference_policy_response::<()>(result.modified_count, e);
+                        }
                         (StatusCode::OK, Json(ApiResponse {
                             success: true,
                             data: Some(()),
                             error: None,
+                            field_errors: None,
+                            error_code: None,
                         }))
                     }
                 },
@@ -838,26 +1115,12 @@ pub async fn batch_archive_documents_handler(
             let collection = db.collection::<Document>(&collection_name);
             let now = mongodb::bson::DateTime::now();
 
-            // First check if all documents are already archived
-            let count_total = match collection.count_documents(
-                doc! { "_id": { "$in": &object_ids } },
-                None
-            ).await {
-                Ok(count) => count,
-                Err(e) => return error_response::<serde_json::Value>(
-                    StatusCode::INTERNAL_SERVER_ERROR, 
-                    e.to_string()
-                ),
-            };
-
-            let count_already_archived = match collection.count_documents(
-                doc! { 
-                    "_id": { "$in": &object_ids },
-                    "is_archive": true 
-                },
-                None
-            ).await {
-                Ok(count) => count,
+            // Only documents not archived yet are archived, and only their references updated
+            let object_ids = match matching_ids(&collection, doc! {
+                "_id": { "$in": &object_ids },
+                "is_archive": { "$ne": true }
+            }).await {
+                Ok(ids) => ids,
                 Err(e) => return error_response::<serde_json::Value>(
                     StatusCode::INTERNAL_SERVER_ERROR, 
                     e.to_string()
@@ -865,7 +1128,7 @@ pub async fn batch_archive_documents_handler(
             };
 
             // If all documents are already archived
-            if count_already_archived == count_total {
+            if object_ids.is_empty() {
                 return (StatusCode::OK, Json(ApiResponse {
                     success: true,
                     data: Some(json!({
@@ -873,9 +1136,16 @@ pub async fn batch_archive_documents_handler(
                         "archived_count": 0
                     })),
                     error: None,
+                    field_errors: None,
+                    error_code: None,
                 }));
             }
 
+            let removal = match plan_removal(&state.schema_cache, &db, &collection_name, &object_ids, RemovalKind::Archive).await {
+                Ok(plan) => plan,
+                Err(e) => return error_response::<serde_json::Value>(e.status_code(), e.message()),
+            };
+
             // Update only non-archived documents
             let filter = doc! {
                 "_id": { "$in": &object_ids },
@@ -895,6 +1165,13 @@ pub async fn batch_archive_documents_handler(
 
             match collection.update_many(filter, update, None).await {
                 Ok(result) => {
+                    if result.modified_count > 0 {
+                        let ids = object_ids.iter().map(|oid| oid.to_hex()).collect();
+                        state.event_bus.publish(&collection_name, ChangeAction::Archive, ids, Some(user_id.clone()));
+                        if let Err(e) = removal.apply(&db, Some(user_oid), &state.event_bus).await {
+                            return reference_policy_response::<serde_json::Value>(result.modified_count, e);
+                        }
+                    }
                     (StatusCode::OK, Json(ApiResponse {
                         success: true,
                         data: Some(json!({
@@ -902,6 +1179,8 @@ pub async fn batch_archive_documents_handler(
                             "archived_count": result.modified_count
                         })),
                         error: None,
+                        field_errors: None,
+                        error_code: None,
                     }))
                 },
                 Err(e) => error_response::<serde_json::Value>(
@@ -978,14 +1257,19 @@ pub async fn recover_document_handler(
                                 success: true,
                                 data: Some(()),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             })),
                             _ => error_response::<()>(StatusCode::NOT_FOUND, "Document not found".into()),
                         }
                     } else {
+                        state.event_bus.publish(&collection_name, ChangeAction::Recover, vec![id.clone()], Some(user_id.clone()));
                         (StatusCode::OK, Json(ApiResponse {
                             success: true,
                             data: Some(()),
                             error: None,
+                            field_errors: None,
+                            error_code: None,
                         }))
                     }
                 },
@@ -1077,6 +1361,8 @@ pub async fn batch_recover_documents_handler(
                         "recovered_count": 0
                     })),
                     error: None,
+                    field_errors: None,
+                    error_code: None,
                 }));
             }
 
@@ -1098,6 +1384,9 @@ pub async fn batch_recover_documents_handler(
 
             match collection.update_many(filter, update, None).await {
                 Ok(result) => {
+                    if result.modified_count > 0 {
+                        state.event_bus.publish(&collection_name, ChangeAction::Recover, ids.clone(), Some(user_id.clone()));
+                    }
                     (StatusCode::OK, Json(ApiResponse {
                         success: true,
                         data: Some(json!({
@@ -1105,6 +1394,8 @@ pub async fn batch_recover_documents_handler(
                             "recovered_count": result.modified_count
                         })),
                         error: None,
+                        field_errors: None,
+                        error_code: None,
                     }))
                 },
                 Err(e) => error_response::<serde_json::Value>(
@@ -1121,6 +1412,7 @@ pub async fn batch_recover_documents_handler(
 pub async fn pin_document_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path((collection_name, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
 ) -> impl IntoResponse {
     tracing::debug!(
@@ -1130,6 +1422,10 @@ pub async fn pin_document_handler(
     
     let token = auth.token();
     let state = state.lock().await;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<Document>(StatusCode::BAD_REQUEST, e),
+    };
     let session_manager = &state.session_manager;
     
     let valid = session_manager.lock().await.validate_session(token).await;
@@ -1157,7 +1453,7 @@ pub async fn pin_document_handler(
 
     match get_database(&state.mongodb_state).await {
         Ok(db) => {
-            let schema = match get_collection_schema_internal(&db, &collection_name).await {
+            let schema = match state.schema_cache.schema(&db, &collection_name).await {
                 Ok(s) => s,
                 Err(e) => {
                     tracing::error!("pin_document_handler: Failed to get schema: {}", e);
@@ -1205,12 +1501,15 @@ pub async fn pin_document_handler(
 
                 match collection.find_one_and_update(filter, update, options).await {
                     Ok(Some(mut updated_doc)) => {
-                        format_date_fields(&mut updated_doc);
+                        format_output_fields(&mut updated_doc, &timezone);
                         tracing::info!("Successfully pinned document {}", id);
+                        state.event_bus.publish(&collection_name, ChangeAction::Pin, vec![id.clone()], Some(user_id.clone()));
                         let response = Json(ApiResponse {
                             success: true,
                             data: Some(updated_doc),
                             error: None,
+                            field_errors: None,
+                            error_code: None,
                         });
                         tracing::info!("Response: {:?}", response); // Log the success response
                         (StatusCode::OK, response)
@@ -1218,11 +1517,13 @@ pub async fn pin_document_handler(
                 Ok(None) => {
                     match collection.find_one(doc! { "_id": doc_id }, None).await {
                         Ok(Some(mut existing_doc)) => {
-                            format_date_fields(&mut existing_doc);
+                            format_output_fields(&mut existing_doc, &timezone);
                             (StatusCode::OK, Json(ApiResponse {
                                 success: true,
                                 data: Some(existing_doc),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Ok(None) => {
@@ -1251,6 +1552,7 @@ pub async fn pin_document_handler(
 pub async fn unpin_document_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path((collection_name, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
 ) -> impl IntoResponse {
     tracing::debug!(
@@ -1260,6 +1562,10 @@ pub async fn unpin_document_handler(
     
     let token = auth.token();
     let state = state.lock().await;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => return error_response::<Document>(StatusCode::BAD_REQUEST, e),
+    };
     let session_manager = &state.session_manager;
     
     let valid = session_manager.lock().await.validate_session(token).await;
@@ -1287,7 +1593,7 @@ pub async fn unpin_document_handler(
 
     match get_database(&state.mongodb_state).await {
         Ok(db) => {
-            let schema = match get_collection_schema_internal(&db, &collection_name).await {
+            let schema = match state.schema_cache.schema(&db, &collection_name).await {
                 Ok(s) => s,
                 Err(e) => {
                     tracing::error!("unpin_document_handler: Failed to get schema: {}", e);
@@ -1335,12 +1641,15 @@ pub async fn unpin_document_handler(
 
             match collection.find_one_and_update(filter, update, options).await {
                 Ok(Some(mut updated_doc)) => {
-                    format_date_fields(&mut updated_doc);
+                    format_output_fields(&mut updated_doc, &timezone);
                     tracing::info!("Successfully unpinned document {}", id);
+                    state.event_bus.publish(&collection_name, ChangeAction::Unpin, vec![id.clone()], Some(user_id.clone()));
                     let response = Json(ApiResponse {
                         success: true,
                         data: Some(updated_doc),
                         error: None,
+                        field_errors: None,
+                        error_code: None,
                     });
                     tracing::info!("Response: {:?}", response); // Log the success response
                     (StatusCode::OK, response)
@@ -1348,11 +1657,13 @@ pub async fn unpin_document_handler(
                 Ok(None) => {
                     match collection.find_one(doc! { "_id": doc_id }, None).await {
                         Ok(Some(mut existing_doc)) => {
-                            format_date_fields(&mut existing_doc);
+                            format_output_fields(&mut existing_doc, &timezone);
                             (StatusCode::OK, Json(ApiResponse {
                                 success: true,
                                 data: Some(existing_doc),
                                 error: None,
+                                field_errors: None,
+                                error_code: None,
                             }))
                         },
                         Ok(None) => {
@@ -1378,215 +1689,383 @@ pub async fn unpin_document_handler(
     }
 }
 
+// Totals of numeric fields over the documents a listing shows. Sums of decimal
+// fields are computed by MongoDB in Decimal128, so money adds up to the cent.
+// e.g. /collections/fines/totals?fields=amount,paid&view=active
+pub async fn collection_totals_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(collection_name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
+    let mut filter: Document = match serde_json::from_str(&filter_str) {
+        Ok(f) => f,
+        Err(e) => return error_response::<Document>(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid filter JSON: {}", e)
+        ),
+    };
+    let view = params.get("view").map(|s| s.as_str()).unwrap_or("all");
+    if let Err(e) = apply_view_filter(view, &mut filter) {
+        return error_response::<Document>(StatusCode::BAD_REQUEST, e);
+    }
+
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+
+    match get_database(mongodb_state).await {
+        Ok(db) => {
+            // Default to every numeric field in the schema
+            let fields: Vec<String> = match params.get("fields") {
+                Some(list) => list.split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty()).collect(),
+                None => {
+                    let schema = state.schema_cache.schema(&db, &collection_name).await.unwrap_or_default();
+                    numeric_fields(&schema)
+                },
+            };
+
+            // $group output names can't contain dots, so fields are addressed by position
+            let mut group = doc! { "_id": Bson::Null, "count": { "$sum": 1 } };
+            for (i, field) in fields.iter().enumerate() {
+                let path = format!("${}", field);
+                group.insert(format!("sum_{}", i), doc! { "$sum": &path });
+                group.insert(format!("avg_{}", i), doc! { "$avg": &path });
+                group.insert(format!("min_{}", i), doc! { "$min": &path });
+                group.insert(format!("max_{}", i), doc! { "$max": &path });
+            }
+            let pipeline = vec![doc! { "$match": filter }, doc! { "$group": group }];
+
+            let collection = db.collection::<Document>(&collection_name);
+            let grouped = match collection.aggregate(pipeline, None).await {
+                Ok(mut cursor) => match cursor.next().await {
+                    Some(Ok(doc)) => doc,
+                    Some(Err(e)) => return error_response::<Document>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    None => Document::new(),
+                },
+                Err(e) => return error_response::<Document>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            };
+
+            let mut totals = Document::new();
+            for (i, field) in fields.iter().enumerate() {
+                let stat = |name: &str| grouped.get(format!("{}_{}", name, i)).cloned().unwrap_or(Bson::Null);
+                totals.insert(field.clone(), doc! {
+                    "sum": stat("sum"),
+                    "avg": stat("avg"),
+                    "min": stat("min"),
+                    "max": stat("max"),
+                });
+            }
+            let mut result = doc! {
+                "count": grouped.get("count").cloned().unwrap_or(Bson::Int32(0)),
+                "fields": totals,
+            };
+            format_output_fields(&mut result, &state.timezone);
+
+            (StatusCode::OK, Json(ApiResponse {
+                success: true,
+                data: Some(result),
+                error: None,
+                field_errors: None,
+                error_code: None,
+            }))
+        },
+        Err((status, e)) => error_response::<Document>(status, e),
+    }
+}
+
+// Top-level schema properties holding numbers
+fn numeric_fields(schema: &Document) -> Vec<String> {
+    let Ok(properties) = schema.get_document("properties") else {
+        return Vec::new();
+    };
+    properties.iter()
+        .filter(|(_, spec)| {
+            let types: Vec<&str> = match spec.as_document().and_then(|s| s.get("bsonType")) {
+                Some(Bson::String(t)) => vec![t.as_str()],
+                Some(Bson::Array(ts)) => ts.iter().filter_map(|t| t.as_str()).collect(),
+                _ => Vec::new(),
+            };
+            types.iter().any(|t| matches!(*t, "int" | "long" | "double" | "decimal" | "number"))
+        })
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
 pub async fn download_collection_csv_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
     Path(collection_name): Path<String>,
     Query(params): Query<HashMap<String, String>>,
 ) -> axum::response::Response {
-    println!("[DEBUG] download_collection_csv_handler called for collection: {}", collection_name);
     let header_type = params.get("headers").map(|s| s.as_str()).unwrap_or("original");
     let include_id = params.get("include_id").map(|s| s == "true").unwrap_or(false);
-    println!("[DEBUG] Header type requested: {}", header_type);
-    println!("[DEBUG] Include ID: {}", include_id);
+    let format = match ExportFormat::parse(params.get("format").map(|s| s.as_str()).unwrap_or("csv")) {
+        Ok(f) => f,
+        Err(e) => {
+            let (status, json) = error_response::<()>(StatusCode::BAD_REQUEST, e);
+            return error_to_response(status, json);
+        }
+    };
+    tracing::debug!(
+        "Export requested: collection={}, format={:?}, headers={}, include_id={}",
+        collection_name, format, header_type, include_id
+    );
     
-    let mongodb_state = &state.lock().await.mongodb_state;
-    println!("[DEBUG] Acquired MongoDB state lock");
+    // Same filter and view as the listings, so the export matches what the table shows
+    let filter_str = params.get("filter").cloned().unwrap_or_else(|| String::from("{}"));
+    let mut filter: Document = match serde_json::from_str(&filter_str) {
+        Ok(f) => f,
+        Err(e) => {
+            let (status, json) = error_response::<()>(StatusCode::BAD_REQUEST, format!("Invalid filter JSON: {}", e));
+            return error_to_response(status, json);
+        }
+    };
+    let view = params.get("view").map(|s| s.as_str()).unwrap_or("empty-or-recovered");
+    if let Err(e) = apply_view_filter(view, &mut filter) {
+        let (status, json) = error_response::<()>(StatusCode::BAD_REQUEST, e);
+        return error_to_response(status, json);
+    }
+    
+    let state = state.lock().await;
+    let mongodb_state = &state.mongodb_state;
+    let timezone = match state.timezone.with_override(&params) {
+        Ok(tz) => tz,
+        Err(e) => {
+            let (status, json) = error_response::<()>(StatusCode::BAD_REQUEST, e);
+            return error_to_response(status, json);
+        }
+    };
     
     match get_database(mongodb_state).await {
         Ok(db) => {
-            println!("[DEBUG] Successfully connected to database");
             let collection = db.collection::<Document>(&collection_name);
-            println!("[DEBUG] Using collection: {}", collection_name);
             
             // Get schema with UI metadata
-            println!("[DEBUG] Fetching collection schema with UI metadata");
-            let schema = match get_collection_schema_with_ui(&db, &collection_name).await {
-                Ok(s) => {
-                    println!("[DEBUG] Schema fetched successfully");
-                    s
-                },
+            let schema = match state.schema_cache.schema_with_ui(&db, &collection_name).await {
+                Ok(s) => s,
                 Err(e) => {
-                    println!("[ERROR] Failed to fetch schema: {}", e);
+                    tracing::error!("Export: failed to fetch schema: {}", e);
                     let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e);
                     return error_to_response(status, json);
                 }
             };
             
-            // Get all documents
-            println!("[DEBUG] Executing find() to retrieve all documents");
-            let cursor = match collection.find(None, None).await {
-                Ok(c) => {
-                    println!("[DEBUG] Cursor obtained successfully");
-                    c
-                },
-                Err(e) => {
-                    println!("[ERROR] Failed to get cursor: {}", e);
-                    let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
-                    return error_to_response(status, json);
-                }
-            };
-            
-            println!("[DEBUG] Processing cursor to retrieve documents");
-            let documents = match process_cursor(cursor).await {
-                Ok(docs) => {
-                    println!("[DEBUG] Retrieved {} documents", docs.len());
-                    docs
-                },
-                Err(e) => {
-                    println!("[ERROR] Failed to process cursor: {}", e);
-                    let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e);
+            // Get headers
+            let properties = match schema.get_document("properties") {
+                Ok(p) => p,
+                Err(_) => {
+                    let (status, json) = error_response::<()>(
+                        StatusCode::INTERNAL_SERVER_ERROR, 
+                        "Schema has no properties".into()
+                    );
                     return error_to_response(status, json);
                 }
             };
             
-            // Generate CSV
-            println!("[DEBUG] Initializing CSV writer");
-            let mut wtr = csv::WriterBuilder::new()
-                .quote_style(csv::QuoteStyle::NonNumeric) // Only quote non-numeric fields
-                .double_quote(true) // Use standard CSV double-quoting
-                .from_writer(vec![]);
-            
-            // Get headers
-            println!("[DEBUG] Extracting properties from schema");
-            let properties = schema.get_document("properties").unwrap();
-            println!("[DEBUG] Found {} properties", properties.keys().count());
-            
             // Create a longer-lived empty document
             let empty_doc = Document::new();
+            let ui = schema.get_document("ui").unwrap_or(&empty_doc);
             
-            // Build field list
-            println!("[DEBUG] Building field list");
-            let mut fields: Vec<String> = properties.keys().map(|k| k.to_string()).collect();
+            // Build field list: explicit columns, otherwise the table's column order without hidden columns
+            let include_hidden = params.get("include_hidden").map(|s| s == "true").unwrap_or(false);
+            let mut fields = match params.get("columns") {
+                Some(columns) => columns.split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| {
+                        let root = c.split('.').next().unwrap_or_default();
+                        !c.is_empty() && (c == "_id" || properties.contains_key(root))
+                    })
+                    .collect(),
+                None => view_columns(properties, ui, include_hidden),
+            };
             if include_id && !fields.contains(&"_id".to_string()) {
-                println!("[DEBUG] Adding _id to field list");
                 fields.insert(0, "_id".to_string());
             }
             
-            println!("[DEBUG] Looking for short_names in UI metadata");
-            let short_names = schema.get_document("ui")
-                .and_then(|ui| ui.get_document("short_names"))
-                .unwrap_or(&empty_doc);
+            let short_names = ui.get_document("short_names").unwrap_or(&empty_doc);
+            
+            let column_widths = ui.get_document("columnWidths").unwrap_or(&empty_doc);
             
             // Generate headers with correct names
-            let headers: Vec<String> = fields.iter().map(|field| {
-                if header_type == "short" {
-                    let result = short_names.get_str(field).unwrap_or(field).to_string();
-                    println!("[DEBUG] Mapping header '{}' to short name '{}'", field, result);
-                    result
+            let columns: Vec<ExportColumn> = fields.iter().map(|field| ExportColumn {
+                field: field.clone(),
+                header: if header_type == "short" {
+                    short_names.get_str(field).unwrap_or(field).to_string()
                 } else {
-                    println!("[DEBUG] Using original header name: {}", field);
                     field.clone()
-                }
+                },
+                width: column_widths.get(field).and_then(|w| match w {
+                    Bson::Int32(i) => Some(*i as f64),
+                    Bson::Int64(i) => Some(*i as f64),
+                    Bson::Double(d) => Some(*d),
+                    _ => None,
+                }),
             }).collect();
-            println!("[DEBUG] Final headers: {:?}", headers);
             
-            // Write headers
-            println!("[DEBUG] Writing headers to CSV");
-            if let Err(e) = wtr.write_record(&headers) {
-                println!("[ERROR] Failed to write CSV headers: {}", e);
-                let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
-                return error_to_response(status, json);
-            }
+            // Spreadsheets get one column per nested value, the JSON formats keep documents whole
+            let columns = if format.is_document_format() {
+                columns
+            } else {
+                let lengths = match array_lengths(&collection, &filter, &array_paths(properties, &fields)).await {
+                    Ok(lengths) => lengths,
+                    Err(e) => {
+                        let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e);
+                        return error_to_response(status, json);
+                    }
+                };
+                flatten_columns(columns, properties, &lengths)
+            };
             
-            // Write rows
-            println!("[DEBUG] Writing document data to CSV rows");
-            let mut row_count = 0;
-            for doc in documents {
-                let mut row = Vec::new();
-                for field in &fields {
-                    let value = match doc.get(field) {
-                        Some(bson) => match bson {
-                            mongodb::bson::Bson::ObjectId(oid) => {
-                                println!("[DEBUG] Converting ObjectId value for field: {}", field);
-                                oid.to_hex()
-                            },
-                            mongodb::bson::Bson::DateTime(dt) => {
-                                println!("[DEBUG] Converting DateTime value for field: {}", field);
-                                let millis = dt.timestamp_millis();
-                                let datetime = chrono::DateTime::from_timestamp_millis(millis)
-                                    .unwrap_or_default();
-                                datetime.to_rfc3339()
-                            },
-                            mongodb::bson::Bson::String(s) => {
-                                println!("[DEBUG] Using String value directly for field: {}", field);
-                                s.clone()
-                            },
-                            mongodb::bson::Bson::Int32(i) => {
-                                println!("[DEBUG] Converting Int32 value for field: {}", field);
-                                i.to_string()
-                            },
-                            mongodb::bson::Bson::Int64(i) => {
-                                println!("[DEBUG] Converting Int64 value for field: {}", field);
-                                i.to_string()
-                            },
-                            mongodb::bson::Bson::Double(d) => {
-                                println!("[DEBUG] Converting Double value for field: {}", field);
-                                d.to_string()
-                            },
-                            mongodb::bson::Bson::Boolean(b) => {
-                                println!("[DEBUG] Converting Boolean value for field: {}", field);
-                                b.to_string()
-                            },
-                            _ => {
-                                println!("[DEBUG] Converting other BSON type for field: {}", field);
-                                bson.to_string()
-                            },
-                        },
-                        None => String::new(),
-                    };
-                    row.push(value);
-                }
-                
-                if let Err(e) = wtr.write_record(&row) {
-                    println!("[ERROR] Failed to write CSV row {}: {}", row_count, e);
+            // Total row count lets clients show download progress
+            let total = match collection.count_documents(filter.clone(), None).await {
+                Ok(count) => count,
+                Err(e) => {
                     let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
                     return error_to_response(status, json);
                 }
-                
-                row_count += 1;
-                if row_count % 100 == 0 {
-                    println!("[DEBUG] Processed {} rows", row_count);
-                }
-            }
-            println!("[DEBUG] Finished writing all {} rows", row_count);
+            };
             
-            let data = match wtr.into_inner() {
-                Ok(d) => {
-                    println!("[DEBUG] Successfully finalized CSV writer, data size: {} bytes", d.len());
-                    d
-                },
+            // Sort by the request, falling back to the table's saved sort settings
+            let sort_field = params.get("sort").cloned()
+                .or_else(|| ui.get_document("sortSettings").ok()
+                    .and_then(|s| s.get_str("field").ok())
+                    .map(|f| f.to_string()))
+                .filter(|f| !f.is_empty());
+            let direction = params.get("direction").cloned()
+                .or_else(|| ui.get_document("sortSettings").ok()
+                    .and_then(|s| s.get_str("direction").ok())
+                    .map(|d| d.to_string()));
+            let options = sort_field.map(|field| {
+                let order = if direction.as_deref() == Some("desc") { -1 } else { 1 };
+                FindOptions::builder().sort(doc! { field: order }).build()
+            });
+            
+            let cursor = match collection.find(filter, options).await {
+                Ok(c) => c,
                 Err(e) => {
-                    println!("[ERROR] Failed to finalize CSV writer: {}", e);
+                    tracing::error!("Export: failed to get cursor: {}", e);
                     let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
                     return error_to_response(status, json);
                 }
             };
             
+            let exporter = match create_exporter(format, columns, &collection_name, timezone) {
+                Ok(exporter) => exporter,
+                Err(e) => {
+                    let (status, json) = error_response::<()>(StatusCode::INTERNAL_SERVER_ERROR, e);
+                    return error_to_response(status, json);
+                }
+            };
+            let documents = cursor.map(|result| result.map_err(|e| e.to_string()));
+            let body = axum::body::Body::from_stream(stream_export(documents, exporter, collection_name.clone()));
+            
             // Create filename
             let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
-            let filename = format!("{}_{}.csv", collection_name, timestamp);
-            println!("[DEBUG] Generated filename: {}", filename);
+            let filename = format!("{}_{}.{}", collection_name, timestamp, format.extension());
 
-            // Create and return a CSV response
-            println!("[DEBUG] Preparing HTTP response with CSV data");
-            let mut response = axum::response::Response::new(axum::body::Body::from(data));
-            response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static("text/csv"));
+            // Create and return a streamed response
+            let mut response = axum::response::Response::new(body);
+            response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(format.content_type()));
             response.headers_mut().insert(
                 header::CONTENT_DISPOSITION,
                 header::HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename)).unwrap(),
             );
+            response.headers_mut().insert("X-Total-Count", header::HeaderValue::from(total));
             *response.status_mut() = StatusCode::OK;
-            println!("[DEBUG] CSV download response ready for collection: {}", collection_name);
             response
         },
         Err((status, e)) => {
-            println!("[ERROR] Database connection failed: {}", e);
+            tracing::error!("Export: database connection failed: {}", e);
             let (status_code, json_response) = error_response::<()>(status, e);
             error_to_response(status_code, json_response)
         }
     }
 }
 
+// Narrow a listing filter to one of the table views (see the listing endpoints).
+// Both conditions must hold, so the view can't replace an `$or`, `$expr` or
+// `is_archive` of the caller's filter.
+fn apply_view_filter(view: &str, filter: &mut Document) -> Result<(), String> {
+    let view_filter = match view {
+        "all" => return Ok(()),
+        "archives" => archived_filter(),
+        "recoveries" => recovered_filter(),
+        "empty-or-recovered" => empty_or_recovered_filter(),
+        "active" => doc! { "is_archive": { "$ne": true } },
+        other => return Err(format!("Unknown view '{}'", other)),
+    };
+    if filter.is_empty() {
+        *filter = view_filter;
+    } else {
+        *filter = doc! { "$and": [std::mem::take(filter), view_filter] };
+    }
+    Ok(())
+}
+
+// Conditions of the archive views, shared by their listing endpoints and the
+// export so an export returns the rows of the table it was started from
+fn archived_filter() -> Document {
+    doc! { "is_archive": true }
+}
+
+fn recovered_filter() -> Document {
+    doc! {
+        "is_archive": false,
+        "$expr": {
+            "$eq": [
+                { "$arrayElemAt": ["$archive_history.action", -1] },
+                "recover"
+            ]
+        }
+    }
+}
+
+// Never archived, or recovered since the last archive
+fn empty_or_recovered_filter() -> Document {
+    doc! {
+        "$or": [
+            {
+                "$or": [
+                    { "archive_history": { "$exists": false } },
+                    { "archive_history": { "$size": 0 } }
+                ]
+            },
+            {
+                "archive_history.0": { "$exists": true },
+                "$expr": {
+                    "$eq": [
+                        { "$arrayElemAt": ["$archive_history.action", -1] },
+                        "recover"
+                    ]
+                }
+            }
+        ]
+    }
+}
+
+// Columns in the saved table order, followed by any properties missing from it
+fn view_columns(properties: &Document, ui: &Document, include_hidden: bool) -> Vec<String> {
+    let as_strings = |key: &str| -> Vec<String> {
+        ui.get_array(key)
+            .map(|items| items.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+            .unwrap_or_default()
+    };
+    let hidden = as_strings("hiddenColumns");
+
+    let mut columns: Vec<String> = as_strings("columnOrder").into_iter()
+        .filter(|c| properties.contains_key(c))
+        .collect();
+    for key in properties.keys() {
+        if !columns.contains(key) {
+            columns.push(key.clone());
+        }
+    }
+
+    if !include_hidden {
+        columns.retain(|c| !hidden.contains(c));
+    }
+    columns
+}
+
 // Helper function to convert error response to Axum response
 fn error_to_response(status: StatusCode, json: Json<ApiResponse<()>>) -> axum::response::Response {
     let json_string = serde_json::to_string(&json.0).unwrap();
@@ -1613,13 +2092,14 @@ fn schema_has_pinned_property(schema: &Document) -> bool {
 
 // Helper functions for document handlers
 pub async fn process_cursor(
-    mut cursor: Cursor<Document>
+    mut cursor: Cursor<Document>,
+    timezone: &InstitutionTimezone,
 ) -> Result<Vec<Document>, String> {
     let mut documents = Vec::new();
     while let Some(document_result) = cursor.next().await {
         match document_result {
             Ok(mut doc) => {
-                format_date_fields(&mut doc);
+                format_output_fields(&mut doc, timezone);
                 documents.push(doc);
             },
             Err(e) => return Err(format!("Error retrieving document: {}", e)),
@@ -1628,18 +2108,3 @@ pub async fn process_cursor(
     
     Ok(documents)
 }
-
-pub fn format_date_fields(doc: &mut Document) {
-    // Similar to your existing implementation
-    let keys: Vec<String> = doc.keys().cloned().collect();
-    
-    for key in keys {
-        if let Some(mongodb::bson::Bson::DateTime(date_time)) = doc.get(&key) {
-            let chrono_date = chrono::DateTime::from_timestamp_millis(date_time.timestamp_millis())
-                .unwrap_or_else(|| chrono::DateTime::from_timestamp(0, 0).unwrap());
-            
-            let formatted_date = chrono_date.format("%Y-%m-%d %H:%M:%S").to_string();
-            doc.insert(key, mongodb::bson::Bson::String(formatted_date));
-        }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,57 @@
+// src/api_server/handlers/event_handlers.rs
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::api_server::state::ApiServerState;
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    // Comma separated collection names; all collections when omitted
+    pub collections: Option<String>,
+}
+
+// Server-Sent Events stream of document changes.
+// Emits "change" events carrying a ChangeEvent, and a "resync" event when the
+// client fell too far behind and should reload its data.
+pub async fn subscribe_events_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.lock().await.event_bus.subscribe();
+
+    let collections: HashSet<String> = query.collections
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        match receiver.recv().await {
+            Ok(event) => Some((Ok(event), receiver)),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => Some((Err(skipped), receiver)),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    })
+    .filter_map(move |received| {
+        let event = match received {
+            Ok(change) if collections.is_empty() || collections.contains(&change.collection) => {
+                Event::default().event("change").json_data(&change).ok()
+            },
+            Ok(_) => None,
+            Err(skipped) => Some(Event::default().event("resync").data(skipped.to_string())),
+        };
+        futures_util::future::ready(event.map(Ok))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
@@ -6,4 +6,5 @@ pub mod document_handlers;
 pub mod system_handlers;
 pub mod csv_temp_handlers;
 pub mod csv_download_handler;
-pub mod csv_import_handler;
\ No newline at end of file
+pub mod csv_import_handler;
+pub mod event_handlers;
\ No newline at end of file
@@ -1,19 +1,25 @@
 // src/api_server/handlers/system_handlers.rs
 
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     Json, 
-    extract::State,
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
 };
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{info, error};
 
 use crate::api_server::state::ApiServerState;
-use crate::api_server::models::{ApiResponse, error_response};
+use crate::api_server::models::{ApiResponse, RestoreSnapshotPayload, error_response};
+use crate::api_server::services::snapshot_service::{delete_snapshot, list_snapshots, restore_snapshot, SnapshotInfo};
 use crate::api_server::services::database_service::get_database;
+use crate::api_server::services::schema_export_service::{load_schemas, render, SchemaFormat};
+use crate::api_server::services::dictionary_service::{self, build_dictionary, DataDictionary, DictionaryFormat};
 use crate::mongodb_schema;
+use crate::schema_pack::{apply_pack, available_packs, find_pack};
+use crate::migrations::{self, MigrationStatus};
 
 // System handlers
 pub async fn health_check_handler() -> impl IntoResponse {
@@ -25,16 +31,20 @@ pub async fn initialize_library_collections_handler(
     State(state): State<Arc<Mutex<ApiServerState>>>,
 ) -> impl IntoResponse {
     info!("API endpoint called: initialize library collections");
-    let mongodb_state = &state.lock().await.mongodb_state;
-    match get_database(mongodb_state).await {
+    let state = state.lock().await;
+    match get_database(&state.mongodb_state).await {
         Ok(db) => {
-            match mongodb_schema::initialize_library_collections(&db).await {
+            let result = mongodb_schema::initialize_library_collections(&db).await;
+            state.schema_cache.invalidate_all();
+            match result {
                 Ok(_) => {
                     info!("Successfully initialized library collections via API endpoint");
                     (StatusCode::OK, Json(ApiResponse {
                         success: true,
                         data: None,
                         error: None,
+                        field_errors: None,
+                        error_code: None,
                     }))
                 },
                 Err(e) => {
@@ -51,4 +61,269 @@ pub async fn initialize_library_collections_handler(
             error_response::<()>(status, e)
         },
     }
-}
\ No newline at end of file
+}
+
+// Schema packs that can be installed: bundled ones and those in SCHEMA_PACK_DIR
+pub async fn list_schema_packs_handler() -> impl IntoResponse {
+    let packs = match available_packs() {
+        Ok(packs) => packs,
+        Err(e) => return error_response::<Vec<serde_json::Value>>(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let packs: Vec<serde_json::Value> = packs.iter()
+        .map(|pack| serde_json::json!({
+            "name": pack.name,
+            "description": pack.description,
+            "collections": pack.collections.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+        }))
+        .collect();
+
+    (StatusCode::OK, Json(ApiResponse {
+        success: true,
+        data: Some(packs),
+        error: None,
+        field_errors: None,
+        error_code: None,
+    }))
+}
+
+// Create the missing collections of a schema pack. The data lists the existing
+// collections whose validator differs from the pack.
+pub async fn apply_schema_pack_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let pack = match find_pack(&name) {
+        Ok(Some(pack)) => pack,
+        Ok(None) => return error_response::<Vec<String>>(StatusCode::NOT_FOUND, format!("Schema pack '{}' not found", name)),
+        Err(e) => return error_response::<Vec<String>>(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<Vec<String>>(status, e),
+    };
+
+    let result = apply_pack(&db, &pack).await;
+    state.schema_cache.invalidate_all();
+    match result {
+        Ok(drifted) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(drifted),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err(e) => {
+            error!("Failed to apply schema pack '{}': {}", name, e);
+            error_response::<Vec<String>>(StatusCode::INTERNAL_SERVER_ERROR, e)
+        },
+    }
+}
+
+// Applied and pending schema migrations
+pub async fn migration_status_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+) -> impl IntoResponse {
+    let mongodb_state = &state.lock().await.mongodb_state;
+    let db = match get_database(mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<MigrationStatus>(status, e),
+    };
+
+    match migrations::status(&db).await {
+        Ok(status) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(status),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err(e) => error_response::<MigrationStatus>(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RollbackPayload {
+    pub target_version: i64,
+}
+
+// Undo migrations newer than target_version; responds with the versions rolled back
+pub async fn rollback_migrations_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Json(payload): Json<RollbackPayload>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<Vec<i64>>(status, e),
+    };
+
+    let result = migrations::rollback_to(&db, payload.target_version).await;
+    state.schema_cache.invalidate_all();
+    match result {
+        Ok(undone) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(undone),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err(e) => {
+            error!("Migration rollback failed: {}", e);
+            error_response::<Vec<i64>>(StatusCode::CONFLICT, e)
+        },
+    }
+}
+
+// Snapshots taken before collections were truncated or dropped, newest first
+pub async fn list_snapshots_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let db = match get_database(&state.mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<Vec<SnapshotInfo>>(status, e),
+    };
+
+    match list_snapshots(&db, &state.timezone).await {
+        Ok(snapshots) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(snapshots),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err(e) => error_response::<Vec<SnapshotInfo>>(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+// Restore a snapshot into its source collection, or into `target`; returns the collection name
+pub async fn restore_snapshot_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(name): Path<String>,
+    payload: Option<Json<RestoreSnapshotPayload>>,
+) -> impl IntoResponse {
+    let (db, schema_cache) = {
+        let state = state.lock().await;
+        match get_database(&state.mongodb_state).await {
+            Ok(db) => (db, state.schema_cache.clone()),
+            Err((status, e)) => return error_response::<String>(status, e),
+        }
+    };
+    let target = payload.and_then(|Json(p)| p.target);
+
+    // A failed restore may have left the target half created, so forget everything
+    let result = restore_snapshot(&db, &name, target.as_deref()).await;
+    schema_cache.invalidate_all();
+    match result {
+        Ok(collection) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(collection),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err((status, e)) => error_response::<String>(status, e),
+    }
+}
+
+pub async fn delete_snapshot_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let mongodb_state = &state.lock().await.mongodb_state;
+    let db = match get_database(mongodb_state).await {
+        Ok(db) => db,
+        Err((status, e)) => return error_response::<()>(status, e),
+    };
+
+    match delete_snapshot(&db, &name).await {
+        Ok(()) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: None,
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })),
+        Err((status, e)) => error_response::<()>(status, e),
+    }
+}
+
+// Generated types for the collection schemas: ?format=ts|json-schema|openapi, and
+// optionally ?collections=a,b to export only those
+pub async fn export_schemas_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let format = match SchemaFormat::parse(params.get("format").map(String::as_str).unwrap_or_default()) {
+        Ok(format) => format,
+        Err(e) => return error_response::<()>(StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let only: Option<Vec<String>> = params.get("collections")
+        .map(|list| list.split(',').map(str::trim).filter(|n| !n.is_empty()).map(String::from).collect());
+
+    let (db, schema_cache) = {
+        let state = state.lock().await;
+        match get_database(&state.mongodb_state).await {
+            Ok(db) => (db, state.schema_cache.clone()),
+            Err((status, e)) => return error_response::<()>(status, e).into_response(),
+        }
+    };
+
+    match load_schemas(&schema_cache, &db, only.as_deref()).await {
+        Ok(schemas) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, format.content_type().to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", format.file_name())),
+            ],
+            render(format, &schemas),
+        ).into_response(),
+        Err((status, e)) => error_response::<()>(status, e).into_response(),
+    }
+}
+
+// Data dictionary of every collection: ?format=json (default), html, md, or an
+// entity-relationship diagram as mermaid or dot
+pub async fn data_dictionary_handler(
+    State(state): State<Arc<Mutex<ApiServerState>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let format = match DictionaryFormat::parse(params.get("format").map(String::as_str).unwrap_or_default()) {
+        Ok(format) => format,
+        Err(e) => return error_response::<DataDictionary>(StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let (db, timezone, schema_cache) = {
+        let state = state.lock().await;
+        let timezone = match state.timezone.with_override(&params) {
+            Ok(tz) => tz,
+            Err(e) => return error_response::<DataDictionary>(StatusCode::BAD_REQUEST, e).into_response(),
+        };
+        match get_database(&state.mongodb_state).await {
+            Ok(db) => (db, timezone, state.schema_cache.clone()),
+            Err((status, e)) => return error_response::<DataDictionary>(status, e).into_response(),
+        }
+    };
+
+    let dictionary = match build_dictionary(&schema_cache, &db, &timezone).await {
+        Ok(dictionary) => dictionary,
+        Err((status, e)) => return error_response::<DataDictionary>(status, e).into_response(),
+    };
+    if format == DictionaryFormat::Json {
+        return (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            data: Some(dictionary),
+            error: None,
+            field_errors: None,
+            error_code: None,
+        })).into_response();
+    }
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("inline; filename=\"{}\"", format.file_name())),
+        ],
+        dictionary_service::render(format, &dictionary),
+    ).into_response()
+}
@@ -6,6 +6,7 @@ pub mod handlers;
 pub mod services;
 pub mod routes;
 pub mod commands;
+pub mod events;
 
 // Re-export the main components for backwards compatibility
 pub use state::ApiServerState;
@@ -18,4 +18,63 @@ pub struct RegisterPayload {
 #[derive(Deserialize)]
 pub struct SessionCheckPayload {
     pub token: String,
-}
\ No newline at end of file
+}
+
+// Field attributes the schema editor can set. On an update, omitted attributes are
+// left as they are; an empty `enum` list or `pattern` removes the constraint.
+#[derive(Deserialize, Default)]
+pub struct FieldDefinition {
+    // A single type ("int") or a list (["string", "null"])
+    #[serde(rename = "bsonType")]
+    pub bson_type: Option<serde_json::Value>,
+    pub required: Option<bool>,
+    #[serde(rename = "enum")]
+    pub allowed_values: Option<Vec<serde_json::Value>>,
+    pub pattern: Option<String>,
+    pub description: Option<String>,
+    // Value inserts get when the field is missing; null removes the default
+    #[serde(default, deserialize_with = "present")]
+    pub default: Option<serde_json::Value>,
+}
+
+// Tell a field sent as null (Some(Null)) apart from one left out (None)
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error> {
+    serde_json::Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+pub struct AddFieldPayload {
+    pub name: String,
+    #[serde(flatten)]
+    pub definition: FieldDefinition,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateFieldPayload {
+    // New name for the field; documents are renamed along with the schema
+    pub rename_to: Option<String>,
+    #[serde(flatten)]
+    pub definition: FieldDefinition,
+}
+
+// Body of the collection rename/clone/truncate calls. Sent without `confirm_token`
+// the call only returns a token and a summary; sent again with it, it runs.
+#[derive(Deserialize)]
+pub struct LifecyclePayload {
+    // Target name for rename and clone
+    pub to: Option<String>,
+    // Clone only: copy the documents too, not just schema, indexes and table settings
+    #[serde(default = "default_with_documents")]
+    pub with_documents: bool,
+    pub confirm_token: Option<String>,
+}
+
+fn default_with_documents() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct RestoreSnapshotPayload {
+    // Collection to restore into; defaults to the one the snapshot was taken of
+    pub target: Option<String>,
+}
@@ -3,6 +3,8 @@
 use axum::{http::StatusCode, Json};
 use mongodb::bson::Document;
 use serde::{Deserialize, Serialize};
+use crate::api_server::services::coercion_service::{describe_errors, FieldError};
+use crate::api_server::services::validation_service::validation_failure;
 
 // Document response types
 #[cfg_attr(debug_assertions, derive(Debug))] // Only in debug builds
@@ -11,8 +13,24 @@ pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub error: Option<String>,
+    // Per-field details when a document failed coercion or schema validation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field_errors: Option<Vec<FieldError>>,
+    // Machine-readable reason for the failure, one of the *_FAILED constants below
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
 }
 
+// A value could not be converted to its schema type
+pub const COERCION_FAILED: &str = "COERCION_FAILED";
+
+// The document does not satisfy the collection's $jsonSchema validator
+pub const VALIDATION_FAILED: &str = "VALIDATION_FAILED";
+
+// Documents were removed, but the ON_DELETE policies of the documents referencing
+// them could not be (fully) applied
+pub const REFERENCE_POLICY_FAILED: &str = "REFERENCE_POLICY_FAILED";
+
 #[derive(Serialize, Deserialize)]
 pub struct InsertResponse {
     pub id: String,
@@ -54,9 +72,47 @@ pub fn error_response<T: Serialize>(status: StatusCode, message: String) -> (Sta
         success: false,
         data: None,
         error: Some(message),
+        field_errors: None,
+        error_code: None,
+    }))
+}
+
+// Error response for a removal that went through while its reference policies didn't
+pub fn reference_policy_response<T: Serialize>(removed: u64, error: String) -> (StatusCode, Json<ApiResponse<T>>) {
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiResponse {
+        success: false,
+        data: None,
+        error: Some(format!("Removed {} document(s), but applying reference policies failed: {}", removed, error)),
+        field_errors: None,
+        error_code: Some(REFERENCE_POLICY_FAILED.to_string()),
+    }))
+}
+
+// Error response listing the fields that failed, tagged with COERCION_FAILED or VALIDATION_FAILED
+pub fn field_errors_response<T: Serialize>(code: &str, errors: Vec<FieldError>) -> (StatusCode, Json<ApiResponse<T>>) {
+    let message = if errors.is_empty() {
+        "Document failed schema validation".to_string()
+    } else {
+        describe_errors(&errors)
+    };
+    (StatusCode::BAD_REQUEST, Json(ApiResponse {
+        success: false,
+        data: None,
+        error: Some(message),
+        field_errors: Some(errors),
+        error_code: Some(code.to_string()),
     }))
 }
 
+// Error response for a failed write: field errors when the validator rejected the
+// document, a plain 500 otherwise
+pub fn write_error_response<T: Serialize>(error: mongodb::error::Error) -> (StatusCode, Json<ApiResponse<T>>) {
+    match validation_failure(&error) {
+        Some(errors) => field_errors_response(VALIDATION_FAILED, errors),
+        None => error_response(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct PaginatedDocuments {
     pub items: Vec<Document>,
@@ -5,6 +5,7 @@ use axum::{
     http::Method,
     Router,
     middleware::map_request,
+    extract::DefaultBodyLimit,
 };
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -20,8 +21,21 @@ use crate::api_server::{
         },
         collection_handlers::{
             list_collections_handler,
+            create_collection_handler,
             get_collection_schema_handler,
             update_ui_metadata_handler,
+            add_schema_field_handler,
+            update_schema_field_handler,
+            remove_schema_field_handler,
+            schema_violations_handler,
+            infer_collection_schema_handler,
+            list_indexes_handler,
+            create_index_handler,
+            drop_index_handler,
+            rename_collection_handler,
+            clone_collection_handler,
+            truncate_collection_handler,
+            drop_collection_handler,
         },
         document_handlers::{
             find_documents_handler,
@@ -30,6 +44,8 @@ use crate::api_server::{
             find_archived_documents_handler,
             find_recovered_documents_handler,
             find_pinned_documents_handler,
+            get_document_handler,
+            get_document_by_key_handler,
             insert_document_handler,
             update_document_handler,
             delete_document_handler,
@@ -41,23 +57,37 @@ use crate::api_server::{
             pin_document_handler,
             unpin_document_handler,
             download_collection_csv_handler,
+            collection_totals_handler,
 
         },
         system_handlers::{
             health_check_handler,
             initialize_library_collections_handler,
+            list_schema_packs_handler,
+            apply_schema_pack_handler,
+            migration_status_handler,
+            rollback_migrations_handler,
+            list_snapshots_handler,
+            restore_snapshot_handler,
+            delete_snapshot_handler,
+            export_schemas_handler,
+            data_dictionary_handler,
         },
         csv_temp_handlers::{
             load_csv_temp,
             save_csv_temp,
+            upload_csv_temp,
+            MAX_CSV_UPLOAD_BYTES,
             delete_csv_temp,
-            validate_csv_temp_handler
+            validate_csv_temp_handler,
+            infer_csv_temp_schema_handler,
         },
         csv_download_handler::{
             download_temp_csv,
             download_temp_csv_post
         },
         csv_import_handler::import_valid_csv_data_handler,
+        event_handlers::subscribe_events_handler,
     },
 };
 use axum::extract::Request;
@@ -95,19 +125,42 @@ pub fn create_api_router() -> (Router<Arc<Mutex<ApiServerState>>>, Vec<String>)
     
     // Collection routes
     add_route!(Method::GET, "/collections", list_collections_handler);
+    add_route!(Method::POST, "/collections", create_collection_handler);
+    add_route!(Method::DELETE, "/collections/:collection_name", drop_collection_handler);
+    add_route!(Method::POST, "/collections/:collection_name/rename", rename_collection_handler);
+    add_route!(Method::POST, "/collections/:collection_name/clone", clone_collection_handler);
+    add_route!(Method::POST, "/collections/:collection_name/truncate", truncate_collection_handler);
     add_route!(Method::GET, "/collections/:collection_name/schema", get_collection_schema_handler);
     add_route!(Method::PUT, "/collections/:collection_name/ui-metadata", update_ui_metadata_handler);
+    add_route!(Method::POST, "/collections/:collection_name/schema/fields", add_schema_field_handler);
+    add_route!(Method::PUT, "/collections/:collection_name/schema/fields/:field", update_schema_field_handler);
+    add_route!(Method::DELETE, "/collections/:collection_name/schema/fields/:field", remove_schema_field_handler);
+    add_route!(Method::GET, "/collections/:collection_name/schema/violations", schema_violations_handler);
+    add_route!(Method::POST, "/collections/:collection_name/schema/violations", schema_violations_handler);
+    add_route!(Method::GET, "/collections/:collection_name/schema/infer", infer_collection_schema_handler);
+    add_route!(Method::GET, "/collections/:collection_name/indexes", list_indexes_handler);
+    add_route!(Method::POST, "/collections/:collection_name/indexes", create_index_handler);
+    add_route!(Method::DELETE, "/collections/:collection_name/indexes/:index_name", drop_index_handler);
     
     // route for temp sqlite3 csv temporary storage
     add_route!(Method::POST, "/api/csv-temp/:collection", save_csv_temp);
+    // Raw CSV files, parsed and split on the server; too big for the default body limit
+    router = router.route(
+        "/api/csv-temp/:collection/upload",
+        post(upload_csv_temp).layer(DefaultBodyLimit::max(MAX_CSV_UPLOAD_BYTES)),
+    );
+    routes.push(format!("{} {}", Method::POST, "/api/csv-temp/:collection/upload"));
     add_route!(Method::GET, "/api/csv-temp/:collection", load_csv_temp);
     add_route!(Method::DELETE, "/api/csv-temp/:collection", delete_csv_temp);
+    add_route!(Method::GET, "/api/csv-temp/:collection/infer-schema", infer_csv_temp_schema_handler);
 
     add_route!(Method::POST, "/api/csv-validate/:collection", validate_csv_temp_handler);
 
     // Download csv from sqlite routes
     add_route!(Method::GET, "/api/csv-temp/:collection/download-csv", download_temp_csv);
     add_route!(Method::POST, "/api/csv-temp/:collection/download-csv", download_temp_csv_post);
+    add_route!(Method::GET, "/api/csv-temp/:collection/export", download_temp_csv);
+    add_route!(Method::POST, "/api/csv-temp/:collection/export", download_temp_csv_post);
 
 
     // Import validated CSV into MongoDB
@@ -129,6 +182,8 @@ pub fn create_api_router() -> (Router<Arc<Mutex<ApiServerState>>>, Vec<String>)
     add_route!(Method::GET, "/collections/:collection_name/recoveries", find_recovered_documents_handler);
     add_route!(Method::GET, "/collections/:collection_name/pins", find_pinned_documents_handler);
     add_route!(Method::POST, "/collections/:collection_name/documents", insert_document_handler);
+    add_route!(Method::GET, "/collections/:collection_name/documents/:id", get_document_handler);
+    add_route!(Method::GET, "/collections/:collection_name/by-key/:value", get_document_by_key_handler);
     add_route!(Method::PUT, "/collections/:collection_name/documents/:id", update_document_handler);
     add_route!(Method::DELETE, "/collections/:collection_name/documents/:id", delete_document_handler);
     add_route!(
@@ -152,6 +207,8 @@ pub fn create_api_router() -> (Router<Arc<Mutex<ApiServerState>>>, Vec<String>)
     add_route!(Method::PUT, "/collections/:collection_name/documents/:id/unpin", unpin_document_handler);
 
     add_route!(Method::GET, "/collections/:collection_name/download-csv", download_collection_csv_handler);
+    add_route!(Method::GET, "/collections/:collection_name/export", download_collection_csv_handler);
+    add_route!(Method::GET, "/collections/:collection_name/totals", collection_totals_handler);
     
     // Auth routes
     add_route!(Method::POST, "/api/auth/login", auth_login_handler);
@@ -161,6 +218,18 @@ pub fn create_api_router() -> (Router<Arc<Mutex<ApiServerState>>>, Vec<String>)
     // System routes
     add_route!(Method::POST, "/api/initialize-library-collections", initialize_library_collections_handler);
     add_route!(Method::GET, "/api/health", health_check_handler);
+    add_route!(Method::GET, "/api/schema-packs", list_schema_packs_handler);
+    add_route!(Method::POST, "/api/schema-packs/:name/apply", apply_schema_pack_handler);
+    add_route!(Method::GET, "/api/migrations", migration_status_handler);
+    add_route!(Method::POST, "/api/migrations/rollback", rollback_migrations_handler);
+    add_route!(Method::GET, "/api/snapshots", list_snapshots_handler);
+    add_route!(Method::POST, "/api/snapshots/:name/restore", restore_snapshot_handler);
+    add_route!(Method::DELETE, "/api/snapshots/:name", delete_snapshot_handler);
+    add_route!(Method::GET, "/api/schemas/export", export_schemas_handler);
+    add_route!(Method::GET, "/api/schemas/dictionary", data_dictionary_handler);
+
+    // Live change notifications (Server-Sent Events)
+    add_route!(Method::GET, "/api/events", subscribe_events_handler);
     
     // Print startup routes information
     println!("Available routes:");
@@ -0,0 +1,587 @@
+// src/api_server/services/coercion_service.rs
+
+use mongodb::bson::{oid::ObjectId, Bson, Decimal128, Document};
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::api_server::services::schema_cache::SchemaCache;
+use crate::timezone::InstitutionTimezone;
+
+// A field that does not satisfy its schema: a value that could not be converted
+// to its bsonType, or one the validator rejects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    // Dotted path of the value, e.g. "authors.0.name"
+    pub field: String,
+    // The schema keyword that failed: bsonType, required, enum, pattern, ...
+    pub rule: String,
+    // What the schema asks for, e.g. "int", "string | null" or "one of: a, b"
+    pub expected: String,
+    pub message: String,
+}
+
+// One line per field, for callers that report errors as a single string
+pub fn describe_errors(errors: &[FieldError]) -> String {
+    errors.iter()
+        .map(|e| if e.field.is_empty() {
+            e.message.clone()
+        } else {
+            format!("Field '{}': {}", e.field, e.message)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// Coerce a document about to be written to `collection_name` to the collection's schema.
+// Collections without a schema are written as-is.
+pub async fn coerce_for_collection(
+    schema_cache: &SchemaCache,
+    db: &Database,
+    collection_name: &str,
+    doc: &mut Document,
+    timezone: &InstitutionTimezone,
+) -> Result<(), Vec<FieldError>> {
+    let schema = schema_cache.schema(db, collection_name).await.unwrap_or_default();
+    coerce_document(doc, &schema, timezone)
+}
+
+// Convert every field of `doc` that the schema describes to its bsonType, in place.
+// Keys may be dotted paths (as in a $set), which are resolved through nested
+// `properties` and array `items`. Fields the schema doesn't mention are left alone.
+pub fn coerce_document(
+    doc: &mut Document,
+    schema: &Document,
+    timezone: &InstitutionTimezone,
+) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+
+    for (key, value) in doc.iter_mut() {
+        if let Some(spec) = spec_for_path(schema, key) {
+            let current = std::mem::replace(value, Bson::Null);
+            *value = coerce_value(current, spec, key, timezone, &mut errors);
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// Coerce a single value against its field spec
+pub fn coerce_field(
+    value: Bson,
+    spec: &Document,
+    field: &str,
+    timezone: &InstitutionTimezone,
+) -> Result<Bson, Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let value = coerce_value(value, spec, field, timezone, &mut errors);
+    if errors.is_empty() { Ok(value) } else { Err(errors) }
+}
+
+// Coerce text from a CSV cell; an empty cell is a missing value
+pub fn coerce_text(
+    value: &str,
+    spec: &Document,
+    field: &str,
+    timezone: &InstitutionTimezone,
+) -> Result<Bson, Vec<FieldError>> {
+    if value.is_empty() {
+        return Ok(Bson::Null);
+    }
+    coerce_field(Bson::String(value.to_string()), spec, field, timezone)
+}
+
+// Build a document from one staged CSV row of (column, cell) pairs. Each cell is
+// coerced to its column's spec; columns the schema doesn't describe stay text.
+// Dotted columns ("address.city", "tags.0") rebuild nested values, and empty ones
+// are left out rather than creating empty objects.
+pub fn document_from_row(
+    cells: &[(String, String)],
+    schema: &Document,
+    timezone: &InstitutionTimezone,
+) -> Result<Document, Vec<FieldError>> {
+    let mut doc = Document::new();
+    let mut errors = Vec::new();
+
+    for (column, cell) in cells {
+        let spec = spec_for_path(schema, column).cloned().unwrap_or_default();
+        match coerce_text(cell, &spec, column, timezone) {
+            Ok(Bson::Null) if column.contains('.') => {},
+            Ok(value) if column.contains('.') => insert_path(&mut doc, column, value),
+            Ok(value) => { doc.insert(column.as_str(), value); },
+            Err(field_errors) => errors.extend(field_errors),
+        }
+    }
+
+    if errors.is_empty() { Ok(doc) } else { Err(errors) }
+}
+
+// Set a dotted path in a document, creating the objects and arrays along it.
+// Numeric segments index arrays; skipped positions are filled with null.
+pub fn insert_path(doc: &mut Document, path: &str, value: Bson) {
+    let segments: Vec<&str> = path.split('.').collect();
+    let mut root = Bson::Document(std::mem::take(doc));
+    insert_segments(&mut root, &segments, value);
+    if let Bson::Document(rebuilt) = root {
+        *doc = rebuilt;
+    }
+}
+
+fn insert_segments(target: &mut Bson, segments: &[&str], value: Bson) {
+    let Some((segment, rest)) = segments.split_first() else { return };
+    let container = |next: Option<&&str>| match next {
+        Some(s) if s.parse::<usize>().is_ok() => Bson::Array(Vec::new()),
+        Some(_) => Bson::Document(Document::new()),
+        None => Bson::Null,
+    };
+
+    let slot = match target {
+        Bson::Document(d) => {
+            if !d.contains_key(*segment) {
+                d.insert(*segment, container(rest.first()));
+            }
+            d.get_mut(*segment)
+        },
+        Bson::Array(items) => match segment.parse::<usize>() {
+            Ok(index) => {
+                while items.len() <= index {
+                    items.push(Bson::Null);
+                }
+                if matches!(items[index], Bson::Null) {
+                    items[index] = container(rest.first());
+                }
+                items.get_mut(index)
+            },
+            Err(_) => None,
+        },
+        // A scalar already sits on this path; keep it
+        _ => None,
+    };
+
+    if let Some(slot) = slot {
+        if rest.is_empty() {
+            *slot = value;
+        } else {
+            insert_segments(slot, rest, value);
+        }
+    }
+}
+
+// Find the spec for a dotted path, e.g. "address.city" or "items.0.qty"
+pub fn spec_for_path<'a>(schema: &'a Document, path: &str) -> Option<&'a Document> {
+    let mut spec = schema;
+    for segment in path.split('.') {
+        let property = spec.get_document("properties").ok()
+            .and_then(|props| props.get_document(segment).ok());
+        spec = match property {
+            Some(property) => property,
+            // Array positions ("0", "$", "$[]") all share the items spec
+            None if segment.parse::<usize>().is_ok() || segment.starts_with('$') => {
+                spec.get_document("items").ok()?
+            },
+            None => return None,
+        };
+    }
+    Some(spec)
+}
+
+// Declared bsonType(s); a schema may list several, e.g. ["string", "null"]
+pub fn declared_types(spec: &Document) -> Vec<&str> {
+    match spec.get("bsonType") {
+        Some(Bson::String(t)) => vec![t.as_str()],
+        Some(Bson::Array(types)) => types.iter().filter_map(|t| t.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn coerce_value(
+    value: Bson,
+    spec: &Document,
+    path: &str,
+    timezone: &InstitutionTimezone,
+    errors: &mut Vec<FieldError>,
+) -> Bson {
+    let value = unwrap_extended_json(value);
+    let types = declared_types(spec);
+
+    // Empty strings mean "no value" unless the field holds text
+    let is_blank = matches!(&value, Bson::String(s) if s.trim().is_empty());
+    if is_blank && !types.is_empty() && !types.contains(&"string") {
+        return Bson::Null;
+    }
+
+    // Nulls are left to the validator
+    if types.is_empty() || matches!(value, Bson::Null) || types.iter().any(|t| has_type(&value, t)) {
+        return coerce_nested(value, spec, path, timezone, errors);
+    }
+
+    let mut message = String::new();
+    for bson_type in types.iter().filter(|t| **t != "null") {
+        match convert(&value, bson_type, timezone) {
+            Ok(converted) => return coerce_nested(converted, spec, path, timezone, errors),
+            Err(e) => message = e,
+        }
+    }
+
+    errors.push(FieldError {
+        field: path.to_string(),
+        rule: "bsonType".to_string(),
+        expected: types.join(" | "),
+        message,
+    });
+    value
+}
+
+// Recurse into object properties and array items
+fn coerce_nested(
+    value: Bson,
+    spec: &Document,
+    path: &str,
+    timezone: &InstitutionTimezone,
+    errors: &mut Vec<FieldError>,
+) -> Bson {
+    match value {
+        Bson::Document(mut doc) => {
+            if let Ok(properties) = spec.get_document("properties") {
+                for (key, child) in doc.iter_mut() {
+                    if let Ok(child_spec) = properties.get_document(key) {
+                        let current = std::mem::replace(child, Bson::Null);
+                        *child = coerce_value(current, child_spec, &format!("{}.{}", path, key), timezone, errors);
+                    }
+                }
+            }
+            Bson::Document(doc)
+        },
+        Bson::Array(items) => {
+            let item_specs: Vec<Option<&Document>> = match spec.get("items") {
+                Some(Bson::Document(item_spec)) => vec![Some(item_spec); items.len()],
+                // Tuple form: one spec per position
+                Some(Bson::Array(specs)) => (0..items.len())
+                    .map(|i| specs.get(i).and_then(|s| s.as_document()))
+                    .collect(),
+                _ => return Bson::Array(items),
+            };
+            Bson::Array(items.into_iter().zip(item_specs).enumerate()
+                .map(|(i, (item, item_spec))| match item_spec {
+                    Some(item_spec) => coerce_value(item, item_spec, &format!("{}.{}", path, i), timezone, errors),
+                    None => item,
+                })
+                .collect())
+        },
+        other => other,
+    }
+}
+
+// JSON request bodies carry dates and ids as Extended JSON wrappers like {"$date": ...}
+fn unwrap_extended_json(value: Bson) -> Bson {
+    match &value {
+        Bson::Document(doc) if doc.len() == 1 && doc.keys().all(|k| k.starts_with('$')) => {
+            match Bson::try_from(value.clone().into_relaxed_extjson()) {
+                Ok(Bson::Document(_)) | Err(_) => value,
+                Ok(unwrapped) => unwrapped,
+            }
+        },
+        _ => value,
+    }
+}
+
+pub fn has_type(value: &Bson, bson_type: &str) -> bool {
+    match bson_type {
+        "string" => matches!(value, Bson::String(_)),
+        "int" => matches!(value, Bson::Int32(_)),
+        "long" => matches!(value, Bson::Int64(_)),
+        "double" => matches!(value, Bson::Double(_)),
+        "decimal" => matches!(value, Bson::Decimal128(_)),
+        "number" => matches!(value, Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_)),
+        "bool" => matches!(value, Bson::Boolean(_)),
+        "date" => matches!(value, Bson::DateTime(_)),
+        "objectId" => matches!(value, Bson::ObjectId(_)),
+        "array" => matches!(value, Bson::Array(_)),
+        "object" => matches!(value, Bson::Document(_)),
+        "null" => matches!(value, Bson::Null),
+        "timestamp" => matches!(value, Bson::Timestamp(_)),
+        "binData" => matches!(value, Bson::Binary(_)),
+        "regex" => matches!(value, Bson::RegularExpression(_)),
+        // Types we don't convert are accepted as they come
+        _ => true,
+    }
+}
+
+fn convert(value: &Bson, bson_type: &str, timezone: &InstitutionTimezone) -> Result<Bson, String> {
+    let got = || format!("Expected {}, got {}", bson_type, describe(value));
+
+    match (bson_type, value) {
+        ("string", Bson::ObjectId(oid)) => Ok(Bson::String(oid.to_hex())),
+        ("string", Bson::DateTime(dt)) => Ok(Bson::String(timezone.format_iso(dt))),
+        ("string", Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) | Bson::Boolean(_)) => {
+            Ok(Bson::String(value.to_string()))
+        },
+
+        ("int", Bson::String(s)) => s.trim().parse::<i32>()
+            .map(Bson::Int32)
+            .map_err(|e| format!("Invalid integer '{}': {}", s, e)),
+        ("int", Bson::Int64(i)) => i32::try_from(*i)
+            .map(Bson::Int32)
+            .map_err(|_| format!("{} is out of range for int", i)),
+        ("int", Bson::Double(d)) if d.fract() == 0.0 && *d >= i32::MIN as f64 && *d <= i32::MAX as f64 => {
+            Ok(Bson::Int32(*d as i32))
+        },
+
+        ("long", Bson::String(s)) => s.trim().parse::<i64>()
+            .map(Bson::Int64)
+            .map_err(|e| format!("Invalid long integer '{}': {}", s, e)),
+        ("long", Bson::Int32(i)) => Ok(Bson::Int64(*i as i64)),
+        ("long", Bson::Double(d)) if d.fract() == 0.0 && d.abs() < 9.0e15 => Ok(Bson::Int64(*d as i64)),
+
+        ("double" | "number", Bson::String(s)) => s.trim().parse::<f64>()
+            .map(Bson::Double)
+            .map_err(|e| format!("Invalid number '{}': {}", s, e)),
+        ("double", Bson::Int32(i)) => Ok(Bson::Double(*i as f64)),
+        ("double", Bson::Int64(i)) => Ok(Bson::Double(*i as f64)),
+        ("double", Bson::Decimal128(d)) => d.to_string().parse::<f64>()
+            .map(Bson::Double)
+            .map_err(|e| format!("Invalid number '{}': {}", d, e)),
+
+        ("decimal", Bson::String(s)) => Decimal128::from_str(s.trim())
+            .map(Bson::Decimal128)
+            .map_err(|e| format!("Invalid decimal '{}': {}", s, e)),
+        ("decimal", Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_)) => Decimal128::from_str(&value.to_string())
+            .map(Bson::Decimal128)
+            .map_err(|e| format!("Invalid decimal '{}': {}", value, e)),
+
+        ("bool", Bson::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" | "t" | "y" => Ok(Bson::Boolean(true)),
+            "false" | "no" | "0" | "f" | "n" => Ok(Bson::Boolean(false)),
+            _ => Err(format!("Invalid boolean '{}'", s)),
+        },
+        ("bool", Bson::Int32(0)) | ("bool", Bson::Int64(0)) => Ok(Bson::Boolean(false)),
+        ("bool", Bson::Int32(1)) | ("bool", Bson::Int64(1)) => Ok(Bson::Boolean(true)),
+
+        ("date", Bson::String(s)) => timezone.parse_datetime(s).map(Bson::DateTime),
+        // Milliseconds since the epoch
+        ("date", Bson::Int64(millis)) => Ok(Bson::DateTime(mongodb::bson::DateTime::from_millis(*millis))),
+
+        ("objectId", Bson::String(s)) => ObjectId::parse_str(s.trim())
+            .map(Bson::ObjectId)
+            .map_err(|e| format!("Invalid ObjectId '{}': {}", s, e)),
+
+        ("array", Bson::String(s)) => match serde_json::from_str::<serde_json::Value>(s) {
+            Ok(json @ serde_json::Value::Array(_)) => Bson::try_from(json)
+                .map_err(|e| format!("Invalid array: {}", e)),
+            Ok(_) => Err(got()),
+            Err(e) => Err(format!("Invalid array: {}", e)),
+        },
+        ("object", Bson::String(s)) => match serde_json::from_str::<serde_json::Value>(s) {
+            Ok(json @ serde_json::Value::Object(_)) => Bson::try_from(json)
+                .map_err(|e| format!("Invalid object: {}", e)),
+            Ok(_) => Err(got()),
+            Err(e) => Err(format!("Invalid object: {}", e)),
+        },
+
+        _ => Err(got()),
+    }
+}
+
+// Short description of a value for error messages
+pub fn describe(value: &Bson) -> String {
+    match value {
+        Bson::String(s) => format!("string '{}'", s),
+        Bson::Int32(i) => format!("int {}", i),
+        Bson::Int64(i) => format!("long {}", i),
+        Bson::Double(d) => format!("double {}", d),
+        Bson::Boolean(b) => format!("bool {}", b),
+        Bson::Array(_) => "array".to_string(),
+        Bson::Document(_) => "object".to_string(),
+        other => format!("{:?}", other.element_type()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+    use mongodb::bson::doc;
+
+    fn utc_plus_8() -> InstitutionTimezone {
+        InstitutionTimezone::Fixed(FixedOffset::east_opt(8 * 3600).unwrap())
+    }
+
+    #[test]
+    fn coerces_text_to_the_declared_type() {
+        let oid = ObjectId::parse_str("65f0a1b2c3d4e5f601234567").unwrap();
+        let cases: Vec<(Document, &str, Bson)> = vec![
+            (doc! { "bsonType": "string" }, "42", Bson::String("42".into())),
+            (doc! { "bsonType": "string" }, " ", Bson::String(" ".into())),
+            (doc! { "bsonType": "int" }, " 42 ", Bson::Int32(42)),
+            (doc! { "bsonType": "int" }, "", Bson::Null),
+            (doc! { "bsonType": "long" }, "9000000000", Bson::Int64(9_000_000_000)),
+            (doc! { "bsonType": "double" }, "2.5", Bson::Double(2.5)),
+            (doc! { "bsonType": "number" }, "7", Bson::Double(7.0)),
+            (doc! { "bsonType": "decimal" }, "1.10", Bson::Decimal128(Decimal128::from_str("1.10").unwrap())),
+            (doc! { "bsonType": "bool" }, "Yes", Bson::Boolean(true)),
+            (doc! { "bsonType": "bool" }, "0", Bson::Boolean(false)),
+            (doc! { "bsonType": "objectId" }, "65f0a1b2c3d4e5f601234567", Bson::ObjectId(oid)),
+            (doc! { "bsonType": ["int", "null"] }, "", Bson::Null),
+            (doc! { "bsonType": ["int", "string"] }, "abc", Bson::String("abc".into())),
+            (doc! { "bsonType": "array" }, "[1, \"a\"]", Bson::Array(vec![Bson::Int32(1), Bson::String("a".into())])),
+            (doc! { "bsonType": "object" }, "{\"a\": 1}", Bson::Document(doc! { "a": 1 })),
+            // Untyped fields keep their text
+            (doc! {}, "x", Bson::String("x".into())),
+            (
+                doc! { "bsonType": "date" },
+                "2025-03-31 09:00",
+                Bson::DateTime(mongodb::bson::DateTime::parse_rfc3339_str("2025-03-31T01:00:00Z").unwrap()),
+            ),
+            (
+                doc! { "bsonType": "date" },
+                "2025-03-31T09:00:00Z",
+                Bson::DateTime(mongodb::bson::DateTime::parse_rfc3339_str("2025-03-31T09:00:00Z").unwrap()),
+            ),
+        ];
+
+        for (spec, input, expected) in cases {
+            let got = coerce_text(input, &spec, "f", &utc_plus_8());
+            assert_eq!(got.ok(), Some(expected), "{:?} as {}", input, spec);
+        }
+    }
+
+    #[test]
+    fn reports_values_that_dont_convert() {
+        let cases: Vec<(Document, &str, &str)> = vec![
+            (doc! { "bsonType": "int" }, "abc", "int"),
+            (doc! { "bsonType": "int" }, "3000000000", "int"),
+            (doc! { "bsonType": "bool" }, "maybe", "bool"),
+            (doc! { "bsonType": "objectId" }, "nope", "objectId"),
+            (doc! { "bsonType": "date" }, "31/03/2025", "date"),
+            (doc! { "bsonType": "array" }, "{\"a\": 1}", "array"),
+            (doc! { "bsonType": ["int", "null"] }, "x", "int | null"),
+        ];
+
+        for (spec, input, expected) in cases {
+            let errors = coerce_text(input, &spec, "f", &InstitutionTimezone::Local).unwrap_err();
+            assert_eq!(errors.len(), 1, "{:?} as {}", input, spec);
+            assert_eq!(errors[0].field, "f");
+            assert_eq!(errors[0].rule, "bsonType");
+            assert_eq!(errors[0].expected, expected);
+        }
+    }
+
+    #[test]
+    fn coerces_values_already_in_json() {
+        let cases: Vec<(Document, Bson, Bson)> = vec![
+            (doc! { "bsonType": "int" }, Bson::Int64(5), Bson::Int32(5)),
+            (doc! { "bsonType": "int" }, Bson::Double(5.0), Bson::Int32(5)),
+            (doc! { "bsonType": "long" }, Bson::Int32(5), Bson::Int64(5)),
+            (doc! { "bsonType": "double" }, Bson::Int32(5), Bson::Double(5.0)),
+            (doc! { "bsonType": "string" }, Bson::Int32(5), Bson::String("5".into())),
+            (doc! { "bsonType": "bool" }, Bson::Int32(1), Bson::Boolean(true)),
+            (doc! { "bsonType": "date" }, Bson::Int64(0), Bson::DateTime(mongodb::bson::DateTime::from_millis(0))),
+            (doc! { "bsonType": "date" }, Bson::Document(doc! { "$date": "1970-01-01T00:00:00Z" }), Bson::DateTime(mongodb::bson::DateTime::from_millis(0))),
+            // Nulls are left to the validator
+            (doc! { "bsonType": "int" }, Bson::Null, Bson::Null),
+        ];
+
+        for (spec, input, expected) in cases {
+            let got = coerce_field(input.clone(), &spec, "f", &InstitutionTimezone::Local);
+            assert_eq!(got.ok(), Some(expected), "{:?} as {}", input, spec);
+        }
+    }
+
+    #[test]
+    fn coerces_nested_paths_and_items() {
+        let schema = doc! {
+            "properties": {
+                "pages": { "bsonType": "int" },
+                "address": { "bsonType": "object", "properties": { "zip": { "bsonType": "int" } } },
+                "scores": { "bsonType": "array", "items": { "bsonType": "double" } },
+            }
+        };
+        let mut update = doc! {
+            "pages": "12",
+            "address.zip": "1000",
+            "scores.1": "2",
+            "address": { "zip": "2000", "city": "Oslo" },
+            "scores": ["1", "2.5"],
+            "unknown": "stays",
+        };
+        coerce_document(&mut update, &schema, &InstitutionTimezone::Local).unwrap();
+        assert_eq!(update, doc! {
+            "pages": 12,
+            "address.zip": 1000,
+            "scores.1": 2.0,
+            "address": { "zip": 2000, "city": "Oslo" },
+            "scores": [1.0, 2.5],
+            "unknown": "stays",
+        });
+
+        let mut bad = doc! { "scores": ["1", "x"] };
+        let errors = coerce_document(&mut bad, &schema, &InstitutionTimezone::Local).unwrap_err();
+        assert_eq!(errors.iter().map(|e| e.field.as_str()).collect::<Vec<_>>(), ["scores.1"]);
+    }
+
+    #[test]
+    fn inserts_dotted_paths() {
+        let cases: Vec<(Document, &str, Bson, Document)> = vec![
+            (doc! {}, "a", Bson::Int32(1), doc! { "a": 1 }),
+            (doc! {}, "a.b", Bson::Int32(1), doc! { "a": { "b": 1 } }),
+            (doc! { "a": { "c": 2 } }, "a.b", Bson::Int32(1), doc! { "a": { "c": 2, "b": 1 } }),
+            (doc! {}, "tags.0", Bson::String("x".into()), doc! { "tags": ["x"] }),
+            (doc! {}, "tags.2", Bson::String("x".into()), doc! { "tags": [Bson::Null, Bson::Null, "x"] }),
+            (doc! {}, "authors.1.name", Bson::String("Ann".into()), doc! { "authors": [Bson::Null, { "name": "Ann" }] }),
+            (doc! { "tags": ["a"] }, "tags.0", Bson::String("b".into()), doc! { "tags": ["b"] }),
+            // A scalar on the way is kept
+            (doc! { "a": 5 }, "a.b", Bson::Int32(1), doc! { "a": 5 }),
+            // Non-numeric segments don't index arrays
+            (doc! { "tags": [] }, "tags.x", Bson::Int32(1), doc! { "tags": [] }),
+        ];
+
+        for (mut doc, path, value, expected) in cases {
+            insert_path(&mut doc, path, value);
+            assert_eq!(doc, expected, "path {}", path);
+        }
+    }
+
+    #[test]
+    fn builds_documents_from_rows() {
+        let schema = doc! {
+            "properties": {
+                "pages": { "bsonType": "int" },
+                "address": { "bsonType": "object", "properties": { "zip": { "bsonType": "int" } } },
+            }
+        };
+        let row = |cells: &[(&str, &str)]| -> Vec<(String, String)> {
+            cells.iter().map(|(c, v)| (c.to_string(), v.to_string())).collect()
+        };
+
+        let doc = document_from_row(&row(&[("pages", "3"), ("address.zip", "1000"), ("note", "")]), &schema, &InstitutionTimezone::Local);
+        assert_eq!(doc.unwrap(), doc! { "pages": 3, "address": { "zip": 1000 }, "note": Bson::Null });
+
+        // Empty dotted cells don't create empty objects
+        let doc = document_from_row(&row(&[("address.zip", "")]), &schema, &InstitutionTimezone::Local);
+        assert_eq!(doc.unwrap(), doc! {});
+
+        let errors = document_from_row(&row(&[("pages", "x"), ("address.zip", "y")]), &schema, &InstitutionTimezone::Local).unwrap_err();
+        assert_eq!(errors.iter().map(|e| e.field.as_str()).collect::<Vec<_>>(), ["pages", "address.zip"]);
+    }
+
+    #[test]
+    fn finds_specs_for_paths() {
+        let schema = doc! {
+            "properties": {
+                "authors": { "bsonType": "array", "items": { "properties": { "name": { "bsonType": "string" } } } },
+            }
+        };
+        let cases = [
+            ("authors", Some("array")),
+            ("authors.0.name", Some("string")),
+            ("authors.$.name", Some("string")),
+            ("authors.$[].name", Some("string")),
+            ("authors.x", None),
+            ("missing", None),
+        ];
+        for (path, expected) in cases {
+            let found = spec_for_path(&schema, path).map(|spec| spec.get_str("bsonType").unwrap_or_default());
+            assert_eq!(found, expected, "path {}", path);
+        }
+    }
+}
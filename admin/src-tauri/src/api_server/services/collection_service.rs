@@ -0,0 +1,349 @@
+// src/api_server/services/collection_service.rs
+
+use axum::http::StatusCode;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::IndexOptions;
+use mongodb::{Database, IndexModel};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::api_server::services::defaults_service::{check_defaults, save_defaults, take_defaults, FieldDefaults};
+use crate::api_server::services::schema_service::fetch_collection_schema;
+use crate::mongodb_schema::{
+    create_archive_index, create_pinned_index, merge_with_archive_pinned_and_row_height_properties,
+    DEFAULT_COLUMN_WIDTH,
+};
+
+// History logs are shown in their own dialogs, not as table columns
+const NON_COLUMN_FIELDS: [&str; 2] = ["archive_history", "pinned_history"];
+
+// Declarative description of a collection: its schema, indexes and default table view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionDefinition {
+    pub name: String,
+    // $jsonSchema properties, keyed by field name
+    pub properties: Document,
+    #[serde(default)]
+    pub required: Vec<String>,
+    #[serde(default)]
+    pub indexes: Vec<IndexDefinition>,
+    // Mix in is_archive/archive_history, pinned_by/pinned_history and row_height,
+    // with their indexes, like the built-in collections
+    #[serde(default = "default_true")]
+    pub standard_fields: bool,
+    #[serde(default = "default_validation_level")]
+    pub validation_level: String,
+    #[serde(default = "default_validation_action")]
+    pub validation_action: String,
+    #[serde(default)]
+    pub sort: Option<SortDefinition>,
+    // Table columns in display order; defaults to every property
+    #[serde(default)]
+    pub columns: Option<Vec<String>>,
+    // Widths for the table columns; columns not listed get the default width
+    #[serde(default)]
+    pub column_widths: HashMap<String, i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexDefinition {
+    // Fields in key order; a leading '-' makes the field descending, e.g. ["school_id", "-time_in_date"]
+    pub fields: Vec<String>,
+    #[serde(default)]
+    pub unique: bool,
+    #[serde(default)]
+    pub name: Option<String>,
+    // Full-text index over every listed field instead of an ordered one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub text: bool,
+    // TTL: documents are removed this long after the (single, date) field's value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_after_seconds: Option<u64>,
+    // Only documents matching this filter are indexed (and checked for uniqueness)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_filter: Option<Document>,
+}
+
+impl IndexDefinition {
+    pub fn check(&self) -> Result<(), String> {
+        if self.fields.is_empty() {
+            return Err("An index needs at least one field".to_string());
+        }
+        if let Some(field) = self.fields.iter().find(|f| f.trim_start_matches('-').is_empty()) {
+            return Err(format!("Invalid index field '{}'", field));
+        }
+        if self.text && self.unique {
+            return Err("A text index can't be unique".to_string());
+        }
+        if self.text && self.fields.iter().any(|f| f.starts_with('-')) {
+            return Err("Text index fields have no direction".to_string());
+        }
+        if self.expire_after_seconds.is_some() && (self.fields.len() != 1 || self.text) {
+            return Err("A TTL index must be on a single date field".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn model(&self) -> IndexModel {
+        let mut keys = Document::new();
+        for field in &self.fields {
+            match field.strip_prefix('-') {
+                Some(descending) => keys.insert(descending, -1),
+                None if self.text => keys.insert(field.as_str(), "text"),
+                None => keys.insert(field.as_str(), 1),
+            };
+        }
+        let mut options = IndexOptions::default();
+        options.unique = self.unique.then_some(true);
+        options.name = self.name.clone();
+        options.expire_after = self.expire_after_seconds.map(Duration::from_secs);
+        options.partial_filter_expression = self.partial_filter.clone();
+        IndexModel::builder().keys(keys).options(options).build()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SortDefinition {
+    pub field: String,
+    #[serde(default = "default_direction")]
+    pub direction: String,
+}
+
+fn default_true() -> bool { true }
+fn default_validation_level() -> String { "moderate".to_string() }
+fn default_validation_action() -> String { "error".to_string() }
+fn default_direction() -> String { "asc".to_string() }
+
+// Names MongoDB accepts and that aren't reserved for the server
+pub fn check_collection_name(name: &str) -> Result<(), String> {
+    let trimmed = name.trim();
+    if trimmed.is_empty() || trimmed != name || name.contains('$') || name.contains('\0') || name.starts_with("system.") {
+        return Err(format!("Invalid collection name '{}'", name));
+    }
+    Ok(())
+}
+
+impl CollectionDefinition {
+    // Catch mistakes before anything is written
+    pub fn check(&self) -> Result<(), String> {
+        check_collection_name(&self.name)?;
+        for (field, spec) in &self.properties {
+            let spec = spec.as_document()
+                .ok_or_else(|| format!("Property '{}' must be an object", field))?;
+            if !spec.contains_key("bsonType") && !spec.contains_key("enum") {
+                return Err(format!("Property '{}' needs a bsonType", field));
+            }
+        }
+
+        let properties = self.all_properties();
+        let known = |field: &str| properties.contains_key(field.split('.').next().unwrap_or(field)) || field == "_id";
+        if let Some(field) = self.required.iter().find(|f| !known(f)) {
+            return Err(format!("Required field '{}' is not a property", field));
+        }
+        for index in &self.indexes {
+            index.check()?;
+            if let Some(field) = index.fields.iter().map(|f| f.trim_start_matches('-')).find(|f| !known(f)) {
+                return Err(format!("Index field '{}' is not a property", field));
+            }
+        }
+        if let Some(field) = self.columns.iter().flatten().find(|f| !known(f)) {
+            return Err(format!("Column '{}' is not a property", field));
+        }
+        if let Some(sort) = &self.sort {
+            if !known(&sort.field) {
+                return Err(format!("Sort field '{}' is not a property", sort.field));
+            }
+            if sort.direction != "asc" && sort.direction != "desc" {
+                return Err(format!("Sort direction must be asc or desc, not '{}'", sort.direction));
+            }
+        }
+        check_defaults(&self.json_schema(), self.defaults())?;
+        if !["off", "moderate", "strict"].contains(&self.validation_level.as_str()) {
+            return Err(format!("Invalid validation level '{}'", self.validation_level));
+        }
+        if !["error", "warn"].contains(&self.validation_action.as_str()) {
+            return Err(format!("Invalid validation action '{}'", self.validation_action));
+        }
+        Ok(())
+    }
+
+    // Declared properties plus the standard ones, if mixed in
+    pub fn all_properties(&self) -> Document {
+        if self.standard_fields {
+            merge_with_archive_pinned_and_row_height_properties(self.properties.clone())
+        } else {
+            self.properties.clone()
+        }
+    }
+
+    // The validator's $jsonSchema; property `default`s are kept apart, see defaults()
+    pub fn json_schema(&self) -> Document {
+        let mut schema = self.annotated_schema();
+        take_defaults(&mut schema);
+        schema
+    }
+
+    // Default values declared on the properties, by field path
+    pub fn defaults(&self) -> FieldDefaults {
+        take_defaults(&mut self.annotated_schema())
+    }
+
+    fn annotated_schema(&self) -> Document {
+        let mut schema = doc! { "bsonType": "object" };
+        if !self.required.is_empty() {
+            schema.insert("required", self.required.clone());
+        }
+        schema.insert("properties", self.all_properties());
+        schema
+    }
+
+    pub fn index_models(&self) -> Vec<IndexModel> {
+        let mut models: Vec<IndexModel> = self.indexes.iter().map(IndexDefinition::model).collect();
+
+        if self.standard_fields {
+            models.push(create_archive_index());
+            models.push(create_pinned_index());
+        }
+        models
+    }
+
+    // Global ui_metadata entry in the same shape as the built-in collections'
+    pub fn ui_settings(&self) -> Document {
+        let columns = match &self.columns {
+            Some(columns) => columns.clone(),
+            None => self.all_properties().keys()
+                .filter(|f| !NON_COLUMN_FIELDS.contains(&f.as_str()))
+                .cloned()
+                .collect(),
+        };
+        let mut column_widths = Document::new();
+        for field in columns {
+            let width = self.column_widths.get(&field).copied().unwrap_or(DEFAULT_COLUMN_WIDTH);
+            column_widths.insert(field, width);
+        }
+        let column_order: Vec<String> = column_widths.keys().cloned().collect();
+        let short_names: Document = column_order.iter()
+            .map(|field| (field.clone(), Bson::String(field.clone())))
+            .collect();
+        let (sort_field, sort_direction) = match &self.sort {
+            Some(sort) => (sort.field.as_str(), sort.direction.as_str()),
+            None => ("created_at", "asc"),
+        };
+
+        let now = mongodb::bson::DateTime::now();
+        doc! {
+            "collection": self.name.as_str(),
+            "ui": {
+                "columnWidths": column_widths,
+                "columnOrder": column_order,
+                "hiddenColumns": [],
+                "sortSettings": {
+                    "field": sort_field,
+                    "direction": sort_direction
+                },
+                "filterSettings": {},
+                "short_names": short_names
+            },
+            "created_at": now,
+            "updated_at": now
+        }
+    }
+}
+
+// Create a new collection with its validator, indexes and default ui_metadata.
+// Nothing is left behind if a step fails.
+pub async fn create_collection(db: &Database, definition: &CollectionDefinition) -> Result<Document, (StatusCode, String)> {
+    definition.check().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let name = definition.name.as_str();
+
+    let existing = db.list_collection_names(doc! { "name": name })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !existing.is_empty() {
+        return Err((StatusCode::CONFLICT, format!("Collection '{}' already exists", name)));
+    }
+
+    let schema = definition.json_schema();
+    db.run_command(
+        doc! {
+            "create": name,
+            "validator": { "$jsonSchema": schema.clone() },
+            "validationLevel": definition.validation_level.as_str(),
+            "validationAction": definition.validation_action.as_str()
+        },
+        None
+    )
+    .await
+    .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to create collection: {}", e)))?;
+
+    if let Err(e) = finish_collection(db, name, definition).await {
+        // Roll back so the definition can be fixed and sent again
+        let _ = db.collection::<Document>(name).drop(None).await;
+        let _ = db.collection::<Document>("ui_metadata")
+            .delete_many(doc! { "collection": name }, None)
+            .await;
+        return Err((StatusCode::BAD_REQUEST, e));
+    }
+    Ok(schema)
+}
+
+// Create a collection from its definition when it doesn't exist yet. An existing
+// collection keeps its validator and defaults, which the schema editor may have
+// changed since; only missing indexes and ui_metadata are added. Returns whether
+// the existing validator differs from the definition.
+pub async fn ensure_collection(db: &Database, definition: &CollectionDefinition) -> Result<bool, String> {
+    definition.check()?;
+    let name = definition.name.as_str();
+
+    let existing = db.list_collection_names(doc! { "name": name })
+        .await
+        .map_err(|e| format!("Failed to list collections: {}", e))?;
+    if existing.is_empty() {
+        db.run_command(
+            doc! {
+                "create": name,
+                "validator": { "$jsonSchema": definition.json_schema() },
+                "validationLevel": definition.validation_level.as_str(),
+                "validationAction": definition.validation_action.as_str()
+            },
+            None
+        )
+        .await
+        .map_err(|e| format!("Failed to create '{}': {}", name, e))?;
+        finish_collection(db, name, definition).await?;
+        return Ok(false);
+    }
+
+    add_indexes_and_metadata(db, name, definition).await?;
+    let current = fetch_collection_schema(db, name).await?.ok();
+    Ok(current.as_ref() != Some(&definition.json_schema()))
+}
+
+async fn finish_collection(db: &Database, name: &str, definition: &CollectionDefinition) -> Result<(), String> {
+    add_indexes_and_metadata(db, name, definition).await?;
+    let schema = definition.json_schema();
+    let defaults = check_defaults(&schema, definition.defaults())?;
+    save_defaults(db, name, &defaults).await
+}
+
+// Indexes that already exist are left as they are, and so is an existing ui_metadata entry
+async fn add_indexes_and_metadata(db: &Database, name: &str, definition: &CollectionDefinition) -> Result<(), String> {
+    let indexes = definition.index_models();
+    if !indexes.is_empty() {
+        db.collection::<Document>(name)
+            .create_indexes(indexes, None)
+            .await
+            .map_err(|e| format!("Failed to create indexes: {}", e))?;
+    }
+
+    db.collection::<Document>("ui_metadata")
+        .update_one(
+            doc! { "collection": name, "user_id": { "$exists": false } },
+            doc! { "$setOnInsert": definition.ui_settings() },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to create UI metadata: {}", e))
+}
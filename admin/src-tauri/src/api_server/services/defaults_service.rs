@@ -0,0 +1,313 @@
+// src/api_server/services/defaults_service.rs
+
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::UpdateOptions;
+use mongodb::Database;
+
+use crate::api_server::services::coercion_service::{coerce_field, describe_errors, spec_for_path};
+use crate::api_server::services::validation_service::validate_document;
+use crate::mongodb_schema::standard_field_defaults;
+use crate::timezone::InstitutionTimezone;
+
+// Fields only the server writes: the timestamps, and the logs kept by the archive
+// and pin endpoints. Values sent by clients are dropped.
+pub const SERVER_MANAGED_FIELDS: [&str; 4] = ["created_at", "updated_at", "archive_history", "pinned_history"];
+
+// $jsonSchema has no `default` keyword, so defaults are kept in the collection's
+// global ui_metadata entry as [{ field, value }]. Fields inside array items are
+// addressed with "$", e.g. "authors.$.role".
+const DEFAULTS_KEY: &str = "defaults";
+
+// Default values by dotted field path
+pub type FieldDefaults = Vec<(String, Bson)>;
+
+pub fn is_server_managed(field: &str) -> bool {
+    SERVER_MANAGED_FIELDS.contains(&field.split('.').next().unwrap_or(field))
+}
+
+// Drop server-managed fields (and paths into them) from a document or $set sent by a client
+pub fn strip_server_managed(doc: &mut Document) {
+    let managed: Vec<String> = doc.keys().filter(|key| is_server_managed(key)).cloned().collect();
+    for key in managed {
+        doc.remove(&key);
+    }
+}
+
+// Remove `default` from every property spec of a schema, nested properties and
+// array items included, and return them by path
+pub fn take_defaults(schema: &mut Document) -> FieldDefaults {
+    let mut defaults = Vec::new();
+    take_spec_defaults(schema, "", &mut defaults);
+    defaults
+}
+
+fn take_spec_defaults(spec: &mut Document, path: &str, defaults: &mut FieldDefaults) {
+    if let Ok(properties) = spec.get_document_mut("properties") {
+        for (key, property) in properties.iter_mut() {
+            let Bson::Document(property) = property else { continue };
+            let property_path = join_path(path, key);
+            if let Some(default) = property.remove("default") {
+                defaults.push((property_path.clone(), default));
+            }
+            take_spec_defaults(property, &property_path, defaults);
+        }
+    }
+    if let Ok(items) = spec.get_document_mut("items") {
+        take_spec_defaults(items, &join_path(path, "$"), defaults);
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+// Put defaults back on their property specs, for schemas shown to clients
+pub fn annotate_defaults(schema: &mut Document, defaults: &[(String, Bson)]) {
+    for (field, value) in defaults {
+        if let Some(spec) = spec_for_path_mut(schema, field) {
+            spec.insert("default", value.clone());
+        }
+    }
+}
+
+fn spec_for_path_mut<'a>(schema: &'a mut Document, path: &str) -> Option<&'a mut Document> {
+    let mut spec = schema;
+    for segment in path.split('.') {
+        spec = if segment == "$" {
+            spec.get_document_mut("items").ok()?
+        } else {
+            spec.get_document_mut("properties").ok()?.get_document_mut(segment).ok()?
+        };
+    }
+    Some(spec)
+}
+
+// Defaults that apply to inserts: the stored ones, then those of the standard
+// archive/pin/row height fields the schema declares
+pub fn effective_defaults(schema: &Document, stored: FieldDefaults) -> FieldDefaults {
+    let mut defaults = stored;
+    for (field, value) in standard_field_defaults() {
+        if spec_for_path(schema, &field).is_some() && !defaults.iter().any(|(f, _)| *f == field) {
+            defaults.push((field, value));
+        }
+    }
+    defaults
+}
+
+// Fill in missing fields from their defaults. Only absent fields are filled, so an
+// explicit null is kept; nested defaults apply inside the objects and array items
+// the document has.
+pub fn apply_defaults(doc: &mut Document, defaults: &[(String, Bson)]) {
+    for (field, value) in defaults {
+        let segments: Vec<&str> = field.split('.').collect();
+        apply_segments(doc, &segments, value);
+    }
+}
+
+fn apply_segments(doc: &mut Document, segments: &[&str], value: &Bson) {
+    match segments {
+        [] => {},
+        [leaf] => {
+            if !doc.contains_key(*leaf) {
+                doc.insert(*leaf, value.clone());
+            }
+        },
+        [segment, "$", rest @ ..] => {
+            if let Ok(items) = doc.get_array_mut(*segment) {
+                for item in items.iter_mut() {
+                    if let Bson::Document(item) = item {
+                        apply_segments(item, rest, value);
+                    }
+                }
+            }
+        },
+        [segment, rest @ ..] => {
+            if let Ok(inner) = doc.get_document_mut(*segment) {
+                apply_segments(inner, rest, value);
+            }
+        },
+    }
+}
+
+// Convert a default to its property's type and check it against the property's rules,
+// so a bad default is refused when it's defined rather than on every insert
+pub fn check_default(field: &str, value: Bson, spec: &Document) -> Result<Bson, String> {
+    if is_server_managed(field) {
+        return Err(format!("Field '{}' is set by the server and can't have a default", field));
+    }
+    let value = coerce_field(value, spec, field, &InstitutionTimezone::from_env())
+        .map_err(|errors| format!("Invalid default: {}", describe_errors(&errors)))?;
+    let leaf = field.rsplit('.').next().unwrap_or(field);
+    let errors = validate_document(
+        &doc! { leaf: value.clone() },
+        &doc! { "properties": { leaf: spec.clone() } },
+    );
+    if !errors.is_empty() {
+        return Err(format!("Invalid default: {}", describe_errors(&errors)));
+    }
+    Ok(value)
+}
+
+// Check every default against its spec in `schema`, converting it to the spec's type
+pub fn check_defaults(schema: &Document, defaults: FieldDefaults) -> Result<FieldDefaults, String> {
+    defaults.into_iter()
+        .map(|(field, value)| {
+            let spec = spec_for_path(schema, &field)
+                .ok_or_else(|| format!("Default for '{}', which is not a property", field))?;
+            check_default(&field, value, spec).map(|value| (field, value))
+        })
+        .collect()
+}
+
+// Defaults stored on a global ui_metadata entry
+pub fn defaults_from_entry(entry: &Document) -> FieldDefaults {
+    entry.get_array(DEFAULTS_KEY)
+        .map(|list| list.iter()
+            .filter_map(Bson::as_document)
+            .filter_map(|d| Some((d.get_str("field").ok()?.to_string(), d.get("value")?.clone())))
+            .collect())
+        .unwrap_or_default()
+}
+
+pub async fn stored_defaults(db: &Database, collection_name: &str) -> Result<FieldDefaults, String> {
+    let entry = db.collection::<Document>("ui_metadata")
+        .find_one(doc! { "collection": collection_name, "user_id": { "$exists": false } }, None)
+        .await
+        .map_err(|e| format!("Failed to load defaults: {}", e))?;
+    Ok(entry.as_ref().map(defaults_from_entry).unwrap_or_default())
+}
+
+pub async fn save_defaults(db: &Database, collection_name: &str, defaults: &[(String, Bson)]) -> Result<(), String> {
+    let list: Vec<Document> = defaults.iter()
+        .map(|(field, value)| doc! { "field": field.as_str(), "value": value.clone() })
+        .collect();
+    let now = mongodb::bson::DateTime::now();
+    db.collection::<Document>("ui_metadata")
+        .update_one(
+            doc! { "collection": collection_name, "user_id": { "$exists": false } },
+            doc! {
+                "$set": { DEFAULTS_KEY: list, "updated_at": now },
+                "$setOnInsert": { "ui": {}, "created_at": now }
+            },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to save defaults: {}", e))
+}
+
+// Set (Some) or clear (None) one field's default
+pub async fn set_default(db: &Database, collection_name: &str, field: &str, value: Option<Bson>) -> Result<(), String> {
+    let mut defaults = stored_defaults(db, collection_name).await?;
+    defaults.retain(|(f, _)| f != field);
+    if let Some(value) = value {
+        defaults.push((field.to_string(), value));
+    }
+    save_defaults(db, collection_name, &defaults).await
+}
+
+// Move the defaults of a field, and of the fields inside it, to a new path;
+// with `to` as None they are removed
+pub async fn move_defaults(db: &Database, collection_name: &str, from: &str, to: Option<&str>) -> Result<(), String> {
+    let defaults = stored_defaults(db, collection_name).await?;
+    let prefix = format!("{}.", from);
+    let mut changed = false;
+    let moved: FieldDefaults = defaults.into_iter()
+        .filter_map(|(field, value)| {
+            let rest = if field == from { Some("") } else { field.strip_prefix(&prefix) };
+            match (rest, to) {
+                (None, _) => Some((field, value)),
+                (Some(rest), Some(to)) => {
+                    changed = true;
+                    Some((format!("{}{}{}", to, if rest.is_empty() { "" } else { "." }, rest), value))
+                },
+                (Some(_), None) => {
+                    changed = true;
+                    None
+                },
+            }
+        })
+        .collect();
+    if changed {
+        save_defaults(db, collection_name, &moved).await?;
+    }
+    Ok(())
+}
+
+// Get a client's document ready to insert: server-managed fields are dropped,
+// missing fields get their `defaults` (see SchemaCache::defaults) and created_at is stamped
+pub fn prepare_insert(doc: &mut Document, defaults: &[(String, Bson)]) {
+    strip_server_managed(doc);
+    apply_defaults(doc, defaults);
+    doc.insert("created_at", mongodb::bson::DateTime::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_defaults_from_every_level() {
+        let cases: Vec<(Document, FieldDefaults)> = vec![
+            (doc! { "properties": { "title": { "bsonType": "string" } } }, vec![]),
+            (
+                doc! { "properties": { "status": { "bsonType": "string", "default": "new" } } },
+                vec![("status".to_string(), Bson::String("new".into()))],
+            ),
+            (
+                doc! { "properties": { "address": { "bsonType": "object", "default": {}, "properties": {
+                    "city": { "bsonType": "string", "default": "Oslo" },
+                } } } },
+                vec![
+                    ("address".to_string(), Bson::Document(doc! {})),
+                    ("address.city".to_string(), Bson::String("Oslo".into())),
+                ],
+            ),
+            (
+                doc! { "properties": { "authors": { "bsonType": "array", "items": { "properties": {
+                    "role": { "bsonType": "string", "default": "author" },
+                } } } } },
+                vec![("authors.$.role".to_string(), Bson::String("author".into()))],
+            ),
+            (
+                doc! { "properties": { "shelves": { "bsonType": "array", "items": { "bsonType": "array", "items": { "properties": {
+                    "n": { "bsonType": "int", "default": 0 },
+                } } } } } },
+                vec![("shelves.$.$.n".to_string(), Bson::Int32(0))],
+            ),
+        ];
+
+        for (schema, expected) in cases {
+            let mut stripped = schema.clone();
+            let defaults = take_defaults(&mut stripped);
+            assert_eq!(defaults, expected, "schema {}", schema);
+            assert!(!stripped.to_string().contains("default"), "defaults left in {}", stripped);
+
+            // Putting them back restores the schema
+            annotate_defaults(&mut stripped, &defaults);
+            assert_eq!(stripped, schema);
+        }
+    }
+
+    #[test]
+    fn applies_defaults_to_missing_fields_only() {
+        let defaults: FieldDefaults = vec![
+            ("status".to_string(), Bson::String("new".into())),
+            ("address.city".to_string(), Bson::String("Oslo".into())),
+            ("authors.$.role".to_string(), Bson::String("author".into())),
+        ];
+        let cases = [
+            (doc! {}, doc! { "status": "new" }),
+            (doc! { "status": Bson::Null }, doc! { "status": Bson::Null }),
+            (doc! { "address": {} }, doc! { "address": { "city": "Oslo" }, "status": "new" }),
+            (
+                doc! { "status": "old", "authors": [{ "name": "Ann" }, { "role": "editor" }, "Bob"] },
+                doc! { "status": "old", "authors": [{ "name": "Ann", "role": "author" }, { "role": "editor" }, "Bob"] },
+            ),
+        ];
+        for (mut doc, expected) in cases {
+            apply_defaults(&mut doc, &defaults);
+            assert_eq!(doc, expected);
+        }
+    }
+}
@@ -0,0 +1,466 @@
+// src/api_server/services/dictionary_service.rs
+
+use axum::http::StatusCode;
+use mongodb::bson::{Bson, Document};
+use mongodb::Database;
+use serde::Serialize;
+
+use crate::api_server::services::coercion_service::declared_types;
+use crate::api_server::services::index_service::{stored_indexes, IndexInfo};
+use crate::api_server::services::reference_service::parse_reference;
+use crate::api_server::services::schema_cache::SchemaCache;
+use crate::api_server::services::schema_export_service::{load_schemas, wire_json};
+use crate::timezone::InstitutionTimezone;
+
+// Validator keywords listed as constraints, with how each is shown
+const CONSTRAINT_KEYWORDS: [(&str, &str); 9] = [
+    ("pattern", "pattern"),
+    ("minimum", "min"),
+    ("maximum", "max"),
+    ("exclusiveMinimum", "greater than"),
+    ("exclusiveMaximum", "less than"),
+    ("minLength", "min length"),
+    ("maxLength", "max length"),
+    ("minItems", "min items"),
+    ("maxItems", "max items"),
+];
+
+type DictionaryResult<T> = Result<T, (StatusCode, String)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictionaryFormat {
+    Json,
+    Html,
+    Markdown,
+    // Entity-relationship diagrams built from the REF: annotations
+    Mermaid,
+    Dot,
+}
+
+impl DictionaryFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            "md" | "markdown" => Ok(Self::Markdown),
+            "mermaid" | "mmd" => Ok(Self::Mermaid),
+            "dot" | "graphviz" => Ok(Self::Dot),
+            other => Err(format!("Unsupported dictionary format '{}'. Use json, html, md, mermaid or dot", other)),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Html => "text/html; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Mermaid | Self::Dot => "text/plain; charset=utf-8",
+        }
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Json => "data-dictionary.json",
+            Self::Html => "data-dictionary.html",
+            Self::Markdown => "data-dictionary.md",
+            Self::Mermaid => "collections.mmd",
+            Self::Dot => "collections.dot",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DataDictionary {
+    pub generated_at: String,
+    pub collections: Vec<CollectionEntry>,
+    pub relationships: Vec<Relationship>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionEntry {
+    pub name: String,
+    pub documents: u64,
+    pub fields: Vec<FieldEntry>,
+    pub indexes: Vec<IndexInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldEntry {
+    // Dotted path; "[]" marks the items of an array, e.g. "authors[].name"
+    pub path: String,
+    // e.g. "string | null" or "array<objectId>"
+    pub bson_type: String,
+    pub required: bool,
+    pub constraints: Vec<String>,
+    pub default: Option<String>,
+    // Collection the field refers to, from its REF: marker
+    pub references: Option<String>,
+    // The description without its REF:/ON_DELETE: markers
+    pub description: String,
+}
+
+// A REF: annotated field, drawn as an edge from its collection to the referenced one
+#[derive(Debug, Serialize)]
+pub struct Relationship {
+    pub from: String,
+    pub field: String,
+    pub to: String,
+    // The field holds a list of references
+    pub many: bool,
+    // The field may be null or missing
+    pub optional: bool,
+    pub on_delete: String,
+}
+
+pub async fn build_dictionary(schema_cache: &SchemaCache, db: &Database, timezone: &InstitutionTimezone) -> DictionaryResult<DataDictionary> {
+    let schemas = load_schemas(schema_cache, db, None).await?;
+
+    let mut collections = Vec::new();
+    let mut relationships = Vec::new();
+    for (name, schema) in &schemas {
+        let indexes = stored_indexes(db, name).await?;
+        let documents = db.collection::<Document>(name)
+            .estimated_document_count(None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        let mut fields = Vec::new();
+        collect_fields(schema, "", true, &indexes, &mut fields);
+        for field in &fields {
+            let Some(target) = &field.references else { continue };
+            let on_delete = field_policy(schema, &field.path);
+            relationships.push(Relationship {
+                from: name.clone(),
+                field: field.path.clone(),
+                to: target.clone(),
+                many: field.path.ends_with("[]") || field.bson_type.starts_with("array"),
+                optional: !field.required || field.bson_type.contains("null"),
+                on_delete,
+            });
+        }
+        collections.push(CollectionEntry { name: name.clone(), documents, fields, indexes });
+    }
+
+    Ok(DataDictionary {
+        generated_at: timezone.format_iso(&mongodb::bson::DateTime::now()),
+        collections,
+        relationships,
+    })
+}
+
+// Flatten a schema's properties, nested objects and array items included
+fn collect_fields(spec: &Document, path: &str, parent_required: bool, indexes: &[IndexInfo], out: &mut Vec<FieldEntry>) {
+    let Ok(properties) = spec.get_document("properties") else { return };
+    let required: Vec<&str> = spec.get_array("required")
+        .map(|list| list.iter().filter_map(Bson::as_str).collect())
+        .unwrap_or_default();
+
+    for (field, property) in properties {
+        let Some(property) = property.as_document() else { continue };
+        let field_path = if path.is_empty() { field.clone() } else { format!("{}.{}", path, field) };
+        let is_required = parent_required && required.contains(&field.as_str());
+        out.push(field_entry(&field_path, property, is_required, indexes));
+        collect_fields(property, &field_path, is_required, indexes, out);
+
+        if let Ok(items) = property.get_document("items") {
+            let items_path = format!("{}[]", field_path);
+            // Reference arrays carry the REF: marker on their items; scalar items
+            // are described by their array's entry
+            if items.contains_key("properties") {
+                collect_fields(items, &items_path, false, indexes, out);
+            } else if items.get_str("description").ok().and_then(parse_reference).is_some() {
+                out.push(field_entry(&items_path, items, false, indexes));
+            }
+        }
+    }
+}
+
+fn field_entry(path: &str, spec: &Document, required: bool, indexes: &[IndexInfo]) -> FieldEntry {
+    let description = spec.get_str("description").unwrap_or_default();
+    let index_path = path.replace("[]", "");
+    let mut constraints = Vec::new();
+    if let Ok(values) = spec.get_array("enum") {
+        let values: Vec<String> = values.iter().map(|v| wire_json(v).to_string()).collect();
+        constraints.push(format!("one of {}", values.join(", ")));
+    }
+    for (keyword, label) in CONSTRAINT_KEYWORDS {
+        if let Some(value) = spec.get(keyword) {
+            constraints.push(format!("{} {}", label, wire_json(value)));
+        }
+    }
+    if spec.get_bool("uniqueItems") == Ok(true) {
+        constraints.push("unique items".to_string());
+    }
+    if indexes.iter().any(|i| i.unique && i.partial_filter.is_none() && i.fields.len() == 1 && i.fields[0].trim_start_matches('-') == index_path) {
+        constraints.push("unique".to_string());
+    }
+
+    FieldEntry {
+        path: path.to_string(),
+        bson_type: type_label(spec),
+        required,
+        constraints,
+        default: spec.get("default").map(|v| wire_json(v).to_string()),
+        references: parse_reference(description).map(|(target, _)| target),
+        description: description.split('|')
+            .map(str::trim)
+            .filter(|part| !part.starts_with("REF:") && !part.starts_with("ON_DELETE:") && !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" | "),
+    }
+}
+
+fn type_label(spec: &Document) -> String {
+    let types = declared_types(spec);
+    if types.is_empty() {
+        return if spec.contains_key("enum") { "enum".to_string() } else { "any".to_string() };
+    }
+    types.iter()
+        .map(|t| match (*t, spec.get_document("items")) {
+            ("array", Ok(items)) if !declared_types(items).is_empty() => format!("array<{}>", declared_types(items).join(" | ")),
+            _ => t.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+fn field_policy(schema: &Document, path: &str) -> String {
+    let mut spec = schema;
+    for segment in path.split('.') {
+        let (name, items) = match segment.strip_suffix("[]") {
+            Some(name) => (name, true),
+            None => (segment, false),
+        };
+        let Some(next) = spec.get_document("properties").ok().and_then(|p| p.get_document(name).ok()) else { break };
+        spec = next;
+        if items {
+            let Ok(next) = spec.get_document("items") else { break };
+            spec = next;
+        }
+    }
+    spec.get_str("description").ok()
+        .and_then(parse_reference)
+        .map(|(_, policy)| policy.label().to_string())
+        .unwrap_or_default()
+}
+
+pub fn render(format: DictionaryFormat, dictionary: &DataDictionary) -> String {
+    match format {
+        DictionaryFormat::Json => serde_json::to_string_pretty(dictionary).unwrap_or_default(),
+        DictionaryFormat::Html => html(dictionary),
+        DictionaryFormat::Markdown => markdown(dictionary),
+        DictionaryFormat::Mermaid => mermaid(dictionary),
+        DictionaryFormat::Dot => dot(dictionary),
+    }
+}
+
+fn index_options(index: &IndexInfo) -> String {
+    let mut options = Vec::new();
+    if index.unique { options.push("unique".to_string()); }
+    if index.text { options.push("text".to_string()); }
+    if index.sparse { options.push("sparse".to_string()); }
+    if let Some(seconds) = index.expire_after_seconds { options.push(format!("expires after {}s", seconds)); }
+    if let Some(filter) = &index.partial_filter { options.push(format!("partial: {}", filter)); }
+    options.join(", ")
+}
+
+// ---- Markdown ----
+
+fn md_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn markdown(dictionary: &DataDictionary) -> String {
+    let mut out = format!("# Data dictionary\n\nGenerated {}.\n\n", dictionary.generated_at);
+    for collection in &dictionary.collections {
+        out.push_str(&format!("- [{}](#{})\n", collection.name, collection.name.to_lowercase()));
+    }
+
+    for collection in &dictionary.collections {
+        out.push_str(&format!("\n## {}\n\n{} document(s).\n\n", collection.name, collection.documents));
+        out.push_str("| Field | Type | Required | Constraints | Default | Description |\n|---|---|---|---|---|---|\n");
+        for field in &collection.fields {
+            let description = match &field.references {
+                Some(target) => format!("References [{}](#{}). {}", target, target.to_lowercase(), field.description),
+                None => field.description.clone(),
+            };
+            out.push_str(&format!(
+                "| `{}` | {} | {} | {} | {} | {} |\n",
+                field.path,
+                md_cell(&field.bson_type),
+                if field.required { "yes" } else { "" },
+                md_cell(&field.constraints.join("; ")),
+                field.default.as_deref().map(|d| format!("`{}`", md_cell(d))).unwrap_or_default(),
+                md_cell(description.trim()),
+            ));
+        }
+        if !collection.indexes.is_empty() {
+            out.push_str("\n| Index | Fields | Options |\n|---|---|---|\n");
+            for index in &collection.indexes {
+                out.push_str(&format!("| {} | {} | {} |\n", md_cell(&index.name), md_cell(&index.fields.join(", ")), md_cell(&index_options(index))));
+            }
+        }
+    }
+
+    if !dictionary.relationships.is_empty() {
+        out.push_str("\n## Relationships\n\n```mermaid\n");
+        out.push_str(&mermaid(dictionary));
+        out.push_str("```\n");
+    }
+    out
+}
+
+// ---- HTML ----
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn html(dictionary: &DataDictionary) -> String {
+    let mut out = String::from(concat!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Data dictionary</title>\n<style>\n",
+        "body { font-family: system-ui, sans-serif; margin: 0; display: flex; }\n",
+        "nav { position: sticky; top: 0; height: 100vh; overflow-y: auto; padding: 1rem; border-right: 1px solid #ddd; min-width: 12rem; }\n",
+        "nav a { display: block; padding: 0.15rem 0; }\n",
+        "main { padding: 1rem 2rem; flex: 1; }\n",
+        "table { border-collapse: collapse; margin: 0.5rem 0 1.5rem; width: 100%; }\n",
+        "th, td { border: 1px solid #ddd; padding: 0.3rem 0.5rem; text-align: left; vertical-align: top; }\n",
+        "th { background: #f5f5f5; }\n",
+        "code { font-size: 0.9em; }\n",
+        "</style>\n</head>\n<body>\n<nav>\n<strong>Collections</strong>\n",
+    ));
+    for collection in &dictionary.collections {
+        let name = escape_html(&collection.name);
+        out.push_str(&format!("<a href=\"#{}\">{}</a>\n", name, name));
+    }
+    if !dictionary.relationships.is_empty() {
+        out.push_str("<a href=\"#relationships\"><em>Relationships</em></a>\n");
+    }
+    out.push_str(&format!("</nav>\n<main>\n<h1>Data dictionary</h1>\n<p>Generated {}.</p>\n", escape_html(&dictionary.generated_at)));
+
+    for collection in &dictionary.collections {
+        let name = escape_html(&collection.name);
+        out.push_str(&format!("<section id=\"{}\">\n<h2>{}</h2>\n<p>{} document(s).</p>\n", name, name, collection.documents));
+        out.push_str("<table>\n<tr><th>Field</th><th>Type</th><th>Required</th><th>Constraints</th><th>Default</th><th>Description</th></tr>\n");
+        for field in &collection.fields {
+            let reference = match &field.references {
+                Some(target) => format!("References <a href=\"#{0}\">{0}</a>. ", escape_html(target)),
+                None => String::new(),
+            };
+            out.push_str(&format!(
+                "<tr><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}{}</td></tr>\n",
+                escape_html(&field.path),
+                escape_html(&field.bson_type),
+                if field.required { "yes" } else { "" },
+                escape_html(&field.constraints.join("; ")),
+                field.default.as_deref().map(|d| format!("<code>{}</code>", escape_html(d))).unwrap_or_default(),
+                reference,
+                escape_html(&field.description),
+            ));
+        }
+        out.push_str("</table>\n");
+        if !collection.indexes.is_empty() {
+            out.push_str("<table>\n<tr><th>Index</th><th>Fields</th><th>Options</th></tr>\n");
+            for index in &collection.indexes {
+                out.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    escape_html(&index.name), escape_html(&index.fields.join(", ")), escape_html(&index_options(index)),
+                ));
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</section>\n");
+    }
+
+    if !dictionary.relationships.is_empty() {
+        out.push_str("<section id=\"relationships\">\n<h2>Relationships</h2>\n<table>\n<tr><th>From</th><th>Field</th><th>To</th><th>Cardinality</th><th>On delete</th></tr>\n");
+        for relationship in &dictionary.relationships {
+            out.push_str(&format!(
+                "<tr><td><a href=\"#{0}\">{0}</a></td><td><code>{1}</code></td><td><a href=\"#{2}\">{2}</a></td><td>{3}</td><td>{4}</td></tr>\n",
+                escape_html(&relationship.from),
+                escape_html(&relationship.field),
+                escape_html(&relationship.to),
+                if relationship.many { "many" } else { "one" },
+                escape_html(&relationship.on_delete),
+            ));
+        }
+        out.push_str("</table>\n</section>\n");
+    }
+    out.push_str("</main>\n</body>\n</html>\n");
+    out
+}
+
+// ---- Diagrams ----
+
+// Mermaid entity and attribute names only allow letters, digits, '_' and '-'
+fn diagram_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect()
+}
+
+// Top-level fields only; nested paths would crowd the boxes
+fn diagram_fields(collection: &CollectionEntry) -> impl Iterator<Item = &FieldEntry> {
+    collection.fields.iter().filter(|f| !f.path.contains('.') && !f.path.contains("[]"))
+}
+
+fn mermaid(dictionary: &DataDictionary) -> String {
+    let mut out = String::from("erDiagram\n");
+    for collection in &dictionary.collections {
+        out.push_str(&format!("    {} {{\n", diagram_name(&collection.name)));
+        out.push_str("        objectId _id PK\n");
+        for field in diagram_fields(collection).filter(|f| f.path != "_id") {
+            let base_type = field.bson_type.split([' ', '<']).next().unwrap_or("any");
+            let bson_type = diagram_name(base_type);
+            let key = if field.references.is_some() { " FK" } else { "" };
+            out.push_str(&format!("        {} {}{}\n", bson_type, diagram_name(&field.path), key));
+        }
+        out.push_str("    }\n");
+    }
+    for relationship in &dictionary.relationships {
+        // The referencing side is always "zero or more"; the referenced side is
+        // one document, or zero or one when the field is optional
+        let to = match (relationship.many, relationship.optional) {
+            (true, _) => "o{",
+            (false, true) => "o|",
+            (false, false) => "||",
+        };
+        out.push_str(&format!(
+            "    {} }}o--{} {} : \"{}\"\n",
+            diagram_name(&relationship.from), to, diagram_name(&relationship.to), relationship.field.replace('"', "'"),
+        ));
+    }
+    out
+}
+
+fn dot_record(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| match c {
+            '{' | '}' | '|' | '<' | '>' | '"' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+fn dot(dictionary: &DataDictionary) -> String {
+    let mut out = String::from("digraph collections {\n    rankdir=LR;\n    node [shape=record, fontname=\"Helvetica\", fontsize=10];\n    edge [fontname=\"Helvetica\", fontsize=9];\n\n");
+    for collection in &dictionary.collections {
+        let fields: Vec<String> = diagram_fields(collection)
+            .map(|f| format!("{} : {}\\l", dot_record(&f.path), dot_record(&f.bson_type)))
+            .collect();
+        out.push_str(&format!(
+            "    \"{}\" [label=\"{{{}|{}}}\"];\n",
+            collection.name.replace('"', "\\\""), dot_record(&collection.name), fields.join(""),
+        ));
+    }
+    out.push('\n');
+    for relationship in &dictionary.relationships {
+        let style = if relationship.optional { ", style=dashed" } else { "" };
+        let head = if relationship.many { "crow" } else { "normal" };
+        out.push_str(&format!(
+            "    \"{}\" -> \"{}\" [label=\"{}\", arrowhead={}{}];\n",
+            relationship.from.replace('"', "\\\""), relationship.to.replace('"', "\\\""),
+            relationship.field.replace('"', "\\\""), head, style,
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
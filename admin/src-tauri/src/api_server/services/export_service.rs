@@ -0,0 +1,589 @@
+// src/api_server/services/export_service.rs
+
+use futures_util::stream::{Stream, StreamExt};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::Collection;
+use std::collections::HashMap;
+use std::io::Read;
+use std::pin::Pin;
+
+use crate::api_server::services::xlsx_writer::{pixels_to_width, XlsxCell, XlsxSheet};
+use crate::timezone::InstitutionTimezone;
+
+// Output is handed to the response body once this many bytes are buffered,
+// so memory stays bounded for the streaming formats
+pub const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+    // JSON array with display values (hex ids, ISO 8601 dates with offset)
+    Json,
+    // One relaxed Extended JSON document per line (UTC dates), importable with mongoimport
+    Ndjson,
+    // JSON array of canonical Extended JSON documents, importable with mongoimport --jsonArray
+    ExtendedJson,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "csv" => Ok(Self::Csv),
+            "xlsx" | "excel" => Ok(Self::Xlsx),
+            "json" => Ok(Self::Json),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            "extjson" | "extended-json" | "ejson" => Ok(Self::ExtendedJson),
+            other => Err(format!(
+                "Unsupported export format '{}'. Use csv, xlsx, json, ndjson or extjson",
+                other
+            )),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Self::Json | Self::ExtendedJson => "application/json",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+            Self::ExtendedJson => "extjson.json",
+        }
+    }
+
+    // JSON formats export whole values keyed by field name and always carry _id
+    pub fn is_document_format(&self) -> bool {
+        matches!(self, Self::Json | Self::Ndjson | Self::ExtendedJson)
+    }
+}
+
+// An exported column: the document field, its header text and width in pixels
+#[derive(Debug, Clone)]
+pub struct ExportColumn {
+    pub field: String,
+    pub header: String,
+    pub width: Option<f64>,
+}
+
+// Writes documents in one output format. Output accumulates in an internal
+// buffer that the caller drains with `take_output`; after `finish`, the caller keeps
+// taking output until it comes back empty.
+pub trait Exporter: Send {
+    fn write_document(&mut self, doc: &Document) -> Result<(), String>;
+    // Write any trailer; no documents may follow
+    fn finish(&mut self) -> Result<(), String>;
+    fn buffered_len(&self) -> usize;
+    fn take_output(&mut self) -> Vec<u8>;
+}
+
+// Dates are written in `timezone` for the human-facing formats; the Extended JSON
+// formats keep UTC so they round-trip through mongoimport
+pub fn create_exporter(
+    format: ExportFormat,
+    columns: Vec<ExportColumn>,
+    sheet_name: &str,
+    timezone: InstitutionTimezone,
+) -> Result<Box<dyn Exporter>, String> {
+    Ok(match format {
+        ExportFormat::Csv => Box::new(CsvExporter::new(columns, timezone)),
+        ExportFormat::Xlsx => Box::new(XlsxExporter::new(columns, sheet_name, timezone)?),
+        ExportFormat::Json => Box::new(JsonArrayExporter::new(columns, JsonStyle::Plain(timezone))),
+        ExportFormat::ExtendedJson => Box::new(JsonArrayExporter::new(columns, JsonStyle::Canonical)),
+        ExportFormat::Ndjson => Box::new(NdjsonExporter { columns, buffer: Vec::new() }),
+    })
+}
+
+// Drive an exporter over a document stream, yielding output chunks.
+// An error ends the stream early, which aborts the download.
+pub fn stream_export<S>(
+    documents: S,
+    exporter: Box<dyn Exporter>,
+    label: String,
+) -> impl Stream<Item = Result<Vec<u8>, std::io::Error>> + Send
+where
+    S: Stream<Item = Result<Document, String>> + Send + 'static,
+{
+    let documents: Pin<Box<dyn Stream<Item = Result<Document, String>> + Send>> = Box::pin(documents);
+
+    futures_util::stream::unfold(
+        Some((documents, exporter, 0u64, false)),
+        move |state| {
+            let label = label.clone();
+            async move {
+                let (mut documents, mut exporter, mut count, finished) = state?;
+
+                // Drain what `finish` left, one chunk per item
+                if finished {
+                    let (exporter, chunk) = match on_blocking_thread(exporter, |e| e.take_output()).await {
+                        Ok(taken) => taken,
+                        Err(e) => return Some((Err(e), None)),
+                    };
+                    return if chunk.is_empty() { None } else { Some((Ok(chunk), Some((documents, exporter, count, true)))) };
+                }
+
+                loop {
+                    match documents.next().await {
+                        Some(Ok(doc)) => {
+                            if let Err(e) = exporter.write_document(&doc) {
+                                tracing::error!("Export of {} failed at document {}: {}", label, count, e);
+                                return Some((Err(std::io::Error::other(e)), None));
+                            }
+                            count += 1;
+
+                            if exporter.buffered_len() >= EXPORT_CHUNK_SIZE {
+                                let chunk = exporter.take_output();
+                                if !chunk.is_empty() {
+                                    return Some((Ok(chunk), Some((documents, exporter, count, false))));
+                                }
+                            }
+                        },
+                        Some(Err(e)) => {
+                            tracing::error!("Export of {} failed at document {}: {}", label, count, e);
+                            return Some((Err(std::io::Error::other(e)), None));
+                        },
+                        None => {
+                            tracing::info!("Export of {} finished: {} documents", label, count);
+                            let finished = on_blocking_thread(exporter, |e| e.finish().map(|_| e.take_output())).await;
+                            let (exporter, chunk) = match finished {
+                                Ok((exporter, Ok(chunk))) => (exporter, chunk),
+                                Ok((_, Err(e))) => return Some((Err(std::io::Error::other(e)), None)),
+                                Err(e) => return Some((Err(e), None)),
+                            };
+                            return if chunk.is_empty() { None } else { Some((Ok(chunk), Some((documents, exporter, count, true)))) };
+                        },
+                    }
+                }
+            }
+        },
+    )
+}
+
+// Finishing a workbook zips it into a file and its output is read back from
+// there, so that work stays off the async workers
+async fn on_blocking_thread<T, F>(mut exporter: Box<dyn Exporter>, work: F) -> Result<(Box<dyn Exporter>, T), std::io::Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn Exporter) -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let output = work(exporter.as_mut());
+        (exporter, output)
+    })
+    .await
+    .map_err(std::io::Error::other)
+}
+
+// Text shown for a value in CSV cells and non-typed spreadsheet cells;
+// dates are wall-clock time in the given timezone
+pub fn display_value(value: &Bson, timezone: &InstitutionTimezone) -> String {
+    match value {
+        Bson::Null | Bson::Undefined => String::new(),
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::DateTime(dt) => timezone.format_wall_clock(dt),
+        Bson::String(s) => s.clone(),
+        Bson::Int32(i) => i.to_string(),
+        Bson::Int64(i) => i.to_string(),
+        Bson::Double(d) => d.to_string(),
+        Bson::Boolean(b) => b.to_string(),
+        Bson::Decimal128(d) => d.to_string(),
+        other => other.clone().into_relaxed_extjson().to_string(),
+    }
+}
+
+// JSON without Extended JSON wrappers, for tools that just want values
+pub fn to_plain_json(value: &Bson, timezone: &InstitutionTimezone) -> serde_json::Value {
+    match value {
+        Bson::ObjectId(oid) => serde_json::Value::String(oid.to_hex()),
+        Bson::DateTime(dt) => serde_json::Value::String(timezone.format_iso(dt)),
+        Bson::Decimal128(d) => serde_json::Value::String(d.to_string()),
+        Bson::Document(doc) => serde_json::Value::Object(
+            doc.iter().map(|(k, v)| (k.clone(), to_plain_json(v, timezone))).collect(),
+        ),
+        Bson::Array(items) => serde_json::Value::Array(items.iter().map(|v| to_plain_json(v, timezone)).collect()),
+        other => other.clone().into_relaxed_extjson(),
+    }
+}
+
+// Value at a dotted column path, e.g. "address.city" or "tags.0". A key that itself
+// contains dots (staged CSV rows keep their column names) wins over the path.
+pub fn lookup_path<'a>(doc: &'a Document, path: &str) -> Option<&'a Bson> {
+    if let Some(value) = doc.get(path) {
+        return Some(value);
+    }
+    let mut segments = path.split('.');
+    let mut current = doc.get(segments.next()?)?;
+    for segment in segments {
+        current = match current {
+            Bson::Document(d) => d.get(segment)?,
+            Bson::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn is_type(spec: &Document, bson_type: &str) -> bool {
+    match spec.get("bsonType") {
+        Some(Bson::String(t)) => t == bson_type,
+        Some(Bson::Array(types)) => types.iter().any(|t| t.as_str() == Some(bson_type)),
+        _ => false,
+    }
+}
+
+// Paths of array fields that get one column per element, i.e. arrays not inside other arrays
+pub fn array_paths(properties: &Document, fields: &[String]) -> Vec<String> {
+    fn collect(properties: &Document, prefix: &str, paths: &mut Vec<String>) {
+        for (key, spec) in properties {
+            let Some(spec) = spec.as_document() else { continue };
+            let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            if is_type(spec, "array") {
+                paths.push(path);
+            } else if let Ok(children) = spec.get_document("properties") {
+                collect(children, &path, paths);
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    collect(properties, "", &mut paths);
+    paths.retain(|path| fields.iter().any(|f| path == f || path.starts_with(&format!("{}.", f))));
+    paths
+}
+
+// Longest array stored at each path among the exported documents
+pub async fn array_lengths(
+    collection: &Collection<Document>,
+    filter: &Document,
+    paths: &[String],
+) -> Result<HashMap<String, usize>, String> {
+    if paths.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut group = doc! { "_id": Bson::Null };
+    for (i, path) in paths.iter().enumerate() {
+        let field = format!("${}", path);
+        group.insert(format!("len_{}", i), doc! {
+            "$max": { "$cond": [{ "$isArray": &field }, { "$size": &field }, 0] }
+        });
+    }
+    let pipeline = vec![doc! { "$match": filter.clone() }, doc! { "$group": group }];
+
+    let mut cursor = collection.aggregate(pipeline, None).await.map_err(|e| e.to_string())?;
+    let lengths = match cursor.next().await {
+        Some(result) => result.map_err(|e| e.to_string())?,
+        None => Document::new(),
+    };
+
+    Ok(paths.iter().enumerate()
+        .map(|(i, path)| {
+            let len = match lengths.get(format!("len_{}", i)) {
+                Some(Bson::Int32(n)) => *n as usize,
+                Some(Bson::Int64(n)) => *n as usize,
+                _ => 0,
+            };
+            (path.clone(), len)
+        })
+        .collect())
+}
+
+// Spread nested values over dotted columns for the tabular formats: object
+// properties become "address.city", array elements "tags.0", "tags.1", ...
+// up to the longest array in `array_lengths`. Arrays nested in arrays stay
+// in one column as JSON.
+pub fn flatten_columns(
+    columns: Vec<ExportColumn>,
+    properties: &Document,
+    array_lengths: &HashMap<String, usize>,
+) -> Vec<ExportColumn> {
+    fn expand(column: ExportColumn, spec: Option<&Document>, array_lengths: &HashMap<String, usize>, out: &mut Vec<ExportColumn>) {
+        let Some(spec) = spec else {
+            out.push(column);
+            return;
+        };
+        let child = |column: &ExportColumn, key: &str| ExportColumn {
+            field: format!("{}.{}", column.field, key),
+            header: format!("{}.{}", column.header, key),
+            width: column.width,
+        };
+
+        if let (true, Ok(children)) = (is_type(spec, "object"), spec.get_document("properties")) {
+            for (key, child_spec) in children {
+                expand(child(&column, key), child_spec.as_document(), array_lengths, out);
+            }
+        } else if let (true, Some(&len)) = (is_type(spec, "array"), array_lengths.get(&column.field).filter(|len| **len > 0)) {
+            let items = spec.get_document("items").ok();
+            // Elements that are objects split further; arrays in arrays have no lengths and stay whole
+            for i in 0..len {
+                expand(child(&column, &i.to_string()), items, array_lengths, out);
+            }
+        } else {
+            out.push(column);
+        }
+    }
+
+    let mut flattened = Vec::new();
+    for column in columns {
+        let spec = properties.get_document(&column.field).ok();
+        expand(column, spec, array_lengths, &mut flattened);
+    }
+    flattened
+}
+
+// Keep only the exported columns (in column order), _id first for the JSON formats
+fn project(doc: &Document, columns: &[ExportColumn]) -> Document {
+    let mut projected = Document::new();
+    if let Some(id) = doc.get("_id") {
+        projected.insert("_id", id.clone());
+    }
+    for column in columns {
+        if let Some(value) = doc.get(&column.field) {
+            projected.insert(column.field.clone(), value.clone());
+        }
+    }
+    projected
+}
+
+struct CsvExporter {
+    columns: Vec<ExportColumn>,
+    timezone: InstitutionTimezone,
+    writer: csv::Writer<Vec<u8>>,
+    header_written: bool,
+}
+
+impl CsvExporter {
+    fn new(columns: Vec<ExportColumn>, timezone: InstitutionTimezone) -> Self {
+        Self { columns, timezone, writer: Self::chunk_writer(), header_written: false }
+    }
+
+    fn chunk_writer() -> csv::Writer<Vec<u8>> {
+        csv::WriterBuilder::new()
+            .quote_style(csv::QuoteStyle::NonNumeric) // Only quote non-numeric fields
+            .double_quote(true) // Use standard CSV double-quoting
+            .from_writer(Vec::with_capacity(EXPORT_CHUNK_SIZE))
+    }
+
+    fn write_header(&mut self) -> Result<(), String> {
+        if !self.header_written {
+            let headers: Vec<&str> = self.columns.iter().map(|c| c.header.as_str()).collect();
+            self.writer.write_record(&headers).map_err(|e| format!("CSV header error: {}", e))?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+}
+
+impl Exporter for CsvExporter {
+    fn write_document(&mut self, doc: &Document) -> Result<(), String> {
+        self.write_header()?;
+        let row: Vec<String> = self.columns.iter()
+            .map(|c| lookup_path(doc, &c.field).map(|v| display_value(v, &self.timezone)).unwrap_or_default())
+            .collect();
+        self.writer.write_record(&row).map_err(|e| format!("CSV write error: {}", e))
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.write_header()
+    }
+
+    fn buffered_len(&self) -> usize {
+        self.writer.get_ref().len()
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        let full = std::mem::replace(&mut self.writer, Self::chunk_writer());
+        // Flushing into a Vec can't fail
+        full.into_inner().unwrap_or_default()
+    }
+}
+
+// Spreadsheets can't be streamed while rows come in: the zip directory comes last.
+// XlsxSheet keeps the rows on disk, and `finish` leaves the workbook in a temporary
+// file that `take_output` then hands out in chunks.
+struct XlsxExporter {
+    columns: Vec<ExportColumn>,
+    timezone: InstitutionTimezone,
+    sheet: Option<XlsxSheet>,
+    workbook: Option<std::fs::File>,
+}
+
+impl XlsxExporter {
+    fn new(columns: Vec<ExportColumn>, sheet_name: &str, timezone: InstitutionTimezone) -> Result<Self, String> {
+        let widths: Vec<Option<f64>> = columns.iter().map(|c| c.width.map(pixels_to_width)).collect();
+        let mut sheet = XlsxSheet::new(sheet_name, &widths)?;
+        let headers: Vec<String> = columns.iter().map(|c| c.header.clone()).collect();
+        sheet.add_header(&headers)?;
+        Ok(Self { columns, timezone, sheet: Some(sheet), workbook: None })
+    }
+}
+
+// Spreadsheet dates have no timezone, so they carry the wall-clock time
+fn to_xlsx_cell(value: Option<&Bson>, timezone: &InstitutionTimezone) -> XlsxCell {
+    match value {
+        None | Some(Bson::Null) | Some(Bson::Undefined) => XlsxCell::Empty,
+        Some(Bson::Int32(i)) => XlsxCell::Number(*i as f64),
+        Some(Bson::Int64(i)) => XlsxCell::Number(*i as f64),
+        Some(Bson::Double(d)) => XlsxCell::Number(*d),
+        // Decimals stay numeric when a double holds them exactly, otherwise text keeps every digit
+        Some(Bson::Decimal128(d)) => {
+            let text = d.to_string();
+            let significant = if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.') } else { &text };
+            match text.parse::<f64>() {
+                Ok(n) if n.is_finite() && n.to_string() == significant => XlsxCell::Number(n),
+                _ => XlsxCell::Text(text),
+            }
+        },
+        Some(Bson::Boolean(b)) => XlsxCell::Bool(*b),
+        Some(Bson::DateTime(dt)) => XlsxCell::DateTime(dt.timestamp_millis() + timezone.offset_millis(dt)),
+        Some(other) => XlsxCell::Text(display_value(other, timezone)),
+    }
+}
+
+impl Exporter for XlsxExporter {
+    fn write_document(&mut self, doc: &Document) -> Result<(), String> {
+        let sheet = self.sheet.as_mut().ok_or("Workbook already finished")?;
+        let cells: Vec<XlsxCell> = self.columns.iter().map(|c| to_xlsx_cell(lookup_path(doc, &c.field), &self.timezone)).collect();
+        sheet.add_row(&cells)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        if let Some(sheet) = self.sheet.take() {
+            self.workbook = Some(sheet.finish()?);
+        }
+        Ok(())
+    }
+
+    // Never flush mid-sheet
+    fn buffered_len(&self) -> usize {
+        0
+    }
+
+    // The next chunk of the finished workbook; a read error ends the output, and
+    // the truncated file then fails to open rather than passing as complete
+    fn take_output(&mut self) -> Vec<u8> {
+        let Some(file) = self.workbook.as_mut() else { return Vec::new() };
+        let mut chunk = Vec::with_capacity(EXPORT_CHUNK_SIZE);
+        match file.by_ref().take(EXPORT_CHUNK_SIZE as u64).read_to_end(&mut chunk) {
+            Ok(_) => chunk,
+            Err(e) => {
+                tracing::error!("Failed to read exported workbook: {}", e);
+                self.workbook = None;
+                Vec::new()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum JsonStyle {
+    Plain(InstitutionTimezone),
+    Canonical,
+}
+
+struct JsonArrayExporter {
+    columns: Vec<ExportColumn>,
+    style: JsonStyle,
+    buffer: Vec<u8>,
+    count: u64,
+}
+
+impl JsonArrayExporter {
+    fn new(columns: Vec<ExportColumn>, style: JsonStyle) -> Self {
+        Self { columns, style, buffer: b"[".to_vec(), count: 0 }
+    }
+}
+
+impl Exporter for JsonArrayExporter {
+    fn write_document(&mut self, doc: &Document) -> Result<(), String> {
+        let projected = Bson::Document(project(doc, &self.columns));
+        let value = match self.style {
+            JsonStyle::Plain(timezone) => to_plain_json(&projected, &timezone),
+            JsonStyle::Canonical => projected.into_canonical_extjson(),
+        };
+        self.buffer.extend_from_slice(if self.count == 0 { b"\n" } else { b",\n" });
+        serde_json::to_writer(&mut self.buffer, &value).map_err(|e| format!("JSON write error: {}", e))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.buffer.extend_from_slice(b"\n]\n");
+        Ok(())
+    }
+
+    fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+struct NdjsonExporter {
+    columns: Vec<ExportColumn>,
+    buffer: Vec<u8>,
+}
+
+impl Exporter for NdjsonExporter {
+    fn write_document(&mut self, doc: &Document) -> Result<(), String> {
+        let value = Bson::Document(project(doc, &self.columns)).into_relaxed_extjson();
+        serde_json::to_writer(&mut self.buffer, &value).map_err(|e| format!("JSON write error: {}", e))?;
+        self.buffer.push(b'\n');
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xlsx_output_drains_in_chunks_into_one_workbook() {
+        let columns = vec![ExportColumn { field: "n".to_string(), header: "N".to_string(), width: Some(70.0) }];
+        let mut exporter = create_exporter(ExportFormat::Xlsx, columns, "numbers", InstitutionTimezone::Local).unwrap();
+        for n in 0..20_000 {
+            exporter.write_document(&doc! { "n": format!("row {} of a sheet bigger than one chunk", n) }).unwrap();
+        }
+        exporter.finish().unwrap();
+
+        let mut chunks = 0;
+        let mut output = Vec::new();
+        loop {
+            let chunk = exporter.take_output();
+            if chunk.is_empty() {
+                break;
+            }
+            assert!(chunk.len() <= EXPORT_CHUNK_SIZE);
+            chunks += 1;
+            output.extend(chunk);
+        }
+        assert!(chunks > 1);
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).expect("complete zip");
+        let mut xml = String::new();
+        archive.by_name("xl/worksheets/sheet1.xml").unwrap().read_to_string(&mut xml).unwrap();
+        assert!(xml.contains("row 19999 of a sheet"));
+    }
+}
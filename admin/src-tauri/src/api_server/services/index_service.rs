@@ -0,0 +1,393 @@
+// src/api_server/services/index_service.rs
+
+use axum::http::StatusCode;
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{Error, ErrorKind};
+use mongodb::options::{AggregateOptions, FindOptions};
+use mongodb::{Collection, Database, IndexModel};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::api_server::services::collection_service::IndexDefinition;
+use crate::api_server::services::export_service::lookup_path;
+
+type IndexResult<T> = Result<T, (StatusCode, String)>;
+
+// Duplicated key values quoted when a unique index can't be built
+const DUPLICATE_SAMPLES: i64 = 5;
+
+// Key values looked up per query when checking candidates against stored documents
+const LOOKUP_BATCH: usize = 500;
+
+// Server error codes for a duplicate key and for an index that clashes with an existing one
+const DUPLICATE_KEY: i32 = 11000;
+const INDEX_OPTIONS_CONFLICT: i32 = 85;
+const INDEX_KEY_SPECS_CONFLICT: i32 = 86;
+
+// An index as the server reports it, in the field notation IndexDefinition uses
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexInfo {
+    pub name: String,
+    // Key fields in order; '-' marks a descending field
+    pub fields: Vec<String>,
+    pub unique: bool,
+    pub text: bool,
+    pub sparse: bool,
+    pub expire_after_seconds: Option<u64>,
+    pub partial_filter: Option<Document>,
+    // Raw key document, for index types the field list can't express (hashed, 2dsphere, ...)
+    pub keys: Document,
+    pub size_bytes: Option<i64>,
+}
+
+// An index build still running on the server
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexBuild {
+    pub indexes: Vec<String>,
+    pub done: i64,
+    pub total: i64,
+    pub message: String,
+    pub running_secs: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexOverview {
+    pub indexes: Vec<IndexInfo>,
+    pub builds: Vec<IndexBuild>,
+}
+
+// Fields whose values, taken together, may appear in at most one document
+#[derive(Debug, Clone, Serialize)]
+pub struct UniqueKey {
+    pub name: String,
+    pub fields: Vec<String>,
+    // Only documents matching this filter take part in the constraint
+    pub partial_filter: Option<Document>,
+    // Documents missing a key field are left out of a sparse index
+    pub sparse: bool,
+}
+
+impl UniqueKey {
+    pub fn label(&self) -> String {
+        self.fields.join(", ")
+    }
+
+    // The key's values in a document, as the index would store them: a missing field
+    // counts as null, so two documents without it collide. None when the document may
+    // not be in the index at all: a sparse index leaves out documents missing every key
+    // field, and a partial one may leave out any with a missing or null field (the
+    // filter isn't evaluated here).
+    pub fn values_in(&self, doc: &Document) -> Option<Vec<Bson>> {
+        let values: Vec<Option<&Bson>> = self.fields.iter().map(|field| lookup_path(doc, field)).collect();
+        if self.sparse && values.iter().all(Option::is_none) {
+            return None;
+        }
+        values.into_iter()
+            .map(|value| match value {
+                None | Some(Bson::Null) if self.partial_filter.is_some() => None,
+                None => Some(Bson::Null),
+                Some(value) => Some(value.clone()),
+            })
+            .collect()
+    }
+
+    // Which of the given key values stored documents already hold, as key_text
+    pub async fn existing(&self, collection: &Collection<Document>, candidates: &[Vec<Bson>]) -> Result<HashSet<String>, Error> {
+        let mut projection = doc! { "_id": 0 };
+        for field in &self.fields {
+            projection.insert(field.as_str(), 1);
+        }
+        let options = FindOptions::builder().projection(projection).build();
+
+        let mut found = HashSet::new();
+        for batch in candidates.chunks(LOOKUP_BATCH) {
+            let alternatives: Vec<Document> = batch.iter()
+                .map(|values| self.fields.iter().cloned().zip(values.iter().cloned()).collect())
+                .collect();
+            let mut filter = doc! { "$or": alternatives };
+            if let Some(partial) = &self.partial_filter {
+                filter = doc! { "$and": [partial.clone(), filter] };
+            }
+            let docs: Vec<Document> = collection.find(filter, options.clone()).await?.try_collect().await?;
+            found.extend(docs.iter().filter_map(|doc| self.values_in(doc)).map(|values| key_text(&values)));
+        }
+        Ok(found)
+    }
+}
+
+// Comparable text for a set of key values
+pub fn key_text(values: &[Bson]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\u{1f}")
+}
+
+// The collection's unique constraints, read from its real unique indexes (compound
+// ones included). The implicit _id index is left out.
+pub async fn unique_keys(collection: &Collection<Document>) -> Result<Vec<UniqueKey>, Error> {
+    let models: Vec<IndexModel> = collection.list_indexes(None).await?.try_collect().await?;
+    Ok(models.into_iter()
+        .map(|model| info_from_model(model, None))
+        .filter(|index| index.unique && !index.text && index.name != "_id_")
+        .map(|index| UniqueKey {
+            name: index.name,
+            fields: index.fields.iter().map(|f| f.trim_start_matches('-').to_string()).collect(),
+            partial_filter: index.partial_filter,
+            sparse: index.sparse,
+        })
+        .collect())
+}
+
+fn info_from_model(model: IndexModel, sizes: Option<&Document>) -> IndexInfo {
+    let options = model.options.unwrap_or_default();
+    let name = options.name.clone().unwrap_or_default();
+    let mut fields = Vec::new();
+    let mut text = false;
+    for (field, direction) in &model.keys {
+        match (field.as_str(), direction) {
+            // Text indexes store their fields as weights behind the _fts/_ftsx keys
+            ("_fts", _) => {
+                text = true;
+                fields.extend(options.weights.iter().flat_map(|w| w.keys().cloned()));
+            },
+            ("_ftsx", _) => {},
+            (_, Bson::Int32(d)) if *d < 0 => fields.push(format!("-{}", field)),
+            (_, Bson::Int64(d)) if *d < 0 => fields.push(format!("-{}", field)),
+            (_, Bson::Double(d)) if *d < 0.0 => fields.push(format!("-{}", field)),
+            _ => fields.push(field.clone()),
+        }
+    }
+
+    IndexInfo {
+        size_bytes: sizes.and_then(|s| match s.get(&name) {
+            Some(Bson::Int32(size)) => Some(*size as i64),
+            Some(Bson::Int64(size)) => Some(*size),
+            Some(Bson::Double(size)) => Some(*size as i64),
+            _ => None,
+        }),
+        name,
+        fields,
+        unique: options.unique.unwrap_or(false),
+        text,
+        sparse: options.sparse.unwrap_or(false),
+        expire_after_seconds: options.expire_after.map(|d| d.as_secs()),
+        partial_filter: options.partial_filter_expression,
+        keys: model.keys,
+    }
+}
+
+async fn require_collection(db: &Database, collection_name: &str) -> IndexResult<()> {
+    let existing = db.list_collection_names(doc! { "name": collection_name })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if existing.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("Collection '{}' not found", collection_name)));
+    }
+    Ok(())
+}
+
+// Every index of the collection with its size, plus the builds in progress.
+// `admin` is the admin database of the same connection, where currentOp runs.
+pub async fn list_indexes(db: &Database, admin: &Database, collection_name: &str) -> IndexResult<IndexOverview> {
+    Ok(IndexOverview {
+        indexes: stored_indexes(db, collection_name).await?,
+        builds: index_builds(db, admin, collection_name).await,
+    })
+}
+
+// Every index of the collection with its size
+pub async fn stored_indexes(db: &Database, collection_name: &str) -> IndexResult<Vec<IndexInfo>> {
+    require_collection(db, collection_name).await?;
+
+    let models: Vec<IndexModel> = db.collection::<Document>(collection_name)
+        .list_indexes(None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list indexes: {}", e)))?
+        .try_collect()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list indexes: {}", e)))?;
+
+    // Sizes are informational; a server that refuses collStats still gets the list
+    let sizes = db.run_command(doc! { "collStats": collection_name }, None)
+        .await
+        .ok()
+        .and_then(|stats| stats.get_document("indexSizes").ok().cloned());
+
+    Ok(models.into_iter().map(|model| info_from_model(model, sizes.as_ref())).collect())
+}
+
+// Index builds running on the collection, from currentOp. Servers that don't let
+// this user see currentOp report no builds rather than failing the listing.
+async fn index_builds(db: &Database, admin: &Database, collection_name: &str) -> Vec<IndexBuild> {
+    let namespace = format!("{}.{}", db.name(), collection_name);
+    let command = doc! {
+        "currentOp": true,
+        "ns": namespace,
+        "command.createIndexes": { "$exists": true }
+    };
+    let Ok(response) = admin.run_command(command, None).await else {
+        return Vec::new();
+    };
+    let Ok(operations) = response.get_array("inprog") else {
+        return Vec::new();
+    };
+
+    operations.iter()
+        .filter_map(Bson::as_document)
+        .map(|op| {
+            let indexes = op.get_document("command")
+                .and_then(|c| c.get_array("indexes"))
+                .map(|specs| specs.iter()
+                    .filter_map(|spec| spec.as_document()?.get_str("name").ok().map(String::from))
+                    .collect())
+                .unwrap_or_default();
+            let progress = op.get_document("progress").ok();
+            let number = |key: &str| progress
+                .and_then(|p| p.get(key))
+                .and_then(|v| match *v {
+                    Bson::Int32(n) => Some(i64::from(n)),
+                    Bson::Int64(n) => Some(n),
+                    Bson::Double(n) => Some(n as i64),
+                    _ => None,
+                })
+                .unwrap_or(0);
+            IndexBuild {
+                indexes,
+                done: number("done"),
+                total: number("total"),
+                message: op.get_str("msg").unwrap_or_default().to_string(),
+                running_secs: op.get_i64("secs_running").or_else(|_| op.get_i32("secs_running").map(i64::from)).unwrap_or(0),
+            }
+        })
+        .collect()
+}
+
+// Build an index and return it as listed afterwards. A unique index is checked
+// against the stored documents first so a failure names the duplicated values.
+// The call returns when the build finishes; list_indexes shows its progress meanwhile.
+pub async fn create_index(db: &Database, collection_name: &str, definition: &IndexDefinition) -> IndexResult<IndexInfo> {
+    definition.check().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    require_collection(db, collection_name).await?;
+    let collection = db.collection::<Document>(collection_name);
+
+    if definition.unique {
+        let duplicates = find_duplicates(&collection, definition)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        if !duplicates.is_empty() {
+            let examples: Vec<String> = duplicates.iter()
+                .map(|(values, count)| format!("{} ({} documents)", values, count))
+                .collect();
+            return Err((StatusCode::CONFLICT, format!(
+                "Can't create a unique index on ({}): values already appear more than once, e.g. {}",
+                key_fields(definition).join(", "),
+                examples.join("; ")
+            )));
+        }
+    }
+
+    let created = collection.create_index(definition.model(), None)
+        .await
+        .map_err(|e| match server_error_code(&e) {
+            Some(DUPLICATE_KEY) | Some(INDEX_OPTIONS_CONFLICT) | Some(INDEX_KEY_SPECS_CONFLICT) => {
+                (StatusCode::CONFLICT, format!("Failed to create index: {}", e))
+            },
+            _ => (StatusCode::BAD_REQUEST, format!("Failed to create index: {}", e)),
+        })?;
+
+    stored_indexes(db, collection_name).await?
+        .into_iter()
+        .find(|index| index.name == created.index_name)
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, format!("Index '{}' was created but is not listed", created.index_name)))
+}
+
+pub async fn drop_index(db: &Database, collection_name: &str, index_name: &str) -> IndexResult<()> {
+    if index_name == "_id_" {
+        return Err((StatusCode::BAD_REQUEST, "The _id index can't be dropped".to_string()));
+    }
+    let indexes = stored_indexes(db, collection_name).await?;
+    if !indexes.iter().any(|index| index.name == index_name) {
+        return Err((StatusCode::NOT_FOUND, format!("Index '{}' not found on '{}'", index_name, collection_name)));
+    }
+    db.collection::<Document>(collection_name)
+        .drop_index(index_name, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to drop index: {}", e)))
+}
+
+fn key_fields(definition: &IndexDefinition) -> Vec<String> {
+    definition.fields.iter().map(|f| f.trim_start_matches('-').to_string()).collect()
+}
+
+// Key values shared by more than one document (within the partial filter, if any),
+// most repeated first. Missing fields group as null, as they do in the index.
+pub async fn find_duplicates(collection: &Collection<Document>, definition: &IndexDefinition) -> Result<Vec<(Document, i64)>, String> {
+    let fields = key_fields(definition);
+    // Group keys can't contain dots, so key parts are grouped by position
+    let mut group_id = Document::new();
+    for (i, field) in fields.iter().enumerate() {
+        group_id.insert(format!("k{}", i), format!("${}", field));
+    }
+
+    let mut pipeline = Vec::new();
+    if let Some(filter) = &definition.partial_filter {
+        pipeline.push(doc! { "$match": filter.clone() });
+    }
+    pipeline.push(doc! { "$group": { "_id": group_id, "count": { "$sum": 1 } } });
+    pipeline.push(doc! { "$match": { "count": { "$gt": 1 } } });
+    pipeline.push(doc! { "$sort": { "count": -1 } });
+    pipeline.push(doc! { "$limit": DUPLICATE_SAMPLES });
+
+    let options = AggregateOptions::builder().allow_disk_use(true).build();
+    let groups: Vec<Document> = collection.aggregate(pipeline, options)
+        .await
+        .map_err(|e| format!("Failed to check for duplicates: {}", e))?
+        .try_collect()
+        .await
+        .map_err(|e| format!("Failed to check for duplicates: {}", e))?;
+
+    Ok(groups.iter()
+        .map(|group| {
+            let positional = group.get_document("_id").cloned().unwrap_or_default();
+            let values: Document = fields.iter().enumerate()
+                .map(|(i, field)| (field.clone(), positional.get(format!("k{}", i)).cloned().unwrap_or(Bson::Null)))
+                .collect();
+            let count = group.get_i32("count").map(i64::from).or_else(|_| group.get_i64("count")).unwrap_or(0);
+            (values, count)
+        })
+        .collect())
+}
+
+fn server_error_code(error: &Error) -> Option<i32> {
+    match error.kind.as_ref() {
+        ErrorKind::Command(command) => Some(command.code),
+        ErrorKind::Write(mongodb::error::WriteFailure::WriteError(write)) => Some(write.code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(partial_filter: Option<Document>, sparse: bool) -> UniqueKey {
+        UniqueKey { name: "k".to_string(), fields: vec!["a".to_string(), "b.c".to_string()], partial_filter, sparse }
+    }
+
+    #[test]
+    fn reads_key_values_as_the_index_stores_them() {
+        let plain = key(None, false);
+        let sparse = key(None, true);
+        let partial = key(Some(doc! { "a": { "$exists": true } }), false);
+        let cases = [
+            (doc! { "a": 1, "b": { "c": "x" } }, Some(vec![Bson::Int32(1), Bson::String("x".into())]), Some(vec![Bson::Int32(1), Bson::String("x".into())]), Some(vec![Bson::Int32(1), Bson::String("x".into())])),
+            (doc! { "a": 1 }, Some(vec![Bson::Int32(1), Bson::Null]), Some(vec![Bson::Int32(1), Bson::Null]), None),
+            (doc! { "a": 1, "b": { "c": Bson::Null } }, Some(vec![Bson::Int32(1), Bson::Null]), Some(vec![Bson::Int32(1), Bson::Null]), None),
+            (doc! {}, Some(vec![Bson::Null, Bson::Null]), None, None),
+        ];
+        for (doc, expected_plain, expected_sparse, expected_partial) in cases {
+            assert_eq!(plain.values_in(&doc), expected_plain, "plain key of {}", doc);
+            assert_eq!(sparse.values_in(&doc), expected_sparse, "sparse key of {}", doc);
+            assert_eq!(partial.values_in(&doc), expected_partial, "partial key of {}", doc);
+        }
+    }
+}
@@ -0,0 +1,386 @@
+// src/api_server/services/inference_service.rs
+
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use mongodb::Collection;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::api_server::services::coercion_service::insert_path;
+use crate::api_server::services::collection_service::{CollectionDefinition, IndexDefinition};
+use crate::api_server::services::index_service::find_duplicates;
+use crate::mongodb_schema::merge_with_archive_pinned_and_row_height_properties;
+use crate::timezone::InstitutionTimezone;
+
+// Documents sampled from a collection when the request doesn't say
+pub const DEFAULT_SAMPLE_SIZE: i64 = 1000;
+pub const MAX_SAMPLE_SIZE: i64 = 10000;
+
+// A text field with at most this many distinct values is proposed as an enum
+const MAX_ENUM_VALUES: usize = 12;
+
+// Numeric types from narrowest to widest; a field mixing them takes the widest
+const NUMERIC_TYPES: [&str; 4] = ["int", "long", "double", "decimal"];
+
+// Types that make sense as a unique key
+const KEY_TYPES: [&str; 4] = ["string", "int", "long", "objectId"];
+
+// What was seen for one field across the sampled documents
+#[derive(Default)]
+struct FieldStats {
+    // Documents (or array elements) where the field is set, null included
+    present: usize,
+    nulls: usize,
+    types: BTreeMap<&'static str, usize>,
+    // Distinct scalar values, keyed by their extended JSON text
+    distinct: HashMap<String, Bson>,
+    // Text values that read as ISO dates
+    date_strings: usize,
+    // Sub-fields of object values
+    fields: Option<Box<ObjectStats>>,
+    // Elements of array values
+    items: Option<Box<FieldStats>>,
+}
+
+#[derive(Default)]
+struct ObjectStats {
+    count: usize,
+    // In the order the fields were first seen, which becomes the column order
+    fields: Vec<(String, FieldStats)>,
+}
+
+// Accumulates sampled documents and proposes a collection definition for them
+pub struct SchemaInference {
+    root: ObjectStats,
+    timezone: InstitutionTimezone,
+}
+
+impl SchemaInference {
+    pub fn new(timezone: InstitutionTimezone) -> Self {
+        Self { root: ObjectStats::default(), timezone }
+    }
+
+    pub fn observe(&mut self, doc: &Document) {
+        self.root.observe(doc, &self.timezone);
+    }
+
+    pub fn sampled(&self) -> usize {
+        self.root.count
+    }
+
+    // Properties with their bsonType (plus "null" where nulls were seen), required
+    // fields set in every sample, unique indexes for top-level fields whose values
+    // never repeat, and enums for text fields with few distinct values.
+    // The standard fields and _id are left to the collection-creation path.
+    pub fn into_definition(self, name: &str) -> CollectionDefinition {
+        let standard = merge_with_archive_pinned_and_row_height_properties(Document::new());
+        let total = self.root.count;
+
+        let mut properties = Document::new();
+        let mut required = Vec::new();
+        let mut indexes = Vec::new();
+        for (field, stats) in &self.root.fields {
+            if field == "_id" || standard.contains_key(field) {
+                continue;
+            }
+            properties.insert(field.as_str(), stats.spec(total));
+            if stats.is_required(total) {
+                required.push(field.clone());
+            }
+            if stats.is_unique_candidate(total) {
+                indexes.push(IndexDefinition {
+                    fields: vec![field.clone()],
+                    unique: true,
+                    name: None,
+                    text: false,
+                    expire_after_seconds: None,
+                    partial_filter: None,
+                });
+            }
+        }
+
+        CollectionDefinition {
+            name: name.to_string(),
+            properties,
+            required,
+            indexes,
+            standard_fields: true,
+            validation_level: "moderate".to_string(),
+            validation_action: "error".to_string(),
+            sort: None,
+            columns: None,
+            column_widths: HashMap::new(),
+        }
+    }
+}
+
+impl ObjectStats {
+    fn observe(&mut self, doc: &Document, timezone: &InstitutionTimezone) {
+        self.count += 1;
+        for (key, value) in doc {
+            let position = match self.fields.iter().position(|(k, _)| k == key) {
+                Some(position) => position,
+                None => {
+                    self.fields.push((key.clone(), FieldStats::default()));
+                    self.fields.len() - 1
+                },
+            };
+            self.fields[position].1.observe(value, timezone);
+        }
+    }
+
+    fn properties_and_required(&self) -> (Document, Vec<String>) {
+        let mut properties = Document::new();
+        let mut required = Vec::new();
+        for (field, stats) in &self.fields {
+            properties.insert(field.as_str(), stats.spec(self.count));
+            if stats.is_required(self.count) {
+                required.push(field.clone());
+            }
+        }
+        (properties, required)
+    }
+}
+
+impl FieldStats {
+    fn observe(&mut self, value: &Bson, timezone: &InstitutionTimezone) {
+        self.present += 1;
+        let alias = type_alias(value);
+        if alias == "null" {
+            self.nulls += 1;
+            return;
+        }
+        *self.types.entry(alias).or_default() += 1;
+
+        match value {
+            Bson::Document(doc) => self.fields.get_or_insert_with(Default::default).observe(doc, timezone),
+            Bson::Array(items) => {
+                let item_stats = self.items.get_or_insert_with(Default::default);
+                for item in items {
+                    item_stats.observe(item, timezone);
+                }
+            },
+            _ => {
+                if let Bson::String(text) = value {
+                    if looks_like_date(text, timezone) {
+                        self.date_strings += 1;
+                    }
+                }
+                self.distinct.entry(value.to_string()).or_insert_with(|| value.clone());
+            },
+        }
+    }
+
+    fn non_null(&self) -> usize {
+        self.present - self.nulls
+    }
+
+    fn is_required(&self, total: usize) -> bool {
+        total > 0 && self.non_null() == total
+    }
+
+    // Set in every sample, never repeated, and of a type that works as a key
+    fn is_unique_candidate(&self, total: usize) -> bool {
+        total > 1
+            && self.is_required(total)
+            && self.distinct.len() == total
+            && self.types.keys().all(|t| KEY_TYPES.contains(t))
+    }
+
+    // Types seen, with mixed numbers folded into the widest of them
+    fn merged_types(&self) -> Vec<&'static str> {
+        let mut types: Vec<&'static str> = self.types.keys().copied().collect();
+        if types.len() > 1 && types.iter().all(|t| NUMERIC_TYPES.contains(t)) {
+            let widest = NUMERIC_TYPES.iter().rev().find(|t| types.contains(t)).copied().unwrap();
+            types = vec![widest];
+        }
+        types
+    }
+
+    fn spec(&self, total: usize) -> Document {
+        let mut types = self.merged_types();
+        let nullable = self.nulls > 0;
+        if types.is_empty() {
+            // Only nulls were seen; nothing to go on but the field's existence
+            types.push("string");
+        }
+        let mut spec = Document::new();
+        let mut bson_types: Vec<&str> = types.clone();
+        if nullable {
+            bson_types.push("null");
+        }
+        match bson_types.as_slice() {
+            [single] => spec.insert("bsonType", *single),
+            _ => spec.insert("bsonType", bson_types.clone()),
+        };
+
+        if types == ["object"] {
+            if let Some(fields) = &self.fields {
+                let (properties, required) = fields.properties_and_required();
+                if !required.is_empty() {
+                    spec.insert("required", required);
+                }
+                spec.insert("properties", properties);
+            }
+        }
+        if types == ["array"] {
+            if let Some(items) = self.items.as_ref().filter(|items| items.present > 0) {
+                spec.insert("items", items.spec(items.present));
+            }
+        }
+        if let Some(values) = self.enum_candidate(&types) {
+            spec.insert("enum", values);
+        }
+
+        let mut description = format!("Inferred: set in {} of {} sampled", self.non_null(), total);
+        if nullable {
+            description.push_str(&format!(", null in {}", self.nulls));
+        }
+        if types == ["string"] && self.date_strings > 0 && self.date_strings == self.non_null() {
+            description.push_str("; every value reads as a date, consider bsonType date");
+        }
+        spec.insert("description", description);
+        spec
+    }
+
+    // Few distinct text values, each used more than once on average
+    fn enum_candidate(&self, types: &[&str]) -> Option<Vec<Bson>> {
+        let distinct = self.distinct.len();
+        if types != ["string"] || !(2..=MAX_ENUM_VALUES).contains(&distinct) || self.non_null() < distinct * 2 {
+            return None;
+        }
+        let mut values: Vec<Bson> = self.distinct.values().cloned().collect();
+        values.sort_by_key(|v| v.as_str().unwrap_or_default().to_string());
+        // The validator checks enum against null values too
+        if self.nulls > 0 {
+            values.push(Bson::Null);
+        }
+        Some(values)
+    }
+}
+
+// The $jsonSchema bsonType alias for a value
+fn type_alias(value: &Bson) -> &'static str {
+    match value {
+        Bson::Null | Bson::Undefined => "null",
+        Bson::String(_) => "string",
+        Bson::Int32(_) => "int",
+        Bson::Int64(_) => "long",
+        Bson::Double(_) => "double",
+        Bson::Decimal128(_) => "decimal",
+        Bson::Boolean(_) => "bool",
+        Bson::DateTime(_) => "date",
+        Bson::ObjectId(_) => "objectId",
+        Bson::Document(_) => "object",
+        Bson::Array(_) => "array",
+        Bson::Binary(_) => "binData",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::RegularExpression(_) => "regex",
+        _ => "string",
+    }
+}
+
+// Only text starting with a four-digit year is tried, so codes like "1-2" stay text
+fn looks_like_date(text: &str, timezone: &InstitutionTimezone) -> bool {
+    let bytes = text.trim().as_bytes();
+    bytes.len() >= 10
+        && bytes[..4].iter().all(u8::is_ascii_digit)
+        && bytes[4] == b'-'
+        && timezone.parse_datetime(text).is_ok()
+}
+
+// Read a CSV cell as the value it most likely holds. Numbers with leading zeros
+// stay text since they are usually codes, not quantities.
+pub fn guess_text(text: &str, timezone: &InstitutionTimezone) -> Bson {
+    let text = text.trim();
+    if text.is_empty() {
+        return Bson::Null;
+    }
+    match text.to_lowercase().as_str() {
+        "true" => return Bson::Boolean(true),
+        "false" => return Bson::Boolean(false),
+        _ => {},
+    }
+
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+    if !leading_zero {
+        if let Ok(int) = text.parse::<i64>() {
+            return match i32::try_from(int) {
+                Ok(small) => Bson::Int32(small),
+                Err(_) => Bson::Int64(int),
+            };
+        }
+        if digits.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '-' | '+')) {
+            if let Ok(double) = text.parse::<f64>() {
+                return Bson::Double(double);
+            }
+        }
+    }
+    if text.len() == 24 {
+        if let Ok(id) = ObjectId::parse_str(text) {
+            return Bson::ObjectId(id);
+        }
+    }
+    if looks_like_date(text, timezone) {
+        if let Ok(date) = timezone.parse_datetime(text) {
+            return Bson::DateTime(date);
+        }
+    }
+    Bson::String(text.to_string())
+}
+
+// Build a document from one staged CSV row, guessing each cell's type.
+// Dotted columns rebuild nested values; empty cells are left out of them.
+pub fn document_from_text(cells: &[(String, String)], timezone: &InstitutionTimezone) -> Document {
+    let mut doc = Document::new();
+    for (column, cell) in cells {
+        let value = guess_text(cell, timezone);
+        if column.contains('.') {
+            if value != Bson::Null {
+                insert_path(&mut doc, column, value);
+            }
+        } else {
+            doc.insert(column.as_str(), value);
+        }
+    }
+    doc
+}
+
+// Propose a definition for an existing collection from a random sample of its documents
+pub async fn infer_from_collection(
+    collection: &Collection<Document>,
+    sample_size: i64,
+    timezone: InstitutionTimezone,
+) -> Result<CollectionDefinition, String> {
+    let mut cursor = collection
+        .aggregate(vec![doc! { "$sample": { "size": sample_size } }], None)
+        .await
+        .map_err(|e| format!("Failed to sample documents: {}", e))?;
+
+    let mut inference = SchemaInference::new(timezone);
+    while let Some(document) = cursor.try_next().await.map_err(|e| format!("Failed to sample documents: {}", e))? {
+        inference.observe(&document);
+    }
+    if inference.sampled() == 0 {
+        return Err(format!("Collection '{}' has no documents to infer a schema from", collection.name()));
+    }
+    let sampled = inference.sampled() as u64;
+    let mut definition = inference.into_definition(collection.name());
+
+    // Values that never repeat in a sample may still repeat in the rest of the
+    // collection, so unless the sample covered every document the proposed unique
+    // keys are kept only when the whole collection has no duplicates
+    let total = collection.count_documents(None, None)
+        .await
+        .map_err(|e| format!("Failed to count documents: {}", e))?;
+    if sampled < total {
+        let mut confirmed = Vec::new();
+        for index in std::mem::take(&mut definition.indexes) {
+            if !index.unique || find_duplicates(collection, &index).await?.is_empty() {
+                confirmed.push(index);
+            }
+        }
+        definition.indexes = confirmed;
+    }
+    Ok(definition)
+}
@@ -0,0 +1,311 @@
+// src/api_server/services/lifecycle_service.rs
+
+use axum::http::StatusCode;
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::Database;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::api_server::events::{ChangeAction, EventBus};
+use crate::api_server::services::collection_service::check_collection_name;
+use crate::api_server::services::reference_service::{find_referencing_fields, plan_collection_removal, RemovalPlan};
+use crate::api_server::services::schema_cache::SchemaCache;
+use crate::api_server::services::snapshot_service::{
+    collection_options, copy_documents, create_index_specs, index_specs, is_snapshot_collection,
+    take_snapshot, SNAPSHOTS_COLLECTION,
+};
+use crate::migrations::{GENDER_TEXT_COLLECTION, MIGRATIONS_COLLECTION};
+
+// Collections the app itself depends on; they can't be renamed, cloned, emptied or dropped
+pub const PROTECTED_COLLECTIONS: [&str; 6] = ["users", "sessions", "ui_metadata", MIGRATIONS_COLLECTION, GENDER_TEXT_COLLECTION, SNAPSHOTS_COLLECTION];
+
+// How long a confirmation token can be used
+const CONFIRMATION_TTL: Duration = Duration::from_secs(300);
+
+type LifecycleResult<T> = Result<T, (StatusCode, String)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LifecycleOperation {
+    Rename { collection: String, to: String },
+    Clone { collection: String, to: String, with_documents: bool },
+    Truncate { collection: String },
+    Drop { collection: String },
+}
+
+// Tokens handed out and not used yet, with the operation each allows and when it expires
+pub type PendingConfirmations = HashMap<String, (LifecycleOperation, Instant)>;
+
+#[derive(Debug, Serialize)]
+pub struct ConfirmationRequest {
+    pub confirm_token: String,
+    pub summary: String,
+    pub expires_in_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LifecycleOutcome {
+    pub summary: String,
+    // Snapshot taken before a truncate or drop
+    pub snapshot: Option<String>,
+}
+
+impl LifecycleOperation {
+    pub fn collection(&self) -> &str {
+        match self {
+            Self::Rename { collection, .. }
+            | Self::Clone { collection, .. }
+            | Self::Truncate { collection }
+            | Self::Drop { collection } => collection,
+        }
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Self::Rename { to, .. } | Self::Clone { to, .. } => Some(to),
+            _ => None,
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            Self::Rename { .. } => "rename",
+            Self::Clone { .. } => "clone",
+            Self::Truncate { .. } => "truncate",
+            Self::Drop { .. } => "drop",
+        }
+    }
+
+    fn summary(&self, documents: u64) -> String {
+        match self {
+            Self::Rename { collection, to } => format!("Rename '{}' ({} documents) to '{}'", collection, documents, to),
+            Self::Clone { collection, to, with_documents: true } => format!("Copy '{}' ({} documents) to '{}'", collection, documents, to),
+            Self::Clone { collection, to, with_documents: false } => {
+                format!("Copy the schema, indexes and table settings of '{}' to '{}', without documents", collection, to)
+            },
+            Self::Truncate { collection } => {
+                format!("Delete all {} documents of '{}'; a snapshot is taken first", documents, collection)
+            },
+            Self::Drop { collection } => {
+                format!("Drop '{}' with its {} documents and table settings; a snapshot is taken first", collection, documents)
+            },
+        }
+    }
+}
+
+pub fn is_protected(name: &str) -> bool {
+    PROTECTED_COLLECTIONS.contains(&name) || is_snapshot_collection(name) || name.starts_with("system.")
+}
+
+async fn collection_exists(db: &Database, name: &str) -> LifecycleResult<bool> {
+    db.list_collection_names(doc! { "name": name })
+        .await
+        .map(|names| !names.is_empty())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// Check the operation can run and return the source's document count
+async fn check(schema_cache: &SchemaCache, db: &Database, operation: &LifecycleOperation) -> LifecycleResult<u64> {
+    let collection = operation.collection();
+    if is_protected(collection) {
+        return Err((StatusCode::FORBIDDEN, format!("Collection '{}' is protected and can't be {}d", collection, operation.verb())));
+    }
+    if !collection_exists(db, collection).await? {
+        return Err((StatusCode::NOT_FOUND, format!("Collection '{}' not found", collection)));
+    }
+    if let LifecycleOperation::Rename { collection, .. } = operation {
+        // REF:<name> annotations would be left pointing at a collection that no longer exists
+        let referencing = find_referencing_fields(schema_cache, db, collection)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        if !referencing.is_empty() {
+            let fields: Vec<String> = referencing.iter().map(|(source, r)| format!("{}.{}", source, r.field)).collect();
+            return Err((StatusCode::CONFLICT, format!(
+                "Collection '{}' is referenced by {}; change those references before renaming it",
+                collection, fields.join(", ")
+            )));
+        }
+    }
+    if let Some(target) = operation.target() {
+        check_collection_name(target).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if is_protected(target) {
+            return Err((StatusCode::FORBIDDEN, format!("'{}' is a reserved collection name", target)));
+        }
+        if collection_exists(db, target).await? {
+            return Err((StatusCode::CONFLICT, format!("Collection '{}' already exists", target)));
+        }
+    }
+    db.collection::<Document>(collection)
+        .estimated_document_count(None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// First step: check the operation and hand out a single-use token that allows it
+pub async fn request_confirmation(
+    pending: &Mutex<PendingConfirmations>,
+    schema_cache: &SchemaCache,
+    db: &Database,
+    operation: LifecycleOperation,
+) -> LifecycleResult<ConfirmationRequest> {
+    let documents = check(schema_cache, db, &operation).await?;
+    // Refuse up front what the reference policies would refuse on execution
+    removal_plan(schema_cache, db, &operation).await?;
+    let summary = operation.summary(documents);
+    let token = Uuid::new_v4().to_string();
+
+    let mut pending = pending.lock().await;
+    let now = Instant::now();
+    pending.retain(|_, (_, expires_at)| *expires_at > now);
+    pending.insert(token.clone(), (operation, now + CONFIRMATION_TTL));
+
+    Ok(ConfirmationRequest { confirm_token: token, summary, expires_in_secs: CONFIRMATION_TTL.as_secs() })
+}
+
+// Second step: use up the token, which must have been issued for this exact operation
+pub async fn confirm(pending: &Mutex<PendingConfirmations>, token: &str, operation: &LifecycleOperation) -> LifecycleResult<()> {
+    let issued = pending.lock().await.remove(token);
+    match issued {
+        Some((allowed, expires_at)) if allowed == *operation && expires_at > Instant::now() => Ok(()),
+        Some((allowed, _)) if allowed != *operation => {
+            Err((StatusCode::PRECONDITION_FAILED, "The confirmation token was issued for a different operation".to_string()))
+        },
+        _ => Err((StatusCode::PRECONDITION_FAILED, "Invalid or expired confirmation token; request a new one".to_string())),
+    }
+}
+
+// Run a confirmed operation. `admin` is the admin database of the same connection,
+// where renameCollection runs.
+pub async fn execute(
+    schema_cache: &SchemaCache,
+    db: &Database,
+    admin: &Database,
+    operation: &LifecycleOperation,
+    events: &EventBus,
+) -> LifecycleResult<LifecycleOutcome> {
+    let documents = check(schema_cache, db, operation).await?;
+    let summary = operation.summary(documents);
+    let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+    let ui_metadata = db.collection::<Document>("ui_metadata");
+    let removal = removal_plan(schema_cache, db, operation).await?;
+
+    let snapshot = match operation {
+        LifecycleOperation::Truncate { collection } | LifecycleOperation::Drop { collection } => {
+            Some(take_snapshot(db, collection, operation.verb()).await.map_err(internal)?)
+        },
+        _ => None,
+    };
+
+    match operation {
+        LifecycleOperation::Rename { collection, to } => {
+            admin.run_command(
+                doc! {
+                    "renameCollection": format!("{}.{}", db.name(), collection),
+                    "to": format!("{}.{}", db.name(), to),
+                    "dropTarget": false
+                },
+                None
+            )
+            .await
+            .map_err(|e| internal(format!("Failed to rename collection: {}", e)))?;
+            ui_metadata.update_many(
+                doc! { "collection": collection.as_str() },
+                doc! { "$set": { "collection": to.as_str(), "updated_at": mongodb::bson::DateTime::now() } },
+                None
+            )
+            .await
+            .map_err(|e| internal(format!("Collection renamed, but its UI metadata was not: {}", e)))?;
+        },
+        LifecycleOperation::Clone { collection, to, with_documents } => {
+            if let Err(e) = clone_collection(db, collection, to, *with_documents).await {
+                // Leave nothing half-copied behind
+                let _ = db.collection::<Document>(to).drop(None).await;
+                let _ = ui_metadata.delete_many(doc! { "collection": to.as_str() }, None).await;
+                return Err(internal(e));
+            }
+        },
+        LifecycleOperation::Truncate { collection } => {
+            db.collection::<Document>(collection)
+                .delete_many(doc! {}, None)
+                .await
+                .map_err(|e| internal(format!("Failed to empty collection: {}", e)))?;
+        },
+        LifecycleOperation::Drop { collection } => {
+            db.collection::<Document>(collection)
+                .drop(None)
+                .await
+                .map_err(|e| internal(format!("Failed to drop collection: {}", e)))?;
+            ui_metadata.delete_many(doc! { "collection": collection.as_str() }, None)
+                .await
+                .map_err(|e| internal(format!("Collection dropped, but its UI metadata was not: {}", e)))?;
+        },
+    }
+
+    if let Some(plan) = removal {
+        let action = if matches!(operation, LifecycleOperation::Drop { .. }) { ChangeAction::Drop } else { ChangeAction::Truncate };
+        events.publish_collection(operation.collection(), action);
+        plan.apply(db, None, events)
+            .await
+            .map_err(|e| internal(format!("Removed {} document(s), but applying reference policies failed: {}", documents, e)))?;
+    }
+
+    info!("{}", summary);
+    Ok(LifecycleOutcome { summary, snapshot })
+}
+
+// For a truncate or drop, the reference policies to apply to the documents that
+// referenced the collection; fails when a restrict policy blocks the removal
+async fn removal_plan(schema_cache: &SchemaCache, db: &Database, operation: &LifecycleOperation) -> LifecycleResult<Option<RemovalPlan>> {
+    let (LifecycleOperation::Truncate { collection } | LifecycleOperation::Drop { collection }) = operation else {
+        return Ok(None);
+    };
+    let plan = plan_collection_removal(schema_cache, db, collection)
+        .await
+        .map_err(|e| (e.status_code(), e.message()))?;
+    Ok(Some(plan))
+}
+
+// New collection with the source's options (validator included), indexes and
+// ui_metadata entries, and optionally its documents
+async fn clone_collection(db: &Database, collection: &str, to: &str, with_documents: bool) -> Result<(), String> {
+    let mut create = doc! { "create": to };
+    for (key, value) in collection_options(db, collection).await? {
+        create.insert(key, value);
+    }
+    db.run_command(create, None)
+        .await
+        .map_err(|e| format!("Failed to create '{}': {}", to, e))?;
+
+    if with_documents {
+        copy_documents(db, collection, to).await?;
+    }
+    create_index_specs(db, to, &index_specs(db, collection).await?).await?;
+
+    let ui_metadata = db.collection::<Document>("ui_metadata");
+    let now = mongodb::bson::DateTime::now();
+    let entries: Vec<Document> = ui_metadata.find(doc! { "collection": collection }, None)
+        .await
+        .map_err(|e| format!("Failed to read UI metadata: {}", e))?
+        .try_collect::<Vec<Document>>()
+        .await
+        .map_err(|e| format!("Failed to read UI metadata: {}", e))?
+        .into_iter()
+        .map(|mut entry| {
+            entry.remove("_id");
+            entry.insert("collection", to);
+            entry.insert("created_at", now);
+            entry.insert("updated_at", now);
+            entry
+        })
+        .collect();
+    if !entries.is_empty() {
+        ui_metadata.insert_many(entries, None)
+            .await
+            .map_err(|e| format!("Failed to copy UI metadata: {}", e))?;
+    }
+    Ok(())
+}
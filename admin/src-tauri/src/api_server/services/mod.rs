@@ -4,13 +4,26 @@
 pub mod database_service;
 pub mod auth_service;
 pub mod schema_service;
+pub mod schema_cache;
+pub mod reference_service;
+pub mod coercion_service;
+pub mod defaults_service;
+pub mod validation_service;
+pub mod schema_editor_service;
+pub mod collection_service;
+pub mod inference_service;
+pub mod index_service;
+pub mod snapshot_service;
+pub mod lifecycle_service;
+pub mod export_service;
+pub mod schema_export_service;
+pub mod dictionary_service;
+pub mod xlsx_writer;
 
 pub use auth_service::{
     login_user,
     register_user,
 };
 
-pub use schema_service::{
-    get_collection_schema_with_ui,
-    update_ui_metadata,
-};
\ No newline at end of file
+pub use schema_service::update_ui_metadata;
+pub use schema_cache::SchemaCache;
\ No newline at end of file
@@ -0,0 +1,426 @@
+// src/api_server/services/reference_service.rs
+
+use axum::http::StatusCode;
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    options::FindOptions,
+    Database,
+};
+use tracing::{info, warn};
+
+use crate::api_server::events::{id_text, ChangeAction, EventBus};
+use crate::api_server::services::schema_cache::SchemaCache;
+
+// Maximum number of blocking document ids reported per referencing field
+const MAX_REPORTED_BLOCKERS: i64 = 10;
+
+// What happens to referencing documents when the referenced document goes away.
+// Declared next to the REF marker in the field description, e.g.
+// "REF:semesters | ON_DELETE:nullify | Reference to last updated semester".
+// Fields without an ON_DELETE marker default to restrict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletePolicy {
+    Restrict,
+    Nullify,
+    CascadeArchive,
+}
+
+impl DeletePolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().replace('-', "_").as_str() {
+            "restrict" => Some(Self::Restrict),
+            "nullify" => Some(Self::Nullify),
+            "cascade_archive" => Some(Self::CascadeArchive),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Restrict => "restrict",
+            Self::Nullify => "nullify",
+            Self::CascadeArchive => "cascade_archive",
+        }
+    }
+}
+
+// Whether the referenced documents are being deleted or only archived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalKind {
+    Delete,
+    Archive,
+}
+
+// A top-level schema property annotated with REF:<collection>
+#[derive(Debug, Clone)]
+pub struct ReferenceField {
+    pub field: String,
+    pub target: String,
+    pub policy: DeletePolicy,
+    pub is_array: bool,
+}
+
+// Documents that prevent a delete or archive under the restrict policy
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReferenceBlocker {
+    pub collection: String,
+    pub field: String,
+    pub count: u64,
+    pub document_ids: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ReferenceError {
+    Blocked(Vec<ReferenceBlocker>),
+    Database(String),
+}
+
+impl ReferenceError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ReferenceError::Blocked(_) => StatusCode::CONFLICT,
+            ReferenceError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ReferenceError::Blocked(blockers) => {
+                let parts: Vec<String> = blockers.iter().map(|blocker| {
+                    let more = if blocker.count > blocker.document_ids.len() as u64 { ", ..." } else { "" };
+                    format!(
+                        "{} document(s) in {}.{} [{}{}]",
+                        blocker.count, blocker.collection, blocker.field,
+                        blocker.document_ids.join(", "), more
+                    )
+                }).collect();
+                format!("Document is still referenced by {}", parts.join("; "))
+            },
+            ReferenceError::Database(e) => e.clone(),
+        }
+    }
+}
+
+// Parse "REF:<collection> | ON_DELETE:<policy> | ..." from a field description
+pub fn parse_reference(description: &str) -> Option<(String, DeletePolicy)> {
+    let mut target = None;
+    let mut policy = DeletePolicy::Restrict;
+
+    for part in description.split('|').map(str::trim) {
+        if let Some(name) = part.strip_prefix("REF:") {
+            target = Some(name.trim().to_string());
+        } else if let Some(value) = part.strip_prefix("ON_DELETE:") {
+            match DeletePolicy::parse(value) {
+                Some(parsed) => policy = parsed,
+                None => warn!("Unknown ON_DELETE policy '{}', falling back to restrict", value.trim()),
+            }
+        }
+    }
+
+    target.filter(|t| !t.is_empty()).map(|t| (t, policy))
+}
+
+// Collect the REF: annotated top-level properties of a collection schema
+pub fn extract_references(schema: &Document) -> Vec<ReferenceField> {
+    let properties = match schema.get_document("properties") {
+        Ok(p) => p,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut references = Vec::new();
+    for (field, spec) in properties {
+        let spec_doc = match spec.as_document() {
+            Some(d) => d,
+            None => continue,
+        };
+
+        if let Some((target, policy)) = spec_doc.get_str("description").ok().and_then(parse_reference) {
+            references.push(ReferenceField { field: field.clone(), target, policy, is_array: false });
+            continue;
+        }
+
+        // Arrays of references carry the marker on their items
+        if spec_doc.get_str("bsonType") == Ok("array") {
+            let item_reference = spec_doc.get_document("items").ok()
+                .and_then(|items| items.get_str("description").ok())
+                .and_then(parse_reference);
+            if let Some((target, policy)) = item_reference {
+                references.push(ReferenceField { field: field.clone(), target, policy, is_array: true });
+            }
+        }
+    }
+
+    references
+}
+
+// A reference may be stored either as an ObjectId or as its hex string
+fn reference_candidates(value: &Bson) -> Vec<Bson> {
+    match value {
+        Bson::ObjectId(oid) => vec![Bson::ObjectId(*oid), Bson::String(oid.to_hex())],
+        Bson::String(s) => match ObjectId::parse_str(s) {
+            Ok(oid) => vec![Bson::ObjectId(oid), Bson::String(s.clone())],
+            Err(_) => vec![Bson::String(s.clone())],
+        },
+        other => vec![other.clone()],
+    }
+}
+
+fn display_reference(value: &Bson) -> String {
+    match value {
+        Bson::String(s) => s.clone(),
+        Bson::ObjectId(oid) => oid.to_hex(),
+        other => other.to_string(),
+    }
+}
+
+// Check that every REF: field present in the document points at an existing document
+pub async fn validate_references(db: &Database, schema: &Document, doc: &Document) -> Result<(), String> {
+    let mut missing = Vec::new();
+
+    for reference in extract_references(schema) {
+        let values: Vec<&Bson> = match doc.get(&reference.field) {
+            None | Some(Bson::Null) => continue,
+            Some(Bson::String(s)) if s.is_empty() => continue,
+            Some(Bson::Array(items)) if reference.is_array => items.iter().collect(),
+            Some(value) => vec![value],
+        };
+
+        let target = db.collection::<Document>(&reference.target);
+        for value in values {
+            let filter = doc! { "_id": { "$in": reference_candidates(value) } };
+            let count = target.count_documents(filter, None)
+                .await
+                .map_err(|e| format!("Failed to check reference '{}': {}", reference.field, e))?;
+
+            if count == 0 {
+                missing.push(format!(
+                    "Field '{}' references missing {} document '{}'",
+                    reference.field, reference.target, display_reference(value)
+                ));
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing.join("; "))
+    }
+}
+
+// Validate references using the collection's current schema
+pub async fn validate_document_references(
+    schema_cache: &SchemaCache,
+    db: &Database,
+    collection_name: &str,
+    doc: &Document,
+) -> Result<(), String> {
+    let schema = schema_cache.schema(db, collection_name).await?;
+    validate_references(db, &schema, doc).await
+}
+
+// Find every (collection, field) that references the given collection
+pub async fn find_referencing_fields(
+    schema_cache: &SchemaCache,
+    db: &Database,
+    target_collection: &str,
+) -> Result<Vec<(String, ReferenceField)>, String> {
+    let schemas = schema_cache.all_schemas(db).await?;
+
+    Ok(schemas.iter()
+        .flat_map(|(name, schema)| {
+            extract_references(schema).into_iter()
+                .filter(|reference| reference.target == target_collection)
+                .map(move |reference| (name.clone(), reference))
+        })
+        .collect())
+}
+
+// A removal that has passed the restrict checks, ready to apply the remaining policies
+pub struct RemovalPlan {
+    kind: RemovalKind,
+    removed: Removed,
+    referencing: Vec<(String, ReferenceField)>,
+}
+
+// The referenced documents going away
+enum Removed {
+    // `ids` as stored; `candidates` as a reference may hold them (ObjectIds also as hex text)
+    Ids { ids: Vec<Bson>, candidates: Vec<Bson> },
+    // Every document of the collection, which isn't listed id by id
+    Collection,
+}
+
+impl Removed {
+    // Matches the documents whose `field` references a removed document
+    fn reference_filter(&self, field: &str) -> Document {
+        match self {
+            Self::Ids { candidates, .. } => doc! { field: { "$in": candidates.clone() } },
+            Self::Collection => doc! { field: { "$exists": true, "$nin": [Bson::Null, Bson::Array(Vec::new())] } },
+        }
+    }
+}
+
+// Check the restrict policies for removing `ids` from `collection_name`.
+// Nothing is written here; call `apply` once the removal itself succeeded.
+pub async fn plan_removal(
+    schema_cache: &SchemaCache,
+    db: &Database,
+    collection_name: &str,
+    ids: &[ObjectId],
+    kind: RemovalKind,
+) -> Result<RemovalPlan, ReferenceError> {
+    // References may hold an ObjectId or its hex text
+    let candidates: Vec<Bson> = ids.iter()
+        .flat_map(|id| [Bson::ObjectId(*id), Bson::String(id.to_hex())])
+        .collect();
+    let ids: Vec<Bson> = ids.iter().map(|id| Bson::ObjectId(*id)).collect();
+    plan(schema_cache, db, collection_name, Removed::Ids { ids, candidates }, kind).await
+}
+
+// `plan_removal` for emptying or dropping `collection_name`: every reference to it
+// counts, so no _ids have to be read
+pub async fn plan_collection_removal(
+    schema_cache: &SchemaCache,
+    db: &Database,
+    collection_name: &str,
+) -> Result<RemovalPlan, ReferenceError> {
+    plan(schema_cache, db, collection_name, Removed::Collection, RemovalKind::Delete).await
+}
+
+async fn plan(
+    schema_cache: &SchemaCache,
+    db: &Database,
+    collection_name: &str,
+    removed: Removed,
+    kind: RemovalKind,
+) -> Result<RemovalPlan, ReferenceError> {
+    let referencing = find_referencing_fields(schema_cache, db, collection_name)
+        .await
+        .map_err(ReferenceError::Database)?;
+
+    let mut blockers = Vec::new();
+    for (source, reference) in referencing.iter().filter(|(_, r)| r.policy == DeletePolicy::Restrict) {
+        let mut filter = removed.reference_filter(&reference.field);
+
+        // Self-references among the documents being removed don't block
+        if source == collection_name {
+            match &removed {
+                Removed::Ids { ids, .. } => {
+                    filter.insert("_id", doc! { "$nin": ids.clone() });
+                },
+                Removed::Collection => continue,
+            }
+        }
+        // Archived documents only block deletion, not archiving
+        if kind == RemovalKind::Archive {
+            filter.insert("is_archive", doc! { "$ne": true });
+        }
+
+        let collection = db.collection::<Document>(source);
+        let count = collection.count_documents(filter.clone(), None)
+            .await
+            .map_err(|e| ReferenceError::Database(format!("Failed to check references in {}: {}", source, e)))?;
+
+        if count > 0 {
+            let options = FindOptions::builder()
+                .projection(doc! { "_id": 1 })
+                .limit(MAX_REPORTED_BLOCKERS)
+                .build();
+            let cursor = collection.find(filter, options)
+                .await
+                .map_err(|e| ReferenceError::Database(e.to_string()))?;
+            let docs: Vec<Document> = cursor.try_collect()
+                .await
+                .map_err(|e| ReferenceError::Database(e.to_string()))?;
+
+            blockers.push(ReferenceBlocker {
+                collection: source.clone(),
+                field: reference.field.clone(),
+                count,
+                document_ids: docs.iter()
+                    .filter_map(|d| d.get_object_id("_id").ok().map(|oid| oid.to_hex()))
+                    .collect(),
+            });
+        }
+    }
+
+    if !blockers.is_empty() {
+        warn!("Removal from {} blocked by references: {:?}", collection_name, blockers);
+        return Err(ReferenceError::Blocked(blockers));
+    }
+
+    Ok(RemovalPlan { kind, removed, referencing })
+}
+
+impl RemovalPlan {
+    // Apply nullify (deletion only) and cascade-archive to the referencing documents,
+    // publishing the documents each policy changed
+    pub async fn apply(&self, db: &Database, user_id: Option<ObjectId>, events: &EventBus) -> Result<(), String> {
+        let now = mongodb::bson::DateTime::now();
+
+        for (source, reference) in &self.referencing {
+            // A collection that was emptied or dropped has nothing left to update
+            if matches!(self.removed, Removed::Collection) && source == &reference.target {
+                continue;
+            }
+            let collection = db.collection::<Document>(source);
+            let field = reference.field.as_str();
+            let mut filter = self.removed.reference_filter(field);
+
+            let (action, update) = match (reference.policy, self.kind) {
+                (DeletePolicy::Nullify, RemovalKind::Delete) => (ChangeAction::Update, {
+                    match &self.removed {
+                        Removed::Ids { candidates, .. } if reference.is_array => {
+                            doc! { "$pull": { field: { "$in": candidates.clone() } } }
+                        },
+                        Removed::Collection if reference.is_array => doc! { "$set": { field: [] } },
+                        _ => doc! { "$unset": { field: "" } },
+                    }
+                }),
+                (DeletePolicy::CascadeArchive, _) => (ChangeAction::Archive, {
+                    filter.insert("is_archive", doc! { "$ne": true });
+                    let mut update = doc! { "$set": { "is_archive": true } };
+                    if let Some(user_oid) = user_id {
+                        update.insert("$push", doc! {
+                            "archive_history": {
+                                "action": "archive",
+                                "user_id": user_oid,
+                                "timestamp": now
+                            }
+                        });
+                    }
+                    update
+                }),
+                // Restrict was enforced in plan_removal; archiving never nullifies
+                _ => continue,
+            };
+
+            // Pin down the documents first, so the ones updated can be published
+            let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+            let matched: Vec<Document> = collection.find(filter, options)
+                .await
+                .map_err(|e| format!("Failed to find references in {}.{}: {}", source, field, e))?
+                .try_collect()
+                .await
+                .map_err(|e| format!("Failed to find references in {}.{}: {}", source, field, e))?;
+            let ids: Vec<Bson> = matched.into_iter().filter_map(|d| d.get("_id").cloned()).collect();
+            if ids.is_empty() {
+                continue;
+            }
+
+            let result = collection.update_many(doc! { "_id": { "$in": ids.clone() } }, update, None)
+                .await
+                .map_err(|e| format!("Failed to update references in {}.{}: {}", source, field, e))?;
+            let changed = ids.iter().map(id_text).collect();
+            events.publish(source, action, changed, user_id.map(|oid| oid.to_hex()));
+            info!(
+                "Applied {:?} to {} document(s) in {}.{}",
+                reference.policy, result.modified_count, source, field
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+// src/api_server/services/schema_cache.rs
+
+use mongodb::bson::{doc, Document};
+use mongodb::Database;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::api_server::services::defaults_service::{defaults_from_entry, effective_defaults, FieldDefaults};
+use crate::api_server::services::schema_service::{fetch_collection_schema, list_collection_schemas, merge_ui_metadata};
+
+// Seconds a cached schema is trusted; changes made outside the app (mongosh,
+// another instance) show up after at most this long
+const TTL_ENV: &str = "SCHEMA_CACHE_TTL_SECS";
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+// A collection's $jsonSchema (or why it has none) and its global ui_metadata entry
+#[derive(Clone)]
+struct CachedSchema {
+    schema: Result<Document, String>,
+    ui_entry: Option<Document>,
+    loaded_at: Instant,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_collection: HashMap<String, CachedSchema>,
+    // Every collection's $jsonSchema, for lookups across collections (references, exports)
+    all_schemas: Option<(Vec<(String, Document)>, Instant)>,
+    // Bumped by every invalidation, so a lookup that started before one doesn't
+    // store what it read
+    generation: u64,
+}
+
+// Collection schemas and UI metadata kept between requests, so writes don't run
+// listCollections and a ui_metadata query each time. Whoever changes a schema or
+// ui_metadata through the app invalidates the collection; the TTL covers the rest.
+// Clones share the same entries.
+#[derive(Clone)]
+pub struct SchemaCache {
+    entries: Arc<Mutex<Entries>>,
+    ttl: Duration,
+}
+
+impl SchemaCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { entries: Arc::new(Mutex::new(Entries::default())), ttl }
+    }
+
+    // TTL from SCHEMA_CACHE_TTL_SECS; 0 turns caching off
+    pub fn from_env() -> Self {
+        let ttl = match std::env::var(TTL_ENV) {
+            Ok(value) => value.trim().parse::<u64>().map(Duration::from_secs).unwrap_or_else(|_| {
+                warn!("Invalid {} '{}'; using {} seconds", TTL_ENV, value, DEFAULT_TTL.as_secs());
+                DEFAULT_TTL
+            }),
+            Err(_) => DEFAULT_TTL,
+        };
+        Self::new(ttl)
+    }
+
+    // The collection's $jsonSchema; errors as get_collection_schema_internal's
+    pub async fn schema(&self, db: &Database, collection_name: &str) -> Result<Document, String> {
+        self.load(db, collection_name).await?.schema
+    }
+
+    // The $jsonSchema with defaults annotated and the global `ui` settings merged in
+    pub async fn schema_with_ui(&self, db: &Database, collection_name: &str) -> Result<Document, String> {
+        let cached = self.load(db, collection_name).await?;
+        Ok(merge_ui_metadata(cached.schema?, cached.ui_entry.as_ref()))
+    }
+
+    // Defaults that apply to inserts, as effective_defaults gives them
+    pub async fn defaults(&self, db: &Database, collection_name: &str) -> Result<FieldDefaults, String> {
+        let cached = self.load(db, collection_name).await?;
+        let stored = cached.ui_entry.as_ref().map(defaults_from_entry).unwrap_or_default();
+        Ok(effective_defaults(&cached.schema.unwrap_or_default(), stored))
+    }
+
+    // The $jsonSchema of every collection that has one, as list_collection_schemas gives them
+    pub async fn all_schemas(&self, db: &Database) -> Result<Vec<(String, Document)>, String> {
+        let generation = {
+            let entries = self.lock();
+            if let Some((schemas, loaded_at)) = &entries.all_schemas {
+                if loaded_at.elapsed() < self.ttl {
+                    return Ok(schemas.clone());
+                }
+            }
+            entries.generation
+        };
+
+        let schemas = list_collection_schemas(db).await?;
+
+        let mut entries = self.lock();
+        if entries.generation == generation && !self.ttl.is_zero() {
+            entries.all_schemas = Some((schemas.clone(), Instant::now()));
+        }
+        Ok(schemas)
+    }
+
+    // Forget a collection after its schema or ui_metadata changed
+    pub fn invalidate(&self, collection_name: &str) {
+        let mut entries = self.lock();
+        entries.by_collection.remove(collection_name);
+        entries.all_schemas = None;
+        entries.generation += 1;
+    }
+
+    // Forget everything, after changes to many collections (schema packs, migrations,
+    // restores) or a new connection
+    pub fn invalidate_all(&self) {
+        let mut entries = self.lock();
+        entries.by_collection.clear();
+        entries.all_schemas = None;
+        entries.generation += 1;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // The entries are only replaced whole, so they're usable after a panic elsewhere
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // The cached entry, read from the server when missing or expired. Errors are
+    // failed queries; those aren't cached.
+    async fn load(&self, db: &Database, collection_name: &str) -> Result<CachedSchema, String> {
+        let generation = {
+            let entries = self.lock();
+            if let Some(cached) = entries.by_collection.get(collection_name) {
+                if cached.loaded_at.elapsed() < self.ttl {
+                    return Ok(cached.clone());
+                }
+            }
+            entries.generation
+        };
+
+        let schema = fetch_collection_schema(db, collection_name).await?;
+        let ui_entry = db.collection::<Document>("ui_metadata")
+            .find_one(doc! { "collection": collection_name, "user_id": { "$exists": false } }, None)
+            .await
+            .map_err(|e| format!("Failed to fetch UI metadata: {}", e))?;
+        let cached = CachedSchema { schema, ui_entry, loaded_at: Instant::now() };
+
+        let mut entries = self.lock();
+        if entries.generation == generation && !self.ttl.is_zero() {
+            entries.by_collection.insert(collection_name.to_string(), cached.clone());
+        }
+        Ok(cached)
+    }
+}
@@ -0,0 +1,478 @@
+// src/api_server/services/schema_editor_service.rs
+
+use axum::http::StatusCode;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Database;
+use futures_util::TryStreamExt;
+use regex::Regex;
+
+use crate::api_server::models::FieldDefinition;
+use crate::api_server::services::defaults_service::{check_default, move_defaults, set_default};
+use crate::api_server::services::schema_service::get_collection_schema_internal;
+use crate::api_server::events::id_text;
+use crate::mongodb_schema::DEFAULT_COLUMN_WIDTH;
+
+// bsonType aliases MongoDB accepts in a $jsonSchema
+const BSON_TYPES: [&str; 15] = [
+    "string", "int", "long", "double", "decimal", "number", "bool", "date",
+    "objectId", "array", "object", "null", "timestamp", "binData", "regex",
+];
+
+// Fields the app itself maintains (ids, timestamps, archive/pin/row height state)
+const RESERVED_FIELDS: [&str; 8] = [
+    "_id", "created_at", "updated_at", "is_archive", "archive_history",
+    "pinned_by", "pinned_history", "row_height",
+];
+
+// How many offending document ids to quote when a change is refused
+const SAMPLE_SIZE: i64 = 5;
+
+type EditResult<T> = Result<T, (StatusCode, String)>;
+
+// Add a property. Field names may be dotted to reach into object properties.
+pub async fn add_field(
+    db: &Database,
+    collection_name: &str,
+    name: &str,
+    definition: &FieldDefinition,
+    force: bool,
+) -> EditResult<Document> {
+    check_field_name(name)?;
+    check_not_reserved(name, "added")?;
+    let current = load_schema(db, collection_name).await?;
+
+    let mut updated = current.clone();
+    let (parent, leaf) = locate_mut(&mut updated, name)?;
+    let properties = properties_mut(parent);
+    if properties.contains_key(&leaf) {
+        return Err((StatusCode::CONFLICT, format!("Field '{}' already exists", name)));
+    }
+    let mut spec = Document::new();
+    apply_definition(&mut spec, definition)?;
+    if !spec.contains_key("bsonType") {
+        return Err((StatusCode::BAD_REQUEST, "A new field needs a bsonType".to_string()));
+    }
+    let default = new_default(name, definition, &spec)?;
+    properties.insert(leaf.clone(), spec);
+    if let Some(required) = definition.required {
+        set_required(parent, &leaf, required);
+    }
+
+    if !force {
+        check_existing_documents(db, collection_name, &current, &updated).await?;
+    }
+    apply_validator(db, collection_name, &updated).await?;
+    if let Some(default) = default {
+        set_default(db, collection_name, name, default).await.map_err(internal)?;
+    }
+    if !name.contains('.') {
+        update_ui_metadata_fields(db, collection_name, UiFieldChange::Added(name)).await?;
+    }
+    Ok(updated)
+}
+
+// Change a property's attributes and/or rename it, renaming it in existing documents too
+pub async fn update_field(
+    db: &Database,
+    collection_name: &str,
+    name: &str,
+    rename_to: Option<&str>,
+    definition: &FieldDefinition,
+    force: bool,
+) -> EditResult<Document> {
+    check_not_reserved(name, "changed")?;
+    let current = load_schema(db, collection_name).await?;
+
+    // Attribute changes are checked against the data under the current name
+    let mut updated = current.clone();
+    let (parent, leaf) = locate_mut(&mut updated, name)?;
+    let spec = properties_mut(parent).get_document_mut(&leaf)
+        .map_err(|_| (StatusCode::NOT_FOUND, format!("Field '{}' not found in the schema", name)))?;
+    apply_definition(spec, definition)?;
+    let default = new_default(name, definition, spec)?;
+    if let Some(required) = definition.required {
+        set_required(parent, &leaf, required);
+    }
+    if !force {
+        check_existing_documents(db, collection_name, &current, &updated).await?;
+    }
+
+    let renamed = match rename_to.map(str::trim).filter(|n| !n.is_empty() && *n != leaf) {
+        Some(new_leaf) => {
+            if new_leaf.contains('.') {
+                return Err((StatusCode::BAD_REQUEST, "A field can only be renamed within its parent; the new name can't contain '.'".to_string()));
+            }
+            check_field_name(new_leaf)?;
+            let new_path = match name.rsplit_once('.') {
+                Some((parent_path, _)) => format!("{}.{}", parent_path, new_leaf),
+                None => new_leaf.to_string(),
+            };
+            check_not_reserved(&new_path, "used as a new name")?;
+
+            let (parent, _) = locate_mut(&mut updated, name)?;
+            if properties_mut(parent).contains_key(new_leaf) {
+                return Err((StatusCode::CONFLICT, format!("Field '{}' already exists", new_path)));
+            }
+            rename_property(parent, &leaf, new_leaf);
+
+            // $rename would overwrite values already stored under the new name
+            if !force {
+                let filter = doc! { name: { "$exists": true }, new_path.as_str(): { "$exists": true } };
+                refuse_if_any(db, collection_name, filter, &format!("already have both '{}' and '{}'", name, new_path)).await?;
+            }
+            Some(new_path)
+        },
+        None => None,
+    };
+
+    // Documents are renamed before the validator changes, so a failed rename leaves
+    // both as they were; a validator that can't be applied puts the names back
+    if let Some(new_path) = &renamed {
+        rename_in_documents(db, collection_name, name, new_path).await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Renaming the field in documents failed: {}", e)))?;
+        if let Err((status, e)) = apply_validator(db, collection_name, &updated).await {
+            return Err(match rename_in_documents(db, collection_name, new_path, name).await {
+                Ok(()) => (status, e),
+                Err(undo) => (status, format!("{}; renaming documents back to '{}' also failed: {}", e, name, undo)),
+            });
+        }
+    } else {
+        apply_validator(db, collection_name, &updated).await?;
+    }
+    if let Some(default) = default {
+        set_default(db, collection_name, name, default).await.map_err(internal)?;
+    }
+
+    if let Some(new_path) = renamed {
+        move_defaults(db, collection_name, name, Some(&new_path)).await.map_err(internal)?;
+        if !name.contains('.') {
+            update_ui_metadata_fields(db, collection_name, UiFieldChange::Renamed(name, &new_path)).await?;
+        }
+    }
+    Ok(updated)
+}
+
+// Remove a property from the schema. Stored values are kept; the change is refused
+// when the schema forbids additional properties and documents still hold the field.
+pub async fn remove_field(
+    db: &Database,
+    collection_name: &str,
+    name: &str,
+    force: bool,
+) -> EditResult<Document> {
+    check_not_reserved(name, "removed")?;
+    let current = load_schema(db, collection_name).await?;
+
+    let mut updated = current.clone();
+    let (parent, leaf) = locate_mut(&mut updated, name)?;
+    if properties_mut(parent).remove(&leaf).is_none() {
+        return Err((StatusCode::NOT_FOUND, format!("Field '{}' not found in the schema", name)));
+    }
+    set_required(parent, &leaf, false);
+
+    if !force {
+        check_existing_documents(db, collection_name, &current, &updated).await?;
+    }
+    apply_validator(db, collection_name, &updated).await?;
+    move_defaults(db, collection_name, name, None).await.map_err(internal)?;
+    if !name.contains('.') {
+        update_ui_metadata_fields(db, collection_name, UiFieldChange::Removed(name)).await?;
+    }
+    Ok(updated)
+}
+
+// The collection's $jsonSchema; a collection without a validator starts from an empty object schema
+async fn load_schema(db: &Database, collection_name: &str) -> EditResult<Document> {
+    if let Ok(schema) = get_collection_schema_internal(db, collection_name).await {
+        return Ok(schema);
+    }
+    let names = db.list_collection_names(doc! { "name": collection_name })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if names.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("Collection '{}' not found", collection_name)));
+    }
+    Ok(doc! { "bsonType": "object", "properties": {} })
+}
+
+// Replace the collection's validator; validationLevel and validationAction are kept
+async fn apply_validator(db: &Database, collection_name: &str, schema: &Document) -> EditResult<()> {
+    db.run_command(doc! { "collMod": collection_name, "validator": { "$jsonSchema": schema.clone() } }, None)
+        .await
+        .map(|_| ())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to update the collection validator: {}", e)))
+}
+
+// Move a field's values to another path. Validation is skipped: the validator in
+// place still describes the field under the name being moved away from.
+async fn rename_in_documents(db: &Database, collection_name: &str, from: &str, to: &str) -> mongodb::error::Result<()> {
+    let options = UpdateOptions::builder().bypass_document_validation(true).build();
+    db.collection::<Document>(collection_name)
+        .update_many(doc! { from: { "$exists": true } }, doc! { "$rename": { from: to } }, options)
+        .await
+        .map(|_| ())
+}
+
+// Refuse a change when documents that satisfy the current schema would fail the new one.
+// Documents that already break the current schema don't block unrelated edits.
+async fn check_existing_documents(
+    db: &Database,
+    collection_name: &str,
+    current: &Document,
+    updated: &Document,
+) -> EditResult<()> {
+    let filter = doc! {
+        "$jsonSchema": current.clone(),
+        "$nor": [{ "$jsonSchema": updated.clone() }],
+    };
+    refuse_if_any(db, collection_name, filter, "would no longer satisfy the schema").await
+}
+
+async fn refuse_if_any(db: &Database, collection_name: &str, filter: Document, problem: &str) -> EditResult<()> {
+    let collection = db.collection::<Document>(collection_name);
+    let count = collection.count_documents(filter.clone(), None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to check existing documents: {}", e)))?;
+    if count == 0 {
+        return Ok(());
+    }
+
+    let options = FindOptions::builder().projection(doc! { "_id": 1 }).limit(SAMPLE_SIZE).build();
+    let samples: Vec<String> = match collection.find(filter, options).await {
+        Ok(cursor) => cursor.try_collect::<Vec<Document>>().await.unwrap_or_default()
+            .iter()
+            .filter_map(|d| d.get("_id").map(id_text))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    Err((StatusCode::CONFLICT, format!(
+        "{} existing document(s) {} (e.g. {}); fix them first or pass force=true",
+        count, problem, samples.join(", ")
+    )))
+}
+
+// Reserved fields (and the fields inside them) are never edited through the schema editor
+fn check_not_reserved(name: &str, action: &str) -> EditResult<()> {
+    let top = name.split('.').next().unwrap_or(name);
+    if RESERVED_FIELDS.contains(&top) {
+        return Err((StatusCode::BAD_REQUEST, format!("Field '{}' is managed by the app and can't be {}", top, action)));
+    }
+    Ok(())
+}
+
+fn check_field_name(name: &str) -> EditResult<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('$')
+        && name.split('.').all(|segment| !segment.is_empty() && segment.parse::<usize>().is_err());
+    if valid { Ok(()) } else { Err((StatusCode::BAD_REQUEST, format!("Invalid field name '{}'", name))) }
+}
+
+// The object spec holding the last segment of `path`, and that segment.
+// Intermediate segments must be object properties.
+fn locate_mut<'a>(schema: &'a mut Document, path: &str) -> EditResult<(&'a mut Document, String)> {
+    let mut segments: Vec<&str> = path.split('.').collect();
+    let leaf = segments.pop().unwrap_or_default().to_string();
+    let mut spec = schema;
+    for segment in segments {
+        spec = spec.get_document_mut("properties")
+            .and_then(|props| props.get_document_mut(segment))
+            .map_err(|_| (StatusCode::NOT_FOUND, format!("'{}' in '{}' is not an object field of the schema", segment, path)))?;
+    }
+    Ok((spec, leaf))
+}
+
+fn properties_mut(spec: &mut Document) -> &mut Document {
+    if spec.get_document("properties").is_err() {
+        spec.insert("properties", Document::new());
+    }
+    spec.get_document_mut("properties").unwrap()
+}
+
+fn set_required(spec: &mut Document, field: &str, required: bool) {
+    let mut list: Vec<Bson> = spec.get_array("required").cloned().unwrap_or_default();
+    list.retain(|f| f.as_str() != Some(field));
+    if required {
+        list.push(Bson::String(field.to_string()));
+    }
+    if list.is_empty() {
+        spec.remove("required");
+    } else {
+        spec.insert("required", list);
+    }
+}
+
+// Rename a property in place, keeping its position and its entry in `required`
+fn rename_property(spec: &mut Document, from: &str, to: &str) {
+    let properties = properties_mut(spec);
+    let renamed: Document = std::mem::take(properties)
+        .into_iter()
+        .map(|(key, value)| if key == from { (to.to_string(), value) } else { (key, value) })
+        .collect();
+    *properties = renamed;
+
+    if let Ok(required) = spec.get_array_mut("required") {
+        for entry in required.iter_mut() {
+            if entry.as_str() == Some(from) {
+                *entry = Bson::String(to.to_string());
+            }
+        }
+    }
+}
+
+fn apply_definition(spec: &mut Document, definition: &FieldDefinition) -> EditResult<()> {
+    let invalid = |message: String| (StatusCode::BAD_REQUEST, message);
+
+    if let Some(bson_type) = &definition.bson_type {
+        let types: Vec<String> = match bson_type {
+            serde_json::Value::String(t) => vec![t.clone()],
+            serde_json::Value::Array(items) => items.iter()
+                .map(|t| t.as_str().map(String::from).ok_or_else(|| invalid("bsonType entries must be strings".to_string())))
+                .collect::<EditResult<_>>()?,
+            _ => return Err(invalid("bsonType must be a string or a list of strings".to_string())),
+        };
+        if let Some(unknown) = types.iter().find(|t| !BSON_TYPES.contains(&t.as_str())) {
+            return Err(invalid(format!("Unknown bsonType '{}'", unknown)));
+        }
+        match types.as_slice() {
+            [] => return Err(invalid("bsonType can't be empty".to_string())),
+            [single] => spec.insert("bsonType", single.clone()),
+            many => spec.insert("bsonType", many.to_vec()),
+        };
+    }
+
+    if let Some(values) = &definition.allowed_values {
+        if values.is_empty() {
+            spec.remove("enum");
+        } else {
+            let values = values.iter()
+                .map(|v| Bson::try_from(v.clone()).map_err(|e| invalid(format!("Invalid enum value: {}", e))))
+                .collect::<EditResult<Vec<Bson>>>()?;
+            spec.insert("enum", values);
+        }
+    }
+
+    if let Some(pattern) = &definition.pattern {
+        if pattern.is_empty() {
+            spec.remove("pattern");
+        } else {
+            Regex::new(pattern).map_err(|e| invalid(format!("Invalid pattern: {}", e)))?;
+            spec.insert("pattern", pattern.clone());
+        }
+    }
+
+    if let Some(description) = &definition.description {
+        spec.insert("description", description.clone());
+    }
+    Ok(())
+}
+
+// The default change a definition asks for: Some(None) clears the field's default,
+// Some(Some(value)) sets it once it has been checked against the field's spec
+fn new_default(name: &str, definition: &FieldDefinition, spec: &Document) -> EditResult<Option<Option<Bson>>> {
+    match &definition.default {
+        None => Ok(None),
+        Some(serde_json::Value::Null) => Ok(Some(None)),
+        Some(value) => {
+            let value = Bson::try_from(value.clone())
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid default: {}", e)))?;
+            let value = check_default(name, value, spec).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Ok(Some(Some(value)))
+        },
+    }
+}
+
+fn internal(message: String) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+enum UiFieldChange<'a> {
+    Added(&'a str),
+    Renamed(&'a str, &'a str),
+    Removed(&'a str),
+}
+
+// Keep column widths, order, short names, hidden columns and the sort field of every
+// saved view (global and per-user) in step with a top-level field change
+async fn update_ui_metadata_fields(db: &Database, collection_name: &str, change: UiFieldChange<'_>) -> EditResult<()> {
+    let ui_collection = db.collection::<Document>("ui_metadata");
+    let cursor = ui_collection.find(doc! { "collection": collection_name }, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to load UI metadata: {}", e)))?;
+    let settings: Vec<Document> = cursor.try_collect()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to load UI metadata: {}", e)))?;
+
+    for setting in settings {
+        let (Ok(id), Ok(ui)) = (setting.get_object_id("_id"), setting.get_document("ui")) else { continue };
+        let mut ui = ui.clone();
+
+        match change {
+            UiFieldChange::Added(name) => {
+                sub_document(&mut ui, "columnWidths").insert(name, DEFAULT_COLUMN_WIDTH);
+                sub_document(&mut ui, "short_names").insert(name, name);
+                let order = ui.get_array_mut("columnOrder");
+                match order {
+                    Ok(order) => order.push(Bson::String(name.to_string())),
+                    Err(_) => { ui.insert("columnOrder", vec![name]); },
+                }
+            },
+            UiFieldChange::Renamed(from, to) => {
+                for key in ["columnWidths", "short_names"] {
+                    let entries = sub_document(&mut ui, key);
+                    let renamed: Document = std::mem::take(entries).into_iter()
+                        .map(|(field, value)| match field == from {
+                            // A short name that was just the field name follows the rename
+                            true if key == "short_names" && value.as_str() == Some(from) => (to.to_string(), Bson::String(to.to_string())),
+                            true => (to.to_string(), value),
+                            false => (field, value),
+                        })
+                        .collect();
+                    *entries = renamed;
+                }
+                for key in ["columnOrder", "hiddenColumns"] {
+                    if let Ok(list) = ui.get_array_mut(key) {
+                        for entry in list.iter_mut().filter(|e| e.as_str() == Some(from)) {
+                            *entry = Bson::String(to.to_string());
+                        }
+                    }
+                }
+                if let Ok(sort) = ui.get_document_mut("sortSettings") {
+                    if sort.get_str("field") == Ok(from) {
+                        sort.insert("field", to);
+                    }
+                }
+            },
+            UiFieldChange::Removed(name) => {
+                sub_document(&mut ui, "columnWidths").remove(name);
+                sub_document(&mut ui, "short_names").remove(name);
+                for key in ["columnOrder", "hiddenColumns"] {
+                    if let Ok(list) = ui.get_array_mut(key) {
+                        list.retain(|e| e.as_str() != Some(name));
+                    }
+                }
+                if let Ok(sort) = ui.get_document_mut("sortSettings") {
+                    if sort.get_str("field") == Ok(name) {
+                        sort.insert("field", "created_at");
+                    }
+                }
+            },
+        }
+
+        ui_collection.update_one(
+            doc! { "_id": id },
+            doc! { "$set": { "ui": ui, "updated_at": mongodb::bson::DateTime::now() } },
+            None,
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to update UI metadata: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn sub_document<'a>(ui: &'a mut Document, key: &str) -> &'a mut Document {
+    if ui.get_document(key).is_err() {
+        ui.insert(key, Document::new());
+    }
+    ui.get_document_mut(key).unwrap()
+}
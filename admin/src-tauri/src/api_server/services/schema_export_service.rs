@@ -0,0 +1,359 @@
+// src/api_server/services/schema_export_service.rs
+
+use axum::http::StatusCode;
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::Database;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::api_server::services::coercion_service::declared_types;
+use crate::api_server::services::defaults_service::{annotate_defaults, defaults_from_entry, effective_defaults};
+use crate::api_server::services::reference_service::parse_reference;
+use crate::api_server::services::schema_cache::SchemaCache;
+use crate::api_server::services::snapshot_service::{is_snapshot_collection, SNAPSHOTS_COLLECTION};
+use crate::migrations::{GENDER_TEXT_COLLECTION, MIGRATIONS_COLLECTION};
+use crate::timezone::{format_output_fields, InstitutionTimezone};
+
+// App bookkeeping that frontends don't read directly
+const INTERNAL_COLLECTIONS: [&str; 5] = ["sessions", "ui_metadata", MIGRATIONS_COLLECTION, GENDER_TEXT_COLLECTION, SNAPSHOTS_COLLECTION];
+
+type ExportResult<T> = Result<T, (StatusCode, String)>;
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// Keywords copied as they are from a $jsonSchema property into JSON Schema
+const PLAIN_KEYWORDS: [&str; 11] = [
+    "pattern", "minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum",
+    "minLength", "maxLength", "minItems", "maxItems", "uniqueItems", "additionalProperties",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaFormat {
+    // One .ts module with an interface per collection
+    TypeScript,
+    // Standalone JSON Schema (draft 2020-12) documents, one per collection
+    JsonSchema,
+    // An OpenAPI 3.1 document whose components.schemas holds every collection
+    OpenApi,
+}
+
+impl SchemaFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "ts" | "typescript" => Ok(Self::TypeScript),
+            "json-schema" | "jsonschema" => Ok(Self::JsonSchema),
+            "openapi" => Ok(Self::OpenApi),
+            other => Err(format!("Unsupported schema format '{}'. Use ts, json-schema or openapi", other)),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::TypeScript => "text/plain; charset=utf-8",
+            Self::JsonSchema | Self::OpenApi => "application/json",
+        }
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::TypeScript => "collections.ts",
+            Self::JsonSchema => "collections.schema.json",
+            Self::OpenApi => "openapi.json",
+        }
+    }
+}
+
+// The $jsonSchema of every exported collection by name, with defaults filled in.
+// `only` limits the export to the listed collections.
+pub async fn load_schemas(schema_cache: &SchemaCache, db: &Database, only: Option<&[String]>) -> ExportResult<Vec<(String, Document)>> {
+    let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+    let entries: Vec<Document> = db.collection::<Document>("ui_metadata")
+        .find(doc! { "user_id": { "$exists": false } }, None)
+        .await
+        .map_err(|e| internal(format!("Failed to load defaults: {}", e)))?
+        .try_collect()
+        .await
+        .map_err(|e| internal(format!("Failed to load defaults: {}", e)))?;
+    let stored: HashMap<String, Document> = entries.into_iter()
+        .filter_map(|entry| Some((entry.get_str("collection").ok()?.to_string(), entry)))
+        .collect();
+
+    let mut schemas: Vec<(String, Document)> = schema_cache.all_schemas(db).await.map_err(internal)?
+        .into_iter()
+        .filter(|(name, _)| match only {
+            Some(names) => names.contains(name),
+            None => !INTERNAL_COLLECTIONS.contains(&name.as_str()) && !is_snapshot_collection(name),
+        })
+        .map(|(name, mut schema)| {
+            let defaults = stored.get(&name).map(defaults_from_entry).unwrap_or_default();
+            let defaults = effective_defaults(&schema, defaults);
+            annotate_defaults(&mut schema, &defaults);
+            (name, schema)
+        })
+        .collect();
+    if let Some(missing) = only.into_iter().flatten().find(|name| !schemas.iter().any(|(n, _)| n == *name)) {
+        return Err((StatusCode::NOT_FOUND, format!("Collection '{}' not found or has no schema", missing)));
+    }
+    schemas.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(schemas)
+}
+
+// The export as one file: TypeScript source, or JSON (for json-schema, an object
+// holding each collection's document by name)
+pub fn render(format: SchemaFormat, schemas: &[(String, Document)]) -> String {
+    match format {
+        SchemaFormat::TypeScript => typescript(schemas),
+        SchemaFormat::JsonSchema => {
+            let documents: Map<String, Value> = json_schemas(schemas).into_iter().collect();
+            serde_json::to_string_pretty(&documents).unwrap_or_default()
+        },
+        SchemaFormat::OpenApi => serde_json::to_string_pretty(&openapi(schemas)).unwrap_or_default(),
+    }
+}
+
+// Interface name for a collection: "school_years" -> "SchoolYears"
+pub fn type_name(collection_name: &str) -> String {
+    collection_name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect()
+}
+
+// A value as the API sends it: ids as { "$oid": ... }, dates as ISO 8601 strings,
+// decimals as strings
+pub fn wire_json(value: &Bson) -> Value {
+    let mut holder = doc! { "value": value.clone() };
+    format_output_fields(&mut holder, &InstitutionTimezone::from_env());
+    holder.get("value").and_then(|v| serde_json::to_value(v).ok()).unwrap_or(Value::Null)
+}
+
+fn required_fields(spec: &Document) -> Vec<String> {
+    spec.get_array("required")
+        .map(|list| list.iter().filter_map(|f| f.as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+// ---- TypeScript ----
+
+pub fn typescript(schemas: &[(String, Document)]) -> String {
+    let names: Vec<&str> = schemas.iter().map(|(name, _)| name.as_str()).collect();
+    let mut out = String::from("// Generated from the collection schemas; changes made here are overwritten.\n\n");
+    out.push_str("export interface ObjectId {\n  $oid: string;\n}\n");
+
+    for (name, schema) in schemas {
+        out.push_str(&format!("\n// Collection '{}'\nexport interface {} ", name, type_name(name)));
+        let mut body = match schema.get_document("properties") {
+            Ok(_) => ts_object(schema, 0, &names),
+            Err(_) => "{\n  [field: string]: unknown;\n}".to_string(),
+        };
+        // Every stored document has an _id, even when the schema doesn't declare it
+        if !schema.get_document("properties").is_ok_and(|p| p.contains_key("_id")) {
+            body = body.replacen("{\n", "{\n  _id: ObjectId;\n", 1);
+        }
+        out.push_str(&body);
+        out.push('\n');
+    }
+    out
+}
+
+fn ts_object(spec: &Document, depth: usize, collections: &[&str]) -> String {
+    let Ok(properties) = spec.get_document("properties") else {
+        return "Record<string, unknown>".to_string();
+    };
+    let required = required_fields(spec);
+    let indent = "  ".repeat(depth + 1);
+    let mut out = String::from("{\n");
+    for (field, property) in properties {
+        let Some(property) = property.as_document() else { continue };
+        let comment = ts_comment(property, collections);
+        if !comment.is_empty() {
+            out.push_str(&format!("{}/** {} */\n", indent, comment));
+        }
+        let optional = if required.contains(field) { "" } else { "?" };
+        out.push_str(&format!("{}{}{}: {};\n", indent, ts_key(field), optional, ts_type(property, depth + 1, collections)));
+    }
+    out.push_str(&"  ".repeat(depth));
+    out.push('}');
+    out
+}
+
+fn ts_comment(spec: &Document, collections: &[&str]) -> String {
+    let mut parts = Vec::new();
+    if let Ok(description) = spec.get_str("description") {
+        parts.push(description.replace("*/", "*\\/"));
+        if let Some((target, _)) = parse_reference(description) {
+            if collections.contains(&target.as_str()) {
+                parts.push(format!("@see {}", type_name(&target)));
+            }
+        }
+    }
+    if let Some(default) = spec.get("default") {
+        parts.push(format!("@default {}", wire_json(default)));
+    }
+    parts.join(" ")
+}
+
+fn ts_key(field: &str) -> String {
+    let identifier = field.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if identifier { field.to_string() } else { format!("{:?}", field) }
+}
+
+fn ts_type(spec: &Document, depth: usize, collections: &[&str]) -> String {
+    let mut members: Vec<String> = Vec::new();
+    let mut add = |member: String| if !members.contains(&member) { members.push(member) };
+
+    if let Ok(values) = spec.get_array("enum") {
+        for value in values {
+            add(wire_json(value).to_string());
+        }
+    } else {
+        for bson_type in declared_types(spec) {
+            add(match bson_type {
+                "string" | "decimal" | "date" => "string".to_string(),
+                "int" | "long" | "double" | "number" => "number".to_string(),
+                "bool" => "boolean".to_string(),
+                "objectId" => "ObjectId".to_string(),
+                "null" => "null".to_string(),
+                "array" => match spec.get_document("items") {
+                    Ok(items) => format!("Array<{}>", ts_type(items, depth, collections)),
+                    Err(_) => "unknown[]".to_string(),
+                },
+                "object" => ts_object(spec, depth, collections),
+                _ => "unknown".to_string(),
+            });
+        }
+    }
+    if members.is_empty() { "unknown".to_string() } else { members.join(" | ") }
+}
+
+// ---- JSON Schema and OpenAPI ----
+
+// Each collection as a standalone JSON Schema document, by collection name
+pub fn json_schemas(schemas: &[(String, Document)]) -> Vec<(String, Value)> {
+    schemas.iter()
+        .map(|(name, schema)| {
+            let mut document = collection_schema(name, schema, "#/$defs/");
+            document.insert("$schema".to_string(), json!(JSON_SCHEMA_DIALECT));
+            document.insert("$id".to_string(), json!(format!("{}.schema.json", name)));
+            document.insert("$defs".to_string(), json!({ "ObjectId": object_id_schema() }));
+            (name.clone(), Value::Object(document))
+        })
+        .collect()
+}
+
+pub fn openapi(schemas: &[(String, Document)]) -> Value {
+    let mut components = Map::new();
+    components.insert("ObjectId".to_string(), object_id_schema());
+    for (name, schema) in schemas {
+        components.insert(type_name(name), Value::Object(collection_schema(name, schema, "#/components/schemas/")));
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": { "title": "Collection schemas", "version": env!("CARGO_PKG_VERSION") },
+        "paths": {},
+        "components": { "schemas": components }
+    })
+}
+
+fn object_id_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": { "$oid": { "type": "string", "pattern": "^[0-9a-fA-F]{24}$" } },
+        "required": ["$oid"],
+        "additionalProperties": false
+    })
+}
+
+fn collection_schema(name: &str, schema: &Document, refs: &str) -> Map<String, Value> {
+    let mut converted = match json_schema_for(schema, refs) {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+    converted.insert("title".to_string(), json!(type_name(name)));
+    converted.insert("type".to_string(), json!("object"));
+    let properties = converted.entry("properties").or_insert_with(|| json!({}));
+    if let Value::Object(properties) = properties {
+        if !properties.contains_key("_id") {
+            let mut with_id = Map::new();
+            with_id.insert("_id".to_string(), json!({ "$ref": format!("{}ObjectId", refs) }));
+            with_id.append(properties);
+            *properties = with_id;
+        }
+    }
+    converted
+}
+
+// Convert a $jsonSchema spec. bsonTypes become JSON types in the API's wire format,
+// and a REF: annotation is kept as "x-ref": <collection>.
+fn json_schema_for(spec: &Document, refs: &str) -> Value {
+    let mut out = Map::new();
+
+    let variants: Vec<Value> = declared_types(spec).into_iter()
+        .map(|bson_type| match bson_type {
+            "string" => json!({ "type": "string" }),
+            "int" | "long" => json!({ "type": "integer" }),
+            "double" | "number" => json!({ "type": "number" }),
+            "decimal" => json!({ "type": "string", "format": "decimal" }),
+            "bool" => json!({ "type": "boolean" }),
+            "date" => json!({ "type": "string", "format": "date-time" }),
+            "null" => json!({ "type": "null" }),
+            "objectId" => json!({ "$ref": format!("{}ObjectId", refs) }),
+            "array" => match spec.get_document("items") {
+                Ok(items) => json!({ "type": "array", "items": json_schema_for(items, refs) }),
+                Err(_) => json!({ "type": "array" }),
+            },
+            "object" => object_schema(spec, refs),
+            _ => json!({}),
+        })
+        .collect();
+    match variants.as_slice() {
+        [] => {},
+        [Value::Object(single)] => out.extend(single.clone()),
+        // Plain types merge into "type": [...]; anything richer becomes anyOf
+        many if many.iter().all(|v| v.as_object().is_some_and(|o| o.len() == 1 && o.contains_key("type"))) => {
+            let types: Vec<Value> = many.iter().filter_map(|v| v.get("type").cloned()).collect();
+            out.insert("type".to_string(), Value::Array(types));
+        },
+        many => { out.insert("anyOf".to_string(), Value::Array(many.to_vec())); },
+    }
+
+    if let Ok(values) = spec.get_array("enum") {
+        out.insert("enum".to_string(), Value::Array(values.iter().map(wire_json).collect()));
+    }
+    for keyword in PLAIN_KEYWORDS {
+        if let Some(value) = spec.get(keyword) {
+            out.insert(keyword.to_string(), value.clone().into_relaxed_extjson());
+        }
+    }
+    if let Some(default) = spec.get("default") {
+        out.insert("default".to_string(), wire_json(default));
+    }
+    if let Ok(description) = spec.get_str("description") {
+        out.insert("description".to_string(), json!(description));
+        if let Some((target, _)) = parse_reference(description) {
+            out.insert("x-ref".to_string(), json!(target));
+        }
+    }
+    Value::Object(out)
+}
+
+fn object_schema(spec: &Document, refs: &str) -> Value {
+    let mut out = json!({ "type": "object" });
+    if let Ok(properties) = spec.get_document("properties") {
+        let converted: Map<String, Value> = properties.iter()
+            .filter_map(|(field, property)| Some((field.clone(), json_schema_for(property.as_document()?, refs))))
+            .collect();
+        out["properties"] = Value::Object(converted);
+    }
+    let required = required_fields(spec);
+    if !required.is_empty() {
+        out["required"] = json!(required);
+    }
+    out
+}
@@ -1,10 +1,18 @@
 // src/api_server/services/schema_service.rs
 
-use mongodb::{bson::{doc, Document}, Database};
-use tracing::error;
+use mongodb::{bson::{doc, Bson, Document}, Database};
 
-// Get collection schema - extract common functionality for reuse
+use crate::api_server::services::defaults_service::{annotate_defaults, defaults_from_entry, effective_defaults};
+
+// Get collection schema - extract common functionality for reuse.
+// Reads the server every time; request paths go through SchemaCache instead.
 pub async fn get_collection_schema_internal(db: &Database, collection_name: &str) -> Result<Document, String> {
+    fetch_collection_schema(db, collection_name).await?
+}
+
+// What listCollections says about a collection's schema. The outer error is a failed
+// command; the inner one a collection that is missing or has no $jsonSchema validator.
+pub async fn fetch_collection_schema(db: &Database, collection_name: &str) -> Result<Result<Document, String>, String> {
     let command = doc! {
         "listCollections": 1,
         "filter": { "name": collection_name }
@@ -20,6 +28,10 @@ pub async fn get_collection_schema_internal(db: &Database, collection_name: &str
     let batches = cursor.get_array("firstBatch")
         .map_err(|_| "No collections found".to_string())?;
     
+    Ok(schema_from_batch(batches))
+}
+
+fn schema_from_batch(batches: &[Bson]) -> Result<Document, String> {
     if batches.is_empty() {
         return Err("Collection not found".into());
     }
@@ -39,37 +51,40 @@ pub async fn get_collection_schema_internal(db: &Database, collection_name: &str
     Ok(json_schema.clone())
 }
 
-// Get collection schema with UI metadata
-pub async fn get_collection_schema_with_ui(
-    db: &Database, 
-    collection_name: &str
-) -> Result<Document, String> {
-    let schema = get_collection_schema_internal(db, collection_name).await?;
-    
-    // Fetch UI metadata from ui_metadata collection
-    let ui_metadata_collection = db.collection::<Document>("ui_metadata");
-    let filter = doc! {
-        "collection": collection_name,
-        "user_id": { "$exists": false } // Global settings
-    };
-    
-    // Try to get UI metadata
-    match ui_metadata_collection.find_one(filter, None).await {
-        Ok(ui_metadata) => {
-            let mut merged_schema = schema.clone();
-            if let Some(ui_metadata_doc) = ui_metadata {
-                if let Ok(ui) = ui_metadata_doc.get_document("ui") {
-                    merged_schema.insert("ui", ui.clone());
-                }
-            }
-            Ok(merged_schema)
-        },
-        Err(e) => {
-            error!("Failed to fetch UI metadata: {}", e);
-            // If we can't get UI metadata, just return the base schema
-            Ok(schema)
-        }
+// Get the $jsonSchema of every collection that has a validator, keyed by collection name
+pub async fn list_collection_schemas(db: &Database) -> Result<Vec<(String, Document)>, String> {
+    let response = db.run_command(doc! { "listCollections": 1 }, None)
+        .await
+        .map_err(|e| format!("Failed to list collections: {}", e))?;
+
+    let batches = response.get_document("cursor")
+        .and_then(|cursor| cursor.get_array("firstBatch"))
+        .map_err(|e| format!("Invalid response format: {}", e))?;
+
+    let schemas = batches.iter()
+        .filter_map(|info| info.as_document())
+        .filter_map(|info| {
+            let name = info.get_str("name").ok()?;
+            let json_schema = info.get_document("options").ok()?
+                .get_document("validator").ok()?
+                .get_document("$jsonSchema").ok()?;
+            Some((name.to_string(), json_schema.clone()))
+        })
+        .collect();
+
+    Ok(schemas)
+}
+
+// Merge a collection's global ui_metadata entry into its schema: effective defaults
+// are annotated on their properties and the `ui` settings added
+pub fn merge_ui_metadata(schema: Document, ui_entry: Option<&Document>) -> Document {
+    let mut merged_schema = schema.clone();
+    let stored = ui_entry.map(defaults_from_entry).unwrap_or_default();
+    annotate_defaults(&mut merged_schema, &effective_defaults(&schema, stored));
+    if let Some(ui) = ui_entry.and_then(|entry| entry.get_document("ui").ok()) {
+        merged_schema.insert("ui", ui.clone());
     }
+    merged_schema
 }
 
 // Update UI metadata for a collection
@@ -0,0 +1,254 @@
+// src/api_server/services/snapshot_service.rs
+
+use axum::http::StatusCode;
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{AggregateOptions, FindOptions};
+use mongodb::{Database, IndexModel};
+use serde::Serialize;
+use tracing::info;
+
+use crate::api_server::services::collection_service::check_collection_name;
+use crate::api_server::services::lifecycle_service::is_protected;
+use crate::timezone::InstitutionTimezone;
+
+// Catalog of snapshots: { _id: snapshot collection, source, reason, created_at,
+// documents, options, indexes, ui_metadata }
+pub const SNAPSHOTS_COLLECTION: &str = "_snapshots";
+
+// Snapshot copies are stored as collections named _snapshot_<source>_<timestamp>
+pub const SNAPSHOT_PREFIX: &str = "_snapshot_";
+
+type SnapshotResult<T> = Result<T, (StatusCode, String)>;
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub source: String,
+    // The operation it was taken before, e.g. "drop"
+    pub reason: String,
+    pub created_at: String,
+    pub documents: i64,
+}
+
+pub fn is_snapshot_collection(name: &str) -> bool {
+    name.starts_with(SNAPSHOT_PREFIX)
+}
+
+// Options the collection was created with (validator, validationLevel, ...)
+pub async fn collection_options(db: &Database, collection_name: &str) -> Result<Document, String> {
+    let response = db.run_command(doc! { "listCollections": 1, "filter": { "name": collection_name } }, None)
+        .await
+        .map_err(|e| format!("Failed to read collection options: {}", e))?;
+    let info = response.get_document("cursor")
+        .and_then(|cursor| cursor.get_array("firstBatch"))
+        .ok()
+        .and_then(|batch| batch.first())
+        .and_then(Bson::as_document)
+        .ok_or_else(|| format!("Collection '{}' not found", collection_name))?;
+    Ok(info.get_document("options").cloned().unwrap_or_default())
+}
+
+// Index specs other than _id, as stored documents that deserialize back into IndexModel
+pub async fn index_specs(db: &Database, collection_name: &str) -> Result<Vec<Document>, String> {
+    let models: Vec<IndexModel> = db.collection::<Document>(collection_name)
+        .list_indexes(None)
+        .await
+        .map_err(|e| format!("Failed to list indexes: {}", e))?
+        .try_collect()
+        .await
+        .map_err(|e| format!("Failed to list indexes: {}", e))?;
+    models.iter()
+        .filter(|model| model.options.as_ref().and_then(|o| o.name.as_deref()) != Some("_id_"))
+        .map(|model| mongodb::bson::to_document(model).map_err(|e| format!("Failed to read index: {}", e)))
+        .collect()
+}
+
+// Recreate stored index specs on a collection
+pub async fn create_index_specs(db: &Database, collection_name: &str, specs: &[Document]) -> Result<(), String> {
+    let models: Vec<IndexModel> = specs.iter()
+        .filter_map(|spec| mongodb::bson::from_document(spec.clone()).ok())
+        .collect();
+    if models.is_empty() {
+        return Ok(());
+    }
+    db.collection::<Document>(collection_name)
+        .create_indexes(models, None)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to create indexes: {}", e))
+}
+
+// Copy every document of `from` into `into`, creating it if needed. Validation is
+// bypassed so documents stored before a schema change still copy.
+pub async fn copy_documents(db: &Database, from: &str, into: &str) -> Result<(), String> {
+    let pipeline = vec![
+        doc! { "$match": {} },
+        doc! { "$merge": { "into": into, "whenMatched": "fail", "whenNotMatched": "insert" } },
+    ];
+    let options = AggregateOptions::builder().bypass_document_validation(true).allow_disk_use(true).build();
+    db.collection::<Document>(from)
+        .aggregate(pipeline, options)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to copy documents from '{}' to '{}': {}", from, into, e))
+}
+
+// Copy a collection's documents, options, indexes and ui_metadata aside before `reason`
+// changes it, and return the snapshot's name
+pub async fn take_snapshot(db: &Database, collection_name: &str, reason: &str) -> Result<String, String> {
+    let name = format!("{}{}_{}", SNAPSHOT_PREFIX, collection_name, chrono::Utc::now().format("%Y%m%d%H%M%S%3f"));
+
+    let options = collection_options(db, collection_name).await?;
+    let indexes = index_specs(db, collection_name).await?;
+    let ui_metadata: Vec<Document> = db.collection::<Document>("ui_metadata")
+        .find(doc! { "collection": collection_name }, None)
+        .await
+        .map_err(|e| format!("Failed to read UI metadata: {}", e))?
+        .try_collect()
+        .await
+        .map_err(|e| format!("Failed to read UI metadata: {}", e))?;
+
+    db.create_collection(&name, None)
+        .await
+        .map_err(|e| format!("Failed to create snapshot collection: {}", e))?;
+    copy_documents(db, collection_name, &name).await?;
+    let documents = db.collection::<Document>(&name)
+        .count_documents(None, None)
+        .await
+        .map_err(|e| format!("Failed to count snapshot documents: {}", e))?;
+
+    db.collection::<Document>(SNAPSHOTS_COLLECTION)
+        .insert_one(doc! {
+            "_id": name.as_str(),
+            "source": collection_name,
+            "reason": reason,
+            "created_at": mongodb::bson::DateTime::now(),
+            "documents": documents as i64,
+            "options": options,
+            "indexes": indexes,
+            "ui_metadata": ui_metadata,
+        }, None)
+        .await
+        .map_err(|e| format!("Failed to record snapshot: {}", e))?;
+
+    info!("Snapshot {} taken of {} ({} documents) before {}", name, collection_name, documents, reason);
+    Ok(name)
+}
+
+pub async fn list_snapshots(db: &Database, timezone: &InstitutionTimezone) -> Result<Vec<SnapshotInfo>, String> {
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .projection(doc! { "options": 0, "indexes": 0, "ui_metadata": 0 })
+        .build();
+    let records: Vec<Document> = db.collection::<Document>(SNAPSHOTS_COLLECTION)
+        .find(None, options)
+        .await
+        .map_err(|e| format!("Failed to list snapshots: {}", e))?
+        .try_collect()
+        .await
+        .map_err(|e| format!("Failed to list snapshots: {}", e))?;
+
+    Ok(records.iter()
+        .map(|record| SnapshotInfo {
+            name: record.get_str("_id").unwrap_or_default().to_string(),
+            source: record.get_str("source").unwrap_or_default().to_string(),
+            reason: record.get_str("reason").unwrap_or_default().to_string(),
+            created_at: record.get_datetime("created_at").map(|d| timezone.format_iso(d)).unwrap_or_default(),
+            documents: record.get_i64("documents").unwrap_or_default(),
+        })
+        .collect())
+}
+
+async fn find_snapshot(db: &Database, name: &str) -> SnapshotResult<Document> {
+    db.collection::<Document>(SNAPSHOTS_COLLECTION)
+        .find_one(doc! { "_id": name }, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read snapshot: {}", e)))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Snapshot '{}' not found", name)))
+}
+
+// Bring a snapshot back as `target` (its source collection by default). The target
+// must not exist, or be empty as a truncate leaves it; a new target gets the
+// snapshot's options, and either way the indexes and ui_metadata come back.
+pub async fn restore_snapshot(db: &Database, name: &str, target: Option<&str>) -> SnapshotResult<String> {
+    let record = find_snapshot(db, name).await?;
+    let target = target
+        .map(String::from)
+        .unwrap_or_else(|| record.get_str("source").unwrap_or_default().to_string());
+    // The same rules as a rename or clone target
+    check_collection_name(&target).map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid restore target '{}': {}", target, e)))?;
+    if is_protected(&target) {
+        return Err((StatusCode::FORBIDDEN, format!("'{}' is a reserved collection name", target)));
+    }
+
+    let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+    let exists = !db.list_collection_names(doc! { "name": target.as_str() })
+        .await
+        .map_err(|e| internal(e.to_string()))?
+        .is_empty();
+    if exists {
+        let count = db.collection::<Document>(&target)
+            .count_documents(None, None)
+            .await
+            .map_err(|e| internal(e.to_string()))?;
+        if count > 0 {
+            return Err((StatusCode::CONFLICT, format!(
+                "Collection '{}' already has {} documents; restore to another name or empty it first",
+                target, count
+            )));
+        }
+    } else {
+        let mut create = doc! { "create": target.as_str() };
+        for (key, value) in record.get_document("options").cloned().unwrap_or_default() {
+            create.insert(key, value);
+        }
+        db.run_command(create, None)
+            .await
+            .map_err(|e| internal(format!("Failed to create '{}': {}", target, e)))?;
+    }
+
+    copy_documents(db, name, &target).await.map_err(internal)?;
+    let indexes: Vec<Document> = record.get_array("indexes")
+        .map(|specs| specs.iter().filter_map(|s| s.as_document().cloned()).collect())
+        .unwrap_or_default();
+    create_index_specs(db, &target, &indexes).await.map_err(internal)?;
+
+    let ui_metadata = db.collection::<Document>("ui_metadata");
+    let has_ui = ui_metadata.count_documents(doc! { "collection": target.as_str() }, None)
+        .await
+        .map_err(|e| internal(e.to_string()))? > 0;
+    if !has_ui {
+        let entries: Vec<Document> = record.get_array("ui_metadata")
+            .map(|entries| entries.iter()
+                .filter_map(|e| e.as_document().cloned())
+                .map(|mut entry| {
+                    entry.remove("_id");
+                    entry.insert("collection", target.as_str());
+                    entry
+                })
+                .collect())
+            .unwrap_or_default();
+        if !entries.is_empty() {
+            ui_metadata.insert_many(entries, None)
+                .await
+                .map_err(|e| internal(format!("Failed to restore UI metadata: {}", e)))?;
+        }
+    }
+
+    info!("Snapshot {} restored as {}", name, target);
+    Ok(target)
+}
+
+pub async fn delete_snapshot(db: &Database, name: &str) -> SnapshotResult<()> {
+    find_snapshot(db, name).await?;
+    db.collection::<Document>(name)
+        .drop(None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to drop snapshot: {}", e)))?;
+    db.collection::<Document>(SNAPSHOTS_COLLECTION)
+        .delete_one(doc! { "_id": name }, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to remove snapshot record: {}", e)))?;
+    Ok(())
+}
@@ -0,0 +1,621 @@
+// src/api_server/services/validation_service.rs
+
+use futures_util::StreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+use mongodb::Collection;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::api_server::events::id_text;
+use crate::api_server::services::coercion_service::{declared_types, describe, describe_errors, has_type, spec_for_path, FieldError};
+
+// Server error code for a write the collection's validator rejected
+pub const DOCUMENT_VALIDATION_FAILURE: i32 = 121;
+
+// Field errors for a write rejected by the validator, or None for any other error.
+// findAndModify reports the failure without the validator's details, which gives
+// an empty list; callers can fall back to `validate_document` for those.
+pub fn validation_failure(error: &Error) -> Option<Vec<FieldError>> {
+    match error.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DOCUMENT_VALIDATION_FAILURE => {
+            Some(e.details.as_ref().map(parse_error_info).unwrap_or_default())
+        },
+        ErrorKind::BulkWrite(failure) => {
+            let rejected: Vec<_> = failure.write_errors.iter()
+                .flatten()
+                .filter(|e| e.code == DOCUMENT_VALIDATION_FAILURE)
+                .collect();
+            if rejected.is_empty() {
+                return None;
+            }
+            Some(rejected.iter()
+                .filter_map(|e| e.details.as_ref())
+                .flat_map(parse_error_info)
+                .collect())
+        },
+        ErrorKind::Command(e) if e.code == DOCUMENT_VALIDATION_FAILURE => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+// Message for a failed write, listing the fields when the validator rejected it
+pub fn describe_write_error(error: &Error) -> String {
+    match validation_failure(error) {
+        Some(errors) if !errors.is_empty() => format!("Document failed schema validation: {}", describe_errors(&errors)),
+        _ => error.to_string(),
+    }
+}
+
+// The driver hands over the whole errInfo ({ failingDocumentId, details })
+fn parse_error_info(info: &Document) -> Vec<FieldError> {
+    info.get_document("details").map(parse_validation_details).unwrap_or_default()
+}
+
+// Translate the `errInfo.details` of a validation failure into field errors.
+// The server nests one entry per failed keyword under `schemaRulesNotSatisfied`,
+// with `properties` and `items` recursing into sub-documents and arrays.
+pub fn parse_validation_details(details: &Document) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if let Ok(rules) = details.get_array("schemaRulesNotSatisfied") {
+        parse_rules(rules, "", &mut errors);
+    }
+    errors
+}
+
+fn parse_rules(rules: &[Bson], path: &str, errors: &mut Vec<FieldError>) {
+    for rule in rules.iter().filter_map(Bson::as_document) {
+        let operator = rule.get_str("operatorName").unwrap_or("unknown");
+        match operator {
+            "properties" => {
+                for property in rule.get_array("propertiesNotSatisfied").into_iter().flatten().filter_map(Bson::as_document) {
+                    let Ok(name) = property.get_str("propertyName") else { continue };
+                    if let Ok(details) = property.get_array("details") {
+                        parse_rules(details, &join_path(path, name), errors);
+                    }
+                }
+            },
+            "required" => {
+                for missing in rule.get_array("missingProperties").into_iter().flatten().filter_map(Bson::as_str) {
+                    errors.push(rule_error(&join_path(path, missing), "required", &Bson::Boolean(true), None));
+                }
+            },
+            "additionalProperties" => {
+                for extra in rule.get_array("additionalProperties").into_iter().flatten().filter_map(Bson::as_str) {
+                    errors.push(rule_error(&join_path(path, extra), "additionalProperties", &Bson::Boolean(false), None));
+                }
+            },
+            "items" => {
+                // Older servers report `index`, newer ones `itemIndex`
+                let index = rule.get_i32("itemIndex").or_else(|_| rule.get_i32("index")).ok();
+                let item_path = match index {
+                    Some(i) => join_path(path, &i.to_string()),
+                    None => path.to_string(),
+                };
+                if let Ok(details) = rule.get_array("details") {
+                    parse_rules(details, &item_path, errors);
+                }
+            },
+            "allOf" | "anyOf" | "oneOf" | "not" => {
+                for schema in rule.get_array("schemasNotSatisfied").into_iter().flatten().filter_map(Bson::as_document) {
+                    if let Ok(details) = schema.get_array("details") {
+                        parse_rules(details, path, errors);
+                    }
+                }
+            },
+            _ => {
+                let specified = rule.get_document("specifiedAs").ok()
+                    .and_then(|s| s.get(operator))
+                    .cloned()
+                    .unwrap_or(Bson::Null);
+                let considered = rule.get_str("consideredType").ok()
+                    .map(String::from)
+                    .or_else(|| rule.get("consideredValue").map(describe));
+                errors.push(rule_error(path, operator, &specified, considered));
+            },
+        }
+    }
+}
+
+// Result of checking the documents already in a collection against a schema
+#[derive(Serialize)]
+pub struct ViolationReport {
+    pub total_documents: u64,
+    pub violating_documents: u64,
+    // One entry per (field, rule), most frequent first
+    pub groups: Vec<ViolationGroup>,
+}
+
+#[derive(Serialize)]
+pub struct ViolationGroup {
+    pub field: String,
+    pub rule: String,
+    pub expected: String,
+    pub count: u64,
+    pub sample_ids: Vec<String>,
+}
+
+// Walk the documents that fail `schema` and hand each one, with its field errors, to `visit`.
+// The server decides what fails (so every keyword counts); the field errors come from
+// `validate_document`, with a catch-all entry for rules it doesn't break down.
+pub async fn scan_violations<F>(collection: &Collection<Document>, schema: &Document, mut visit: F) -> Result<(), String>
+where
+    F: FnMut(&Document, Vec<FieldError>),
+{
+    let filter = doc! { "$nor": [{ "$jsonSchema": schema.clone() }] };
+    let mut cursor = collection.find(filter, None)
+        .await
+        .map_err(|e| format!("Failed to scan documents: {}", e))?;
+
+    let validator = SchemaValidator::new(schema);
+    while let Some(document) = cursor.next().await {
+        let document = document.map_err(|e| format!("Failed to scan documents: {}", e))?;
+        let mut errors = validator.validate(&document);
+        if errors.is_empty() {
+            errors.push(FieldError {
+                field: String::new(),
+                rule: "$jsonSchema".to_string(),
+                expected: String::new(),
+                message: "fails a schema rule that can't be traced to a field".to_string(),
+            });
+        }
+        visit(&document, errors);
+    }
+    Ok(())
+}
+
+// Counts and up to `sample_limit` ids per (field, rule) for the documents violating `schema`
+pub async fn violation_report(
+    collection: &Collection<Document>,
+    schema: &Document,
+    sample_limit: usize,
+) -> Result<ViolationReport, String> {
+    let total_documents = collection.count_documents(None, None)
+        .await
+        .map_err(|e| format!("Failed to count documents: {}", e))?;
+
+    let mut violating_documents = 0;
+    let mut groups: Vec<ViolationGroup> = Vec::new();
+    scan_violations(collection, schema, |document, errors| {
+        violating_documents += 1;
+        let id = document.get("_id").map(id_text).unwrap_or_default();
+        for error in errors {
+            let group = match groups.iter_mut().position(|g| g.field == error.field && g.rule == error.rule) {
+                Some(i) => &mut groups[i],
+                None => {
+                    groups.push(ViolationGroup {
+                        field: error.field,
+                        rule: error.rule,
+                        expected: error.expected,
+                        count: 0,
+                        sample_ids: Vec::new(),
+                    });
+                    groups.last_mut().unwrap()
+                },
+            };
+            group.count += 1;
+            if group.sample_ids.len() < sample_limit && !group.sample_ids.contains(&id) {
+                group.sample_ids.push(id.clone());
+            }
+        }
+    }).await?;
+
+    groups.sort_by_key(|g| std::cmp::Reverse(g.count));
+    Ok(ViolationReport { total_documents, violating_documents, groups })
+}
+
+// Every violation as CSV rows of _id, field, rule, message, for cleanup in a spreadsheet
+pub async fn violations_csv(collection: &Collection<Document>, schema: &Document) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["_id", "field", "rule", "message"]).map_err(|e| e.to_string())?;
+
+    let mut write_error = None;
+    scan_violations(collection, schema, |document, errors| {
+        let id = document.get("_id").map(id_text).unwrap_or_default();
+        for error in errors {
+            if let Err(e) = writer.write_record([id.as_str(), &error.field, &error.rule, &error.message]) {
+                write_error.get_or_insert(e.to_string());
+            }
+        }
+    }).await?;
+    if let Some(e) = write_error {
+        return Err(e);
+    }
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+// Check a document against a `$jsonSchema` before it is written, so rejected
+// writes get the same messages whether they come from the server or not.
+// Covers the keywords the schema editor produces: required, bsonType, enum,
+// pattern, minimum/maximum, minLength/maxLength, minItems/maxItems and
+// additionalProperties. Use a `SchemaValidator` when checking many documents.
+pub fn validate_document(doc: &Document, schema: &Document) -> Vec<FieldError> {
+    SchemaValidator::new(schema).validate(doc)
+}
+
+// A schema with its `pattern`s compiled once, for checking many documents
+pub struct SchemaValidator<'a> {
+    schema: &'a Document,
+    // Patterns the regex crate can't compile are missing and left to the server
+    patterns: HashMap<&'a str, Regex>,
+}
+
+impl<'a> SchemaValidator<'a> {
+    pub fn new(schema: &'a Document) -> Self {
+        let mut patterns = HashMap::new();
+        collect_patterns(schema, &mut patterns);
+        Self { schema, patterns }
+    }
+
+    pub fn validate(&self, doc: &Document) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        self.validate_object(doc, self.schema, "", &mut errors);
+        errors
+    }
+
+    // Check the fields a `$set` writes (which may be dotted paths), the way the
+    // "moderate" validation level does: a document that already fails the schema
+    // may still be updated, and only the values being set are checked otherwise.
+    // Rules that span the whole document are left to the server.
+    pub fn validate_update(&self, current: &Document, set: &Document) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if !self.validate(current).is_empty() {
+            return errors;
+        }
+
+        for (path, value) in set {
+            match spec_for_path(self.schema, path) {
+                Some(spec) => self.validate_value(value, spec, path, &mut errors),
+                None => {
+                    let parent = match path.rsplit_once('.') {
+                        Some((parent, _)) => spec_for_path(self.schema, parent),
+                        None => Some(self.schema),
+                    };
+                    if parent.is_some_and(|p| p.get_bool("additionalProperties") == Ok(false)) && path != "_id" {
+                        errors.push(rule_error(path, "additionalProperties", &Bson::Boolean(false), None));
+                    }
+                },
+            }
+        }
+        errors
+    }
+
+    fn validate_object(&self, doc: &Document, spec: &Document, path: &str, errors: &mut Vec<FieldError>) {
+        for required in spec.get_array("required").into_iter().flatten().filter_map(Bson::as_str) {
+            if !doc.contains_key(required) {
+                errors.push(rule_error(&join_path(path, required), "required", &Bson::Boolean(true), None));
+            }
+        }
+
+        let properties = spec.get_document("properties").ok();
+        for (key, value) in doc {
+            match properties.and_then(|p| p.get_document(key).ok()) {
+                Some(property) => self.validate_value(value, property, &join_path(path, key), errors),
+                None if spec.get_bool("additionalProperties") == Ok(false) && key != "_id" => {
+                    errors.push(rule_error(&join_path(path, key), "additionalProperties", &Bson::Boolean(false), None));
+                },
+                None => {},
+            }
+        }
+    }
+
+    fn validate_value(&self, value: &Bson, spec: &Document, path: &str, errors: &mut Vec<FieldError>) {
+        let types = declared_types(spec);
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            errors.push(rule_error(path, "bsonType", spec.get("bsonType").unwrap(), Some(describe(value))));
+            // The other keywords assume the declared type
+            return;
+        }
+
+        if let Ok(allowed) = spec.get_array("enum") {
+            if !allowed.iter().any(|a| same_value(a, value)) {
+                errors.push(rule_error(path, "enum", &Bson::Array(allowed.clone()), Some(describe(value))));
+            }
+        }
+
+        match value {
+            Bson::String(s) => {
+                if let Ok(pattern) = spec.get_str("pattern") {
+                    if self.patterns.get(pattern).is_some_and(|re| !re.is_match(s)) {
+                        errors.push(rule_error(path, "pattern", &Bson::String(pattern.to_string()), Some(describe(value))));
+                    }
+                }
+                let length = s.chars().count() as f64;
+                check_bound(spec, "minLength", |min| length >= min, path, value, errors);
+                check_bound(spec, "maxLength", |max| length <= max, path, value, errors);
+            },
+            Bson::Array(items) => {
+                let count = items.len() as f64;
+                check_bound(spec, "minItems", |min| count >= min, path, value, errors);
+                check_bound(spec, "maxItems", |max| count <= max, path, value, errors);
+                if let Ok(item_spec) = spec.get_document("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        self.validate_value(item, item_spec, &join_path(path, &i.to_string()), errors);
+                    }
+                }
+            },
+            Bson::Document(doc) => self.validate_object(doc, spec, path, errors),
+            _ => {
+                if let Some(number) = as_number(value) {
+                    check_bound(spec, "minimum", |min| number >= min, path, value, errors);
+                    check_bound(spec, "maximum", |max| number <= max, path, value, errors);
+                }
+            },
+        }
+    }
+}
+
+fn collect_patterns<'a>(spec: &'a Document, patterns: &mut HashMap<&'a str, Regex>) {
+    if let Ok(pattern) = spec.get_str("pattern") {
+        if !patterns.contains_key(pattern) {
+            if let Ok(re) = Regex::new(pattern) {
+                patterns.insert(pattern, re);
+            }
+        }
+    }
+    for property in spec.get_document("properties").into_iter().flat_map(|p| p.values()).filter_map(Bson::as_document) {
+        collect_patterns(property, patterns);
+    }
+    if let Ok(items) = spec.get_document("items") {
+        collect_patterns(items, patterns);
+    }
+}
+
+// Equality as the server sees it for `enum`: numbers compare by value whatever
+// their type, so Int32(1), Int64(1) and Double(1.0) are the same
+fn same_value(a: &Bson, b: &Bson) -> bool {
+    match (as_number(a), as_number(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+fn check_bound(
+    spec: &Document,
+    keyword: &str,
+    satisfied: impl Fn(f64) -> bool,
+    path: &str,
+    value: &Bson,
+    errors: &mut Vec<FieldError>,
+) {
+    if let Some(bound) = spec.get(keyword) {
+        if let Some(limit) = as_number(bound) {
+            if !satisfied(limit) {
+                errors.push(rule_error(path, keyword, bound, Some(describe(value))));
+            }
+        }
+    }
+}
+
+fn as_number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(i) => Some(*i as f64),
+        Bson::Int64(i) => Some(*i as f64),
+        Bson::Double(d) => Some(*d),
+        Bson::Decimal128(d) => d.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+// One error for a failed keyword, worded the same for server and local checks.
+// `got` describes the offending value when it is known.
+fn rule_error(field: &str, rule: &str, specified: &Bson, got: Option<String>) -> FieldError {
+    let expected = match specified {
+        Bson::Array(items) => {
+            let separator = if rule == "bsonType" || rule == "type" { " | " } else { ", " };
+            items.iter().map(bson_text).collect::<Vec<_>>().join(separator)
+        },
+        other => bson_text(other),
+    };
+
+    let message = match rule {
+        "required" => "is required".to_string(),
+        "additionalProperties" => "is not allowed by the schema".to_string(),
+        "bsonType" | "type" => format!("must be {}", expected),
+        "enum" => format!("must be one of: {}", expected),
+        "pattern" => format!("must match the pattern {}", expected),
+        "minimum" => format!("must be at least {}", expected),
+        "maximum" => format!("must be at most {}", expected),
+        "minLength" => format!("must be at least {} characters long", expected),
+        "maxLength" => format!("must be at most {} characters long", expected),
+        "minItems" => format!("must have at least {} items", expected),
+        "maxItems" => format!("must have at most {} items", expected),
+        other => format!("does not satisfy '{}'", other),
+    };
+    let message = match got {
+        Some(got) if rule != "required" => format!("{}, got {}", message, got),
+        _ => message,
+    };
+
+    let expected = match rule {
+        "required" => "a value".to_string(),
+        "additionalProperties" => "no such field".to_string(),
+        "enum" => format!("one of: {}", expected),
+        _ => expected,
+    };
+
+    FieldError {
+        field: field.to_string(),
+        rule: rule.to_string(),
+        expected,
+        message,
+    }
+}
+
+fn bson_text(value: &Bson) -> String {
+    match value {
+        Bson::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::error::WriteError;
+
+    fn write_error(code: i32, details: Document) -> Error {
+        let write_error: WriteError = mongodb::bson::from_document(doc! {
+            "code": code,
+            "errmsg": "Document failed validation",
+            "errInfo": { "failingDocumentId": 1, "details": details },
+        }).unwrap();
+        Error::from(ErrorKind::Write(WriteFailure::WriteError(write_error)))
+    }
+
+    // (field, rule, expected) of each error
+    type Fields<'a> = Vec<(&'a str, &'a str, &'a str)>;
+
+    fn fields(errors: &[FieldError]) -> Fields<'_> {
+        errors.iter().map(|e| (e.field.as_str(), e.rule.as_str(), e.expected.as_str())).collect()
+    }
+
+    #[test]
+    fn parses_validator_details() {
+        let cases: Vec<(Document, Fields)> = vec![
+            (
+                doc! { "operatorName": "required", "specifiedAs": { "required": ["title"] }, "missingProperties": ["title", "isbn"] },
+                vec![("title", "required", "a value"), ("isbn", "required", "a value")],
+            ),
+            (
+                doc! { "operatorName": "properties", "propertiesNotSatisfied": [{
+                    "propertyName": "pages",
+                    "details": [{ "operatorName": "bsonType", "specifiedAs": { "bsonType": "int" }, "reason": "type did not match", "consideredValue": "12", "consideredType": "string" }],
+                }] },
+                vec![("pages", "bsonType", "int")],
+            ),
+            (
+                doc! { "operatorName": "additionalProperties", "specifiedAs": { "additionalProperties": false }, "additionalProperties": ["extra"] },
+                vec![("extra", "additionalProperties", "no such field")],
+            ),
+            (
+                doc! { "operatorName": "properties", "propertiesNotSatisfied": [{
+                    "propertyName": "address",
+                    "details": [{ "operatorName": "properties", "propertiesNotSatisfied": [{
+                        "propertyName": "zip",
+                        "details": [{ "operatorName": "minimum", "specifiedAs": { "minimum": 1000 }, "consideredValue": 5 }],
+                    }] }],
+                }] },
+                vec![("address.zip", "minimum", "1000")],
+            ),
+            (
+                doc! { "operatorName": "properties", "propertiesNotSatisfied": [{
+                    "propertyName": "tags",
+                    "details": [{ "operatorName": "items", "itemIndex": 2, "details": [
+                        { "operatorName": "enum", "specifiedAs": { "enum": ["a", "b"] }, "consideredValue": "c" },
+                    ] }],
+                }] },
+                vec![("tags.2", "enum", "one of: a, b")],
+            ),
+            (
+                // Older servers name the position `index`
+                doc! { "operatorName": "properties", "propertiesNotSatisfied": [{
+                    "propertyName": "tags",
+                    "details": [{ "operatorName": "items", "index": 0, "details": [
+                        { "operatorName": "maxLength", "specifiedAs": { "maxLength": 3 }, "consideredValue": "long" },
+                    ] }],
+                }] },
+                vec![("tags.0", "maxLength", "3")],
+            ),
+            (
+                doc! { "operatorName": "allOf", "schemasNotSatisfied": [{ "index": 0, "details": [
+                    { "operatorName": "required", "missingProperties": ["title"] },
+                ] }] },
+                vec![("title", "required", "a value")],
+            ),
+            (
+                doc! { "operatorName": "bsonType", "specifiedAs": { "bsonType": ["int", "null"] }, "consideredType": "string" },
+                vec![("", "bsonType", "int | null")],
+            ),
+        ];
+
+        for (rule, expected) in cases {
+            let details = doc! { "operatorName": "$jsonSchema", "schemaRulesNotSatisfied": [rule.clone()] };
+            let errors = validation_failure(&write_error(DOCUMENT_VALIDATION_FAILURE, details)).unwrap();
+            assert_eq!(fields(&errors), expected, "rule {}", rule);
+        }
+    }
+
+    #[test]
+    fn describes_the_offending_value() {
+        let details = doc! { "schemaRulesNotSatisfied": [{ "operatorName": "properties", "propertiesNotSatisfied": [{
+            "propertyName": "pages",
+            "details": [{ "operatorName": "bsonType", "specifiedAs": { "bsonType": "int" }, "consideredType": "string" }],
+        }] }] };
+        let errors = parse_validation_details(&details);
+        assert_eq!(errors[0].message, "must be int, got string");
+
+        let error = write_error(DOCUMENT_VALIDATION_FAILURE, details);
+        assert_eq!(describe_write_error(&error), "Document failed schema validation: Field 'pages': must be int, got string");
+    }
+
+    #[test]
+    fn ignores_other_write_errors() {
+        let duplicate = write_error(11000, doc! {});
+        assert!(validation_failure(&duplicate).is_none());
+        assert!(validation_failure(&Error::custom("network")).is_none());
+    }
+
+    fn book_schema() -> Document {
+        doc! {
+            "required": ["title"],
+            "additionalProperties": false,
+            "properties": {
+                "_id": {},
+                "title": { "bsonType": "string", "pattern": "^[A-Z]" },
+                "status": { "enum": [1, 2] },
+                "row_height": { "bsonType": "int", "minimum": 20 },
+                "address": { "bsonType": "object", "additionalProperties": false, "properties": {
+                    "zip": { "bsonType": "string", "pattern": "^[0-9]{4}$" },
+                } },
+            },
+        }
+    }
+
+    #[test]
+    fn validates_documents() {
+        let schema = book_schema();
+        let validator = SchemaValidator::new(&schema);
+        let cases: Vec<(Document, Fields)> = vec![
+            (doc! { "title": "Dune", "status": 1 }, vec![]),
+            // Numbers match enum entries by value
+            (doc! { "title": "Dune", "status": 2_i64 }, vec![]),
+            (doc! { "title": "Dune", "status": 1.0 }, vec![]),
+            (doc! { "title": "Dune", "status": 3 }, vec![("status", "enum", "one of: 1, 2")]),
+            (doc! { "title": "dune" }, vec![("title", "pattern", "^[A-Z]")]),
+            (doc! { "title": "Dune", "address": { "zip": "12a" } }, vec![("address.zip", "pattern", "^[0-9]{4}$")]),
+            (doc! { "extra": 1 }, vec![("title", "required", "a value"), ("extra", "additionalProperties", "no such field")]),
+        ];
+        for (doc, expected) in cases {
+            assert_eq!(fields(&validator.validate(&doc)), expected, "document {}", doc);
+            assert_eq!(fields(&validate_document(&doc, &schema)), expected, "document {}", doc);
+        }
+    }
+
+    #[test]
+    fn validates_only_the_fields_an_update_sets() {
+        let schema = book_schema();
+        let validator = SchemaValidator::new(&schema);
+        let valid = doc! { "title": "Dune" };
+        // Stored before the schema existed; moderate validation still allows updates
+        let legacy = doc! { "title": "dune", "extra": 1 };
+        let cases: Vec<(&Document, Document, Fields)> = vec![
+            (&valid, doc! { "row_height": 40 }, vec![]),
+            (&valid, doc! { "row_height": 10 }, vec![("row_height", "minimum", "20")]),
+            (&valid, doc! { "title": "dune" }, vec![("title", "pattern", "^[A-Z]")]),
+            (&valid, doc! { "address.zip": "12a" }, vec![("address.zip", "pattern", "^[0-9]{4}$")]),
+            (&valid, doc! { "address.city": "Oslo" }, vec![("address.city", "additionalProperties", "no such field")]),
+            (&valid, doc! { "extra": 1 }, vec![("extra", "additionalProperties", "no such field")]),
+            (&legacy, doc! { "row_height": 40 }, vec![]),
+            (&legacy, doc! { "row_height": 10 }, vec![]),
+        ];
+        for (current, set, expected) in cases {
+            assert_eq!(fields(&validator.validate_update(current, &set)), expected, "update {} of {}", set, current);
+        }
+    }
+}
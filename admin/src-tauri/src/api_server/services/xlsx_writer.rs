@@ -0,0 +1,199 @@
+// src/api_server/services/xlsx_writer.rs
+
+// Single-sheet XLSX export on top of rust_xlsxwriter, with column widths and a
+// frozen bold header row. The sheet runs in constant-memory mode (rows go to a
+// temporary file as they are written) and the finished workbook is saved to a
+// temporary file too, so large exports don't have to fit in memory.
+
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+
+// Excel counts days from 1899-12-30; 25569 is 1970-01-01
+const EXCEL_UNIX_EPOCH_DAYS: f64 = 25569.0;
+const MILLIS_PER_DAY: f64 = 86_400_000.0;
+
+// Sheet names are limited to 31 characters
+const MAX_SHEET_NAME_LEN: usize = 31;
+
+// Longest text a cell can hold; longer values are cut rather than failing the export
+const MAX_CELL_TEXT_LEN: usize = 32_767;
+
+#[derive(Debug, Clone)]
+pub enum XlsxCell {
+    Empty,
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    // Milliseconds since the Unix epoch, written as an Excel date
+    DateTime(i64),
+}
+
+pub struct XlsxSheet {
+    workbook: Workbook,
+    header_format: Format,
+    date_format: Format,
+    next_row: u32,
+}
+
+impl XlsxSheet {
+    // `widths` are in Excel character units, one per column
+    pub fn new(name: &str, widths: &[Option<f64>]) -> Result<Self, String> {
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet_with_constant_memory();
+        sheet.set_name(sanitize_sheet_name(name)).map_err(xlsx_error)?;
+        sheet.set_freeze_panes(1, 0).map_err(xlsx_error)?;
+        for (index, width) in widths.iter().enumerate() {
+            if let Some(width) = width {
+                sheet.set_column_width(column_number(index)?, *width).map_err(xlsx_error)?;
+            }
+        }
+
+        Ok(Self {
+            workbook,
+            header_format: Format::new().set_bold(),
+            date_format: Format::new().set_num_format("m/d/yyyy h:mm"),
+            next_row: 0,
+        })
+    }
+
+    pub fn add_header(&mut self, headers: &[String]) -> Result<(), String> {
+        let row = self.take_row();
+        let sheet = self.workbook.worksheet_from_index(0).map_err(xlsx_error)?;
+        for (index, header) in headers.iter().enumerate() {
+            sheet.write_string_with_format(row, column_number(index)?, cell_text(header), &self.header_format)
+                .map_err(xlsx_error)?;
+        }
+        Ok(())
+    }
+
+    // Fails once the sheet is out of rows or columns
+    pub fn add_row(&mut self, cells: &[XlsxCell]) -> Result<(), String> {
+        let row = self.take_row();
+        let sheet = self.workbook.worksheet_from_index(0).map_err(xlsx_error)?;
+
+        for (index, cell) in cells.iter().enumerate() {
+            let col = column_number(index)?;
+            let written = match cell {
+                XlsxCell::Empty => continue,
+                XlsxCell::Text(text) => sheet.write_string(row, col, cell_text(text)),
+                XlsxCell::Number(n) if n.is_finite() => sheet.write_number(row, col, *n),
+                XlsxCell::Number(n) => sheet.write_string(row, col, n.to_string()),
+                XlsxCell::Bool(b) => sheet.write_boolean(row, col, *b),
+                XlsxCell::DateTime(millis) => {
+                    let serial = *millis as f64 / MILLIS_PER_DAY + EXCEL_UNIX_EPOCH_DAYS;
+                    sheet.write_number_with_format(row, col, serial, &self.date_format)
+                },
+            };
+            written.map_err(xlsx_error)?;
+        }
+        Ok(())
+    }
+
+    // Assemble the workbook into a temporary file, positioned at its start
+    pub fn finish(mut self) -> Result<File, String> {
+        let mut file = tempfile::tempfile().map_err(|e| format!("Failed to create temporary file: {}", e))?;
+        self.workbook.save_to_writer(&mut file).map_err(xlsx_error)?;
+        file.seek(SeekFrom::Start(0)).map_err(|e| format!("Failed to read workbook: {}", e))?;
+        Ok(file)
+    }
+
+    fn take_row(&mut self) -> u32 {
+        self.next_row += 1;
+        self.next_row - 1
+    }
+}
+
+// Convert a pixel width from the table UI to Excel character units
+pub fn pixels_to_width(pixels: f64) -> f64 {
+    (pixels / 7.0).clamp(4.0, 255.0)
+}
+
+fn column_number(index: usize) -> Result<u16, String> {
+    u16::try_from(index).map_err(|_| xlsx_error(XlsxError::RowColumnLimitError))
+}
+
+fn cell_text(text: &str) -> String {
+    text.chars().take(MAX_CELL_TEXT_LEN).collect()
+}
+
+fn xlsx_error(e: XlsxError) -> String {
+    format!("Failed to write spreadsheet: {}", e)
+}
+
+fn sanitize_sheet_name(name: &str) -> String {
+    let cleaned: String = name.chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+        .take(MAX_SHEET_NAME_LEN)
+        .collect();
+    // Excel also rejects names starting or ending with an apostrophe
+    let cleaned = cleaned.trim_matches('\'');
+    if cleaned.trim().is_empty() { "Sheet1".to_string() } else { cleaned.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn sheet_xml(sheet: XlsxSheet) -> String {
+        let file = sheet.finish().expect("workbook saved");
+        let mut archive = zip::ZipArchive::new(file).expect("output is a zip archive");
+        let mut xml = String::new();
+        archive.by_name("xl/worksheets/sheet1.xml").expect("sheet part")
+            .read_to_string(&mut xml).expect("sheet is UTF-8");
+        xml
+    }
+
+    #[test]
+    fn round_trips_typed_cells() {
+        let mut sheet = XlsxSheet::new("books", &[Some(20.0), None, None, None]).unwrap();
+        sheet.add_header(&["title".to_string(), "pages".to_string(), "lent".to_string(), "added".to_string()]).unwrap();
+        sheet.add_row(&[
+            XlsxCell::Text("Tom & <Jerry>".to_string()),
+            XlsxCell::Number(312.0),
+            XlsxCell::Bool(true),
+            XlsxCell::DateTime(0),
+        ]).unwrap();
+        sheet.add_row(&[XlsxCell::Empty, XlsxCell::Number(f64::NAN)]).unwrap();
+
+        let xml = sheet_xml(sheet);
+        assert!(xml.contains("Tom &amp; &lt;Jerry&gt;"));
+        assert!(xml.contains("<v>312</v>"));
+        assert!(xml.contains("t=\"b\""));
+        assert!(xml.contains("<v>25569</v>"), "dates are Excel serials");
+        assert!(xml.contains("NaN"), "non-finite numbers are written as text");
+        assert!(xml.contains("state=\"frozen\""));
+    }
+
+    #[test]
+    fn cuts_text_to_the_cell_limit() {
+        let mut sheet = XlsxSheet::new("long", &[]).unwrap();
+        sheet.add_row(&[XlsxCell::Text("x".repeat(MAX_CELL_TEXT_LEN + 10))]).unwrap();
+        let xml = sheet_xml(sheet);
+        assert!(xml.contains(&"x".repeat(MAX_CELL_TEXT_LEN)));
+        assert!(!xml.contains(&"x".repeat(MAX_CELL_TEXT_LEN + 1)));
+    }
+
+    #[test]
+    fn rejects_columns_past_the_sheet_limit() {
+        let mut sheet = XlsxSheet::new("wide", &[]).unwrap();
+        let cells = vec![XlsxCell::Number(1.0); 16_385];
+        assert!(sheet.add_row(&cells).is_err());
+    }
+
+    #[test]
+    fn sanitizes_sheet_names() {
+        let cases = [
+            ("books", "books"),
+            ("a/b:c", "a_b_c"),
+            ("'quoted'", "quoted"),
+            ("", "Sheet1"),
+            ("   ", "Sheet1"),
+            ("abcdefghijklmnopqrstuvwxyz0123456789", "abcdefghijklmnopqrstuvwxyz01234"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(sanitize_sheet_name(name), expected, "sheet name {:?}", name);
+        }
+    }
+}
@@ -1,6 +1,10 @@
 // src/api_server/state.rs
 use crate::mongodb_manager::MongoDbState;
 use crate::session::SessionManager;
+use crate::api_server::events::EventBus;
+use crate::timezone::InstitutionTimezone;
+use crate::api_server::services::lifecycle_service::PendingConfirmations;
+use crate::api_server::services::SchemaCache;
 use std::sync::Arc;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -13,6 +17,12 @@ pub struct ApiServerState {
     pub server_handle: Option<tokio::task::JoinHandle<()>>,
     pub routes: Vec<String>,
     pub temp_dirs: Arc<AsyncMutex<HashMap<String, PathBuf>>>,
+    pub event_bus: EventBus,
+    pub timezone: InstitutionTimezone,
+    // Confirmation tokens for collection rename/clone/truncate/drop
+    pub confirmations: Arc<AsyncMutex<PendingConfirmations>>,
+    // Collection schemas and UI metadata, shared with the Tauri commands
+    pub schema_cache: SchemaCache,
 }
 
 impl ApiServerState {
@@ -23,6 +33,10 @@ impl ApiServerState {
             server_handle: None,
             routes: Vec::new(),
             temp_dirs: Arc::new(AsyncMutex::new(HashMap::new())),
+            event_bus: EventBus::new(),
+            timezone: InstitutionTimezone::from_env(),
+            confirmations: Arc::new(AsyncMutex::new(HashMap::new())),
+            schema_cache: SchemaCache::from_env(),
         }
     }
 }
\ No newline at end of file
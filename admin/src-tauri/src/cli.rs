@@ -0,0 +1,122 @@
+// src/cli.rs
+
+// Command-line actions, run instead of the app when the first argument names one:
+//
+//   vue-tauri export-schemas [--format ts|json-schema|openapi] [--out <path>]
+//                            [--collections a,b] [--uri <mongodb uri>] [--database <name>]
+//   vue-tauri data-dictionary [--format json|html|md|mermaid|dot] [--out <file>]
+//                             [--uri <mongodb uri>] [--database <name>]
+//
+// Without --out the output is printed. With it, json-schema writes
+// <collection>.schema.json files into the --out directory; everything else is one file.
+// Windows release builds have no console to print to, so pass --out there.
+
+use mongodb::{options::ClientOptions, Client, Database};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::api_server::services::dictionary_service::{self, build_dictionary, DictionaryFormat};
+use crate::api_server::services::schema_cache::SchemaCache;
+use crate::api_server::services::schema_export_service::{json_schemas, load_schemas, render, SchemaFormat};
+use crate::mongodb_manager::{DATABASE_NAME, DEFAULT_CONNECTION_STRING};
+use crate::timezone::InstitutionTimezone;
+
+// Run the action named by `args` (without the program name) and return its exit
+// code, or None when the arguments don't name an action and the app should start
+pub fn run(args: &[String]) -> Option<i32> {
+    let (action, rest) = args.split_first()?;
+    let result = match action.as_str() {
+        "export-schemas" => options(rest).and_then(|options| block_on(export_schemas(options))),
+        "data-dictionary" => options(rest).and_then(|options| block_on(data_dictionary(options))),
+        _ => return None,
+    };
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("{}: {}", action, e);
+            Some(1)
+        },
+    }
+}
+
+// --name value pairs
+fn options(args: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = arg.strip_prefix("--").ok_or_else(|| format!("Unexpected argument '{}'", arg))?;
+        let value = args.next().ok_or_else(|| format!("Missing value for --{}", name))?;
+        options.insert(name.to_string(), value.clone());
+    }
+    Ok(options)
+}
+
+// --uri (or MONGODB_URI) and --database, defaulting to the local server the app uses
+async fn connect(options: &HashMap<String, String>) -> Result<Database, String> {
+    let uri = options.get("uri").cloned()
+        .or_else(|| std::env::var("MONGODB_URI").ok())
+        .unwrap_or_else(|| DEFAULT_CONNECTION_STRING.to_string());
+    let database = options.get("database").map(String::as_str).unwrap_or(DATABASE_NAME);
+
+    let client_options = ClientOptions::parse(&uri)
+        .await
+        .map_err(|e| format!("Failed to parse connection string: {}", e))?;
+    let client = Client::with_options(client_options)
+        .map_err(|e| format!("Failed to create MongoDB client: {}", e))?;
+    Ok(client.database(database))
+}
+
+// Each action reads the schemas once, so there's nothing to keep between lookups
+fn uncached() -> SchemaCache {
+    SchemaCache::new(Duration::ZERO)
+}
+
+fn block_on<F: std::future::Future<Output = Result<(), String>>>(future: F) -> Result<(), String> {
+    tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Failed to start the runtime: {}", e))?
+        .block_on(future)
+}
+
+async fn export_schemas(options: HashMap<String, String>) -> Result<(), String> {
+    let format = SchemaFormat::parse(options.get("format").map(String::as_str).unwrap_or_default())?;
+    let only: Option<Vec<String>> = options.get("collections")
+        .map(|list| list.split(',').map(str::trim).filter(|n| !n.is_empty()).map(String::from).collect());
+    let schemas = load_schemas(&uncached(), &connect(&options).await?, only.as_deref())
+        .await
+        .map_err(|(_, e)| e)?;
+
+    match (options.get("out"), format) {
+        (None, _) => println!("{}", render(format, &schemas)),
+        (Some(dir), SchemaFormat::JsonSchema) => {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create '{}': {}", dir, e))?;
+            for (name, document) in json_schemas(&schemas) {
+                let path = Path::new(dir).join(format!("{}.schema.json", name));
+                let text = serde_json::to_string_pretty(&document).unwrap_or_default();
+                std::fs::write(&path, text).map_err(|e| format!("Failed to write '{}': {}", path.display(), e))?;
+            }
+            eprintln!("Wrote {} schema(s) to {}", schemas.len(), dir);
+        },
+        (Some(file), _) => {
+            std::fs::write(file, render(format, &schemas)).map_err(|e| format!("Failed to write '{}': {}", file, e))?;
+            eprintln!("Wrote {} collection(s) to {}", schemas.len(), file);
+        },
+    }
+    Ok(())
+}
+
+async fn data_dictionary(options: HashMap<String, String>) -> Result<(), String> {
+    let format = DictionaryFormat::parse(options.get("format").map(String::as_str).unwrap_or_default())?;
+    let dictionary = build_dictionary(&uncached(), &connect(&options).await?, &InstitutionTimezone::from_env())
+        .await
+        .map_err(|(_, e)| e)?;
+    let text = dictionary_service::render(format, &dictionary);
+    match options.get("out") {
+        None => println!("{}", text),
+        Some(file) => {
+            std::fs::write(file, text).map_err(|e| format!("Failed to write '{}': {}", file, e))?;
+            eprintln!("Wrote {} collection(s) to {}", dictionary.collections.len(), file);
+        },
+    }
+    Ok(())
+}
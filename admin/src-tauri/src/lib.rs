@@ -11,6 +11,10 @@ mod lib_mongodb_schema;
 mod api_server;
 mod session; // Add the session module
 mod auth; // Add the auth module
+mod timezone;
+mod schema_pack;
+mod migrations;
+mod cli;
 
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -18,6 +22,12 @@ fn greet(name: &str) -> String {
 }
 
 
+// Run a command-line action such as `export-schemas` if the arguments name one;
+// returns its exit code, or None when the app should start instead
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    cli::run(args)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize tracing (for API server logs)
@@ -28,7 +38,7 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
             // Initialize MongoDB state
-            let mongodb_state = mongodb_manager::MongoDbState::new("app_database");
+            let mongodb_state = mongodb_manager::MongoDbState::new(mongodb_manager::DATABASE_NAME);
             app.manage(mongodb_state.clone());
 
             // Initialize session manager
@@ -36,10 +46,24 @@ pub fn run() {
             app.manage(session_manager.clone());
 
             // Initialize API server state with MongoDB reference
-            let api_server_state = Arc::new(Mutex::new(api_server::ApiServerState::new(
+            let api_server_state = api_server::ApiServerState::new(
                 mongodb_state.clone(),
                 session_manager.clone()
-            )));
+            );
+
+            // Tauri commands read schemas through the same cache as the API, and
+            // use the institution timezone it read at startup
+            app.manage(api_server_state.schema_cache.clone());
+            app.manage(api_server_state.timezone);
+
+            // Push document change events to the frontend, sourced from MongoDB
+            // change streams when the server runs as a replica set
+            let event_bus = api_server_state.event_bus.clone();
+            app.manage(event_bus.clone());
+            api_server::events::forward_to_frontend(app.handle().clone(), event_bus.clone());
+            api_server::events::spawn_change_stream_watcher(mongodb_state.clone(), event_bus);
+
+            let api_server_state = Arc::new(Mutex::new(api_server_state));
             
             // Clone the state for the auto-start task
             let auto_start_state = api_server_state.clone();
@@ -84,6 +108,8 @@ pub fn run() {
             mongodb_manager::list_collections,
             mongodb_manager::get_collection_schema,
             mongodb_manager::initialize_library_collections,
+            mongodb_manager::get_migration_status,
+            mongodb_manager::rollback_migrations,
             
             // API server commands
             api_server::is_api_server_running,
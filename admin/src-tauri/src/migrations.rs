@@ -0,0 +1,315 @@
+// src/migrations.rs
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Database;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::api_server::services::schema_service::list_collection_schemas;
+use crate::mongodb_schema::DEFAULT_ROW_HEIGHT;
+use crate::timezone::InstitutionTimezone;
+
+// Collection recording applied migrations, one document per version: { _id: version, name, applied_at, duration_ms }
+pub const MIGRATIONS_COLLECTION: &str = "_migrations";
+
+// Documents rewritten per round trip by `transform_in_batches`
+const BATCH_SIZE: i64 = 500;
+
+// Text gender values of school_accounts as they were before gender_codes_to_int,
+// as { _id, gender }, so the conversion can be undone document by document
+pub const GENDER_TEXT_COLLECTION: &str = "_migration_gender_text";
+
+type StepFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+type Step = fn(&Database) -> StepFuture<'_>;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: Step,
+    // None for migrations that can't be undone
+    down: Option<Step>,
+}
+
+// Every migration, in the order they run. Versions only ever grow; never renumber or
+// remove an entry that has shipped.
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "backfill_row_height", up: backfill_row_height, down: None },
+    Migration { version: 2, name: "gender_codes_to_int", up: gender_codes_to_int, down: Some(gender_codes_to_string) },
+];
+
+#[derive(Serialize)]
+pub struct MigrationStatus {
+    pub current_version: i64,
+    pub latest_version: i64,
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<PendingMigration>,
+}
+
+#[derive(Serialize)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: String,
+    pub duration_ms: i64,
+}
+
+#[derive(Serialize)]
+pub struct PendingMigration {
+    pub version: i64,
+    pub name: String,
+    pub reversible: bool,
+}
+
+fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+async fn applied_migrations(db: &Database) -> Result<Vec<Document>, String> {
+    let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+    let cursor = db.collection::<Document>(MIGRATIONS_COLLECTION)
+        .find(None, options)
+        .await
+        .map_err(|e| format!("Failed to read applied migrations: {}", e))?;
+    cursor.try_collect()
+        .await
+        .map_err(|e| format!("Failed to read applied migrations: {}", e))
+}
+
+fn version_of(record: &Document) -> Option<i64> {
+    match record.get("_id") {
+        Some(Bson::Int32(v)) => Some(*v as i64),
+        Some(Bson::Int64(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+// Refuse a database migrated by a newer build of the app, whose schema this one
+// doesn't know; call it before writing any validator or index. Returns the applied versions.
+pub async fn check_compatible(db: &Database) -> Result<Vec<i64>, String> {
+    let applied: Vec<i64> = applied_migrations(db).await?.iter().filter_map(version_of).collect();
+
+    let latest = latest_version();
+    if let Some(newer) = applied.iter().find(|v| **v > latest) {
+        return Err(format!(
+            "Database is at migration version {}, newer than this app knows ({}); update the app before connecting",
+            newer, latest
+        ));
+    }
+    Ok(applied)
+}
+
+// Apply every migration that hasn't run yet, in version order, and return their versions.
+// Refuses to do anything when the database has been migrated by a newer build of the app.
+pub async fn run_pending(db: &Database) -> Result<Vec<i64>, String> {
+    let applied = check_compatible(db).await?;
+
+    let mut ran = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        info!("Running migration {} ({})", migration.version, migration.name);
+        let started = Instant::now();
+        (migration.up)(db)
+            .await
+            .map_err(|e| format!("Migration {} ({}) failed: {}", migration.version, migration.name, e))?;
+
+        db.collection::<Document>(MIGRATIONS_COLLECTION)
+            .insert_one(doc! {
+                "_id": migration.version,
+                "name": migration.name,
+                "applied_at": mongodb::bson::DateTime::now(),
+                "duration_ms": started.elapsed().as_millis() as i64,
+            }, None)
+            .await
+            .map_err(|e| format!("Migration {} ran but could not be recorded: {}", migration.version, e))?;
+        ran.push(migration.version);
+    }
+    Ok(ran)
+}
+
+// Undo applied migrations above `target_version`, newest first, and return their versions.
+// Stops before touching anything if one of them has no down step.
+pub async fn rollback_to(db: &Database, target_version: i64) -> Result<Vec<i64>, String> {
+    let applied: Vec<i64> = applied_migrations(db).await?.iter().filter_map(version_of).collect();
+
+    let mut to_undo = Vec::new();
+    for version in applied.iter().rev().filter(|v| **v > target_version) {
+        let migration = MIGRATIONS.iter()
+            .find(|m| m.version == *version)
+            .ok_or_else(|| format!("Migration {} is unknown to this app and can't be rolled back", version))?;
+        let down = migration.down
+            .ok_or_else(|| format!("Migration {} ({}) can't be rolled back", migration.version, migration.name))?;
+        to_undo.push((migration, down));
+    }
+
+    let mut undone = Vec::new();
+    for (migration, down) in to_undo {
+        info!("Rolling back migration {} ({})", migration.version, migration.name);
+        down(db)
+            .await
+            .map_err(|e| format!("Rolling back migration {} ({}) failed: {}", migration.version, migration.name, e))?;
+        db.collection::<Document>(MIGRATIONS_COLLECTION)
+            .delete_one(doc! { "_id": migration.version }, None)
+            .await
+            .map_err(|e| format!("Migration {} was rolled back but its record remains: {}", migration.version, e))?;
+        undone.push(migration.version);
+    }
+    Ok(undone)
+}
+
+pub async fn status(db: &Database) -> Result<MigrationStatus, String> {
+    let records = applied_migrations(db).await?;
+    let timezone = InstitutionTimezone::from_env();
+
+    let applied: Vec<AppliedMigration> = records.iter()
+        .filter_map(|record| {
+            Some(AppliedMigration {
+                version: version_of(record)?,
+                name: record.get_str("name").unwrap_or_default().to_string(),
+                applied_at: record.get_datetime("applied_at").map(|d| timezone.format_iso(d)).unwrap_or_default(),
+                duration_ms: record.get_i64("duration_ms").unwrap_or_default(),
+            })
+        })
+        .collect();
+    let pending = MIGRATIONS.iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| PendingMigration { version: m.version, name: m.name.to_string(), reversible: m.down.is_some() })
+        .collect();
+
+    Ok(MigrationStatus {
+        current_version: applied.iter().map(|a| a.version).max().unwrap_or(0),
+        latest_version: latest_version(),
+        applied,
+        pending,
+    })
+}
+
+// Rewrite the documents matching `filter` in batches, walking them in _id order.
+// `transform` returns the update for a document, or None to leave it alone.
+// Validation is bypassed so documents that already break the schema don't stop the run.
+pub async fn transform_in_batches<F>(
+    db: &Database,
+    collection_name: &str,
+    filter: Document,
+    transform: F,
+) -> Result<u64, String>
+where
+    F: Fn(&Document) -> Option<Document>,
+{
+    let collection = db.collection::<Document>(collection_name);
+    let update_options = UpdateOptions::builder().bypass_document_validation(true).build();
+    let mut last_id: Option<Bson> = None;
+    let mut modified = 0;
+
+    loop {
+        let mut batch_filter = filter.clone();
+        if let Some(id) = &last_id {
+            batch_filter = doc! { "$and": [filter.clone(), { "_id": { "$gt": id.clone() } }] };
+        }
+        let options = FindOptions::builder().sort(doc! { "_id": 1 }).limit(BATCH_SIZE).build();
+        let batch: Vec<Document> = collection.find(batch_filter, options)
+            .await
+            .map_err(|e| e.to_string())?
+            .try_collect()
+            .await
+            .map_err(|e| e.to_string())?;
+        if batch.is_empty() {
+            break;
+        }
+
+        for document in &batch {
+            let (Some(id), Some(update)) = (document.get("_id"), transform(document)) else { continue };
+            let result = collection.update_one(doc! { "_id": id.clone() }, update, update_options.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+            modified += result.modified_count;
+        }
+        last_id = batch.last().and_then(|d| d.get("_id").cloned());
+    }
+    Ok(modified)
+}
+
+// ----- Migrations -----
+
+// Give every document in collections with a row_height property an explicit height
+fn backfill_row_height(db: &Database) -> StepFuture<'_> {
+    Box::pin(async move {
+        let schemas = list_collection_schemas(db).await?;
+        for (collection_name, schema) in schemas {
+            let has_row_height = schema.get_document("properties")
+                .map(|p| p.contains_key("row_height"))
+                .unwrap_or(false);
+            if !has_row_height {
+                continue;
+            }
+            let modified = transform_in_batches(db, &collection_name, doc! { "row_height": { "$exists": false } }, |_| {
+                Some(doc! { "$set": { "row_height": DEFAULT_ROW_HEIGHT } })
+            }).await?;
+            info!("Backfilled row_height on {} document(s) in {}", modified, collection_name);
+        }
+        Ok(())
+    })
+}
+
+// school_accounts.gender is an int code; older imports stored it as text
+fn gender_codes_to_int(db: &Database) -> StepFuture<'_> {
+    Box::pin(async move {
+        let pipeline = [
+            doc! { "$match": { "gender": { "$type": "string" } } },
+            doc! { "$project": { "gender": 1 } },
+            doc! { "$out": GENDER_TEXT_COLLECTION },
+        ];
+        db.collection::<Document>("school_accounts")
+            .aggregate(pipeline, None)
+            .await
+            .map_err(|e| format!("Failed to save the text gender values: {}", e))?;
+
+        let modified = transform_in_batches(db, "school_accounts", doc! { "gender": { "$type": "string" } }, |document| {
+            let text = document.get_str("gender").ok()?.trim();
+            match text.parse::<i32>() {
+                Ok(code) => Some(doc! { "$set": { "gender": code } }),
+                Err(_) => {
+                    warn!("Leaving non-numeric gender '{}' on {:?}", text, document.get("_id"));
+                    None
+                },
+            }
+        }).await?;
+        info!("Converted gender to int on {} document(s)", modified);
+        Ok(())
+    })
+}
+
+// Put back the text saved by gender_codes_to_int, on the documents it converted that
+// still hold the converted code; ints stored any other way are left alone
+fn gender_codes_to_string(db: &Database) -> StepFuture<'_> {
+    Box::pin(async move {
+        let saved = db.collection::<Document>(GENDER_TEXT_COLLECTION);
+        let accounts = db.collection::<Document>("school_accounts");
+        let options = UpdateOptions::builder().bypass_document_validation(true).build();
+        let mut cursor = saved.find(None, None)
+            .await
+            .map_err(|e| format!("Failed to read the saved gender values: {}", e))?;
+
+        let mut restored = 0;
+        while let Some(entry) = cursor.try_next().await.map_err(|e| e.to_string())? {
+            let (Some(id), Ok(text)) = (entry.get("_id"), entry.get_str("gender")) else { continue };
+            let Ok(code) = text.trim().parse::<i32>() else { continue };
+            let result = accounts.update_one(
+                doc! { "_id": id.clone(), "gender": code },
+                doc! { "$set": { "gender": text } },
+                options.clone(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+            restored += result.modified_count;
+        }
+
+        saved.drop(None).await.map_err(|e| format!("Failed to drop {}: {}", GENDER_TEXT_COLLECTION, e))?;
+        info!("Restored text gender on {} document(s)", restored);
+        Ok(())
+    })
+}
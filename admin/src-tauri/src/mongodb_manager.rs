@@ -1,9 +1,16 @@
 // src/mongodb_manager.rs
 
 use crate::mongodb_schema;
+use crate::migrations;
+use crate::timezone::{format_output_fields, InstitutionTimezone};
+use crate::api_server::services::coercion_service::{coerce_document, coerce_for_collection, describe_errors};
+use crate::api_server::services::defaults_service::{prepare_insert, strip_server_managed};
+use crate::api_server::services::SchemaCache;
+use crate::api_server::services::validation_service::describe_write_error;
+use crate::api_server::events::{ChangeAction, EventBus};
 
 use mongodb::{Client, Database, options::ClientOptions};
-use mongodb::bson::{doc, Document};
+use mongodb::bson::Document;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tauri::State;
@@ -12,6 +19,12 @@ use futures_util::stream::StreamExt;
 use tracing::{info, error};
 
 // Define MongoDB connection state
+// Database the app works in, shared with the command-line actions
+pub const DATABASE_NAME: &str = "app_database";
+
+// Server the app connects to on startup
+pub const DEFAULT_CONNECTION_STRING: &str = "mongodb://localhost:27017";
+
 #[derive(Debug)]
 pub struct MongoDbState {
     pub client: Arc<Mutex<Option<Client>>>, // Add pub modifier here
@@ -36,6 +49,15 @@ impl MongoDbState {
         let client = client_guard.as_ref().unwrap();
         Ok(client.database(&self.database_name))
     }
+
+    // The admin database on the same connection, for server-wide commands like currentOp
+    pub async fn get_admin_database(&self) -> Result<Database, String> {
+        let client_guard = self.client.lock().await;
+        match client_guard.as_ref() {
+            Some(client) => Ok(client.database("admin")),
+            None => Err("Database connection not initialized. Call connect() first.".into()),
+        }
+    }
 }
 
 // Make MongoDbState cloneable
@@ -51,6 +73,7 @@ impl Clone for MongoDbState {
 #[tauri::command]
 pub async fn connect_mongodb(
     mongodb_state: State<'_, MongoDbState>,
+    schema_cache: State<'_, SchemaCache>,
     connection_string: String,
 ) -> Result<(), String> {
     let mut client_guard = mongodb_state.client.lock().await;
@@ -76,106 +99,71 @@ pub async fn connect_mongodb(
         .await
         .map_err(|e| format!("Failed to connect to MongoDB: {}", e))?;
     
-    // Store the client
+    // Bring the data up to date; a database migrated by a newer app is refused
+    migrations::run_pending(&client.database(&mongodb_state.database_name)).await?;
+    
+    // Store the client; schemas cached from another server no longer apply
     *client_guard = Some(client);
+    schema_cache.invalidate_all();
     
     Ok(())
 }
 
 #[tauri::command]
-pub async fn disconnect_mongodb(mongodb_state: State<'_, MongoDbState>) -> Result<(), String> {
+pub async fn disconnect_mongodb(
+    mongodb_state: State<'_, MongoDbState>,
+    schema_cache: State<'_, SchemaCache>,
+) -> Result<(), String> {
     let mut client_guard = mongodb_state.client.lock().await;
     *client_guard = None;
+    schema_cache.invalidate_all();
     Ok(())
 }
 
 #[tauri::command]
 pub async fn get_collection_schema(
     mongodb_state: State<'_, MongoDbState>,
+    schema_cache: State<'_, SchemaCache>,
     collection_name: String,
 ) -> Result<serde_json::Value, String> {
-    println!("DEBUG: get_collection_schema called for collection '{}'", collection_name);
-    
     let db = mongodb_state.get_database().await?;
-    println!("DEBUG: Successfully got database reference");
-    
-    let command = doc! {
-        "listCollections": 1,
-        "filter": { "name": &collection_name }
-    };
-    
-    println!("DEBUG: Executing listCollections command with filter for '{}'", collection_name);
-    let response = db.run_command(command, None)
-        .await
-        .map_err(|e| {
-            println!("DEBUG: Error running command: {}", e);
-            format!("Failed to get collection info: {}", e)
-        })?;
-    
-    println!("DEBUG: Got response from listCollections");
-    let cursor = response.get_document("cursor")
-        .map_err(|e| {
-            println!("DEBUG: Error getting cursor from response: {:?}", e);
-            "Invalid response format"
-        })?;
-    
-    let batches = cursor.get_array("firstBatch")
-        .map_err(|e| {
-            println!("DEBUG: Error getting firstBatch from cursor: {:?}", e);
-            "No collections found"
-        })?;
-    
-    if batches.is_empty() {
-        println!("DEBUG: No collections found matching the name '{}'", collection_name);
-        return Err("Collection not found".into());
-    }
-    
-    println!("DEBUG: Found {} collections", batches.len());
-    let coll_info = batches[0].as_document().ok_or_else(|| {
-        println!("DEBUG: First item in batches is not a document");
-        "Invalid collection info"
-    })?;
-    
-    println!("DEBUG: Getting options from collection info");
-    let options = coll_info.get_document("options")
-        .map_err(|e| {
-            println!("DEBUG: No options found in collection info: {:?}", e);
-            "No options found"
-        })?;
-    
-    println!("DEBUG: Getting validator from options");
-    let validator = options.get_document("validator")
-        .map_err(|e| {
-            println!("DEBUG: No validator found in options: {:?}", e);
-            "No validator found"
-        })?;
-    
-    println!("DEBUG: Getting $jsonSchema from validator");
-    let json_schema = validator.get_document("$jsonSchema")
-        .map_err(|e| {
-            println!("DEBUG: No JSON schema found in validator: {:?}", e);
-            "No JSON schema found"
-        })?;
-    
-    println!("DEBUG: Converting BSON to JSON");
-    let schema: serde_json::Value = mongodb::bson::from_bson(json_schema.clone().into())
-        .map_err(|e| {
-            println!("DEBUG: BSON to JSON conversion failed: {}", e);
-            format!("BSON to JSON conversion failed: {}", e)
-        })?;
-    
-    println!("DEBUG: Successfully retrieved schema for collection '{}'", collection_name);
-    Ok(schema)
+    let json_schema = schema_cache.schema(&db, &collection_name).await?;
+    mongodb::bson::from_bson(json_schema.into())
+        .map_err(|e| format!("BSON to JSON conversion failed: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_migration_status(
+    mongodb_state: State<'_, MongoDbState>,
+) -> Result<migrations::MigrationStatus, String> {
+    let db = mongodb_state.get_database().await?;
+    migrations::status(&db).await
+}
+
+// Undo migrations newer than `target_version`; returns the versions rolled back
+#[tauri::command]
+pub async fn rollback_migrations(
+    mongodb_state: State<'_, MongoDbState>,
+    schema_cache: State<'_, SchemaCache>,
+    target_version: i64,
+) -> Result<Vec<i64>, String> {
+    let db = mongodb_state.get_database().await?;
+    let result = migrations::rollback_to(&db, target_version).await;
+    schema_cache.invalidate_all();
+    result
 }
 
 #[tauri::command]
 pub async fn initialize_library_collections(
     state: tauri::State<'_, MongoDbState>,
+    schema_cache: State<'_, SchemaCache>,
 ) -> Result<(), String> {
     info!("Initializing library collections via Tauri command");
     let db = state.get_database().await?;
     
-    match crate::mongodb_schema::initialize_library_collections(&db).await {
+    let result = crate::mongodb_schema::initialize_library_collections(&db).await;
+    schema_cache.invalidate_all();
+    match result {
         Ok(_) => {
             info!("Successfully initialized library collections via Tauri command");
             Ok(())
@@ -191,6 +179,9 @@ pub async fn initialize_library_collections(
 #[tauri::command]
 pub async fn insert_document(
     mongodb_state: State<'_, MongoDbState>,
+    schema_cache: State<'_, SchemaCache>,
+    event_bus: State<'_, EventBus>,
+    timezone: State<'_, InstitutionTimezone>,
     collection_name: String,
     document: serde_json::Value,
 ) -> Result<String, String> {
@@ -201,123 +192,34 @@ pub async fn insert_document(
     let mut doc = mongodb::bson::to_document(&document)
         .map_err(|e| format!("Failed to convert document to BSON: {}", e))?;
     
-    // Retrieve collection schema
-    let schema = get_collection_schema_internal(&db, &collection_name).await?;
+    // Server-managed fields, created_at and defaults, as for inserts through the API
+    let schema = schema_cache.schema(&db, &collection_name).await.unwrap_or_default();
+    let defaults = schema_cache.defaults(&db, &collection_name).await.unwrap_or_default();
+    prepare_insert(&mut doc, &defaults);
     
-    // Process date fields
-    if let Some(properties) = schema.get("properties").and_then(|p| p.as_document()) {
-        for (field, spec) in properties {
-            // Check if the field is a date type in the schema
-            if let Some(spec_doc) = spec.as_document() {
-                if let Some("date") = spec_doc.get("bsonType").and_then(|b| b.as_str()) {
-                    // Check if the field exists in the document as a string
-                    if let Some(bson::Bson::String(date_str)) = doc.get(field) {
-                        // Parse the string date to a DateTime
-                        // This assumes ISO format like "2025-03-31T17:18"
-                        if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(date_str) {
-                            // Convert to MongoDB DateTime and update the document
-                            let mongo_date = bson::DateTime::from_millis(datetime.timestamp_millis());
-                            doc.insert(field, mongo_date);
-                        } else {
-                            // If parsing fails, try a more flexible approach
-                            // This handles formats like "2025-03-31T17:18" without seconds
-                            let extended_date_str = format!("{}:00Z", date_str);
-                            if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(&extended_date_str) {
-                                let mongo_date = bson::DateTime::from_millis(datetime.timestamp_millis());
-                                doc.insert(field, mongo_date);
-                            } else {
-                                return Err(format!("Failed to parse date field '{}': {}", field, date_str));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+    // Convert fields to their schema types; offset-less dates are in the institution timezone
+    coerce_document(&mut doc, &schema, &timezone)
+        .map_err(|errors| describe_errors(&errors))?;
     
     let result = collection.insert_one(doc, None)
         .await
-        .map_err(|e| format!("Failed to insert document: {}", e))?;
+        .map_err(|e| format!("Failed to insert document: {}", describe_write_error(&e)))?;
     
     match result.inserted_id.as_object_id() {
-        Some(id) => Ok(id.to_hex()),
+        Some(id) => {
+            event_bus.publish(&collection_name, ChangeAction::Insert, vec![id.to_hex()], None);
+            Ok(id.to_hex())
+        },
         None => Err("Failed to get inserted document ID".into()),
     }
 }
 
 
-async fn get_collection_schema_internal(
-    db: &Database,
-    collection_name: &str,
-) -> Result<Document, String> {
-    println!("DEBUG: get_collection_schema_internal called for collection '{}'", collection_name);
-    
-    let command = doc! {
-        "listCollections": 1,
-        "filter": { "name": collection_name }
-    };
-    
-    println!("DEBUG: Executing listCollections command with filter for '{}'", collection_name);
-    let response = db.run_command(command, None)
-        .await
-        .map_err(|e| {
-            println!("DEBUG: Error running command: {}", e);
-            format!("Failed to get collection info: {}", e)
-        })?;
-    
-    println!("DEBUG: Got response from listCollections");
-    let cursor = response.get_document("cursor")
-        .map_err(|e| {
-            println!("DEBUG: Error getting cursor from response: {:?}", e);
-            "Invalid response format".to_string()
-        })?;
-    
-    let batches = cursor.get_array("firstBatch")
-        .map_err(|e| {
-            println!("DEBUG: Error getting firstBatch from cursor: {:?}", e);
-            "No collections found".to_string()
-        })?;
-    
-    if batches.is_empty() {
-        println!("DEBUG: No collections found matching the name '{}'", collection_name);
-        return Err("Collection not found".into());
-    }
-    
-    println!("DEBUG: Found {} collections", batches.len());
-    let coll_info = batches[0].as_document().ok_or_else(|| {
-        println!("DEBUG: First item in batches is not a document");
-        "Invalid collection info".to_string()
-    })?;
-    
-    println!("DEBUG: Getting options from collection info");
-    let options = coll_info.get_document("options")
-        .map_err(|e| {
-            println!("DEBUG: No options found in collection info: {:?}", e);
-            "No options found".to_string()
-        })?;
-    
-    println!("DEBUG: Getting validator from options");
-    let validator = options.get_document("validator")
-        .map_err(|e| {
-            println!("DEBUG: No validator found in options: {:?}", e);
-            "No validator found".to_string()
-        })?;
-    
-    println!("DEBUG: Getting $jsonSchema from validator");
-    let json_schema = validator.get_document("$jsonSchema")
-        .map_err(|e| {
-            println!("DEBUG: No JSON schema found in validator: {:?}", e);
-            "No JSON schema found".to_string()
-        })?;
-    
-    println!("DEBUG: Successfully retrieved schema for collection '{}'", collection_name);
-    Ok(json_schema.clone())
-}
-
 // Find documents function (not generic)
 #[tauri::command]
 pub async fn find_documents(
     mongodb_state: State<'_, MongoDbState>,
+    timezone: State<'_, InstitutionTimezone>,
     collection_name: String,
     filter: Document,
 ) -> Result<Vec<Document>, String> {
@@ -332,8 +234,8 @@ pub async fn find_documents(
     while let Some(document_result) = cursor.next().await {
         match document_result {
             Ok(mut doc) => {
-                // Convert BSON date fields to readable string format
-                format_date_fields(&mut doc);
+                // Convert BSON date fields to ISO 8601 strings in the institution timezone
+                format_output_fields(&mut doc, &timezone);
                 documents.push(doc);
             },
             Err(e) => return Err(format!("Error retrieving document: {}", e)),
@@ -343,33 +245,16 @@ pub async fn find_documents(
     Ok(documents)
 }
 
-// Helper function to format date fields in a document
-fn format_date_fields(doc: &mut Document) {
-    // Get a list of keys to avoid borrowing issues
-    let keys: Vec<String> = doc.keys().cloned().collect();
-    
-    for key in keys {
-        if let Some(bson::Bson::DateTime(date_time)) = doc.get(&key) {
-            // Convert MongoDB DateTime to chrono::DateTime
-            let chrono_date = chrono::DateTime::from_timestamp_millis(date_time.timestamp_millis())
-                .unwrap_or_else(|| chrono::DateTime::from_timestamp(0, 0).unwrap());
-            
-            // Format the date as a readable string (ISO 8601 format)
-            let formatted_date = chrono_date.format("%Y-%m-%d %H:%M:%S").to_string();
-            
-            // Replace the BSON DateTime with the formatted string
-            doc.insert(key, bson::Bson::String(formatted_date));
-        }
-    }
-}
-
 // Update document by ID
 #[tauri::command]
 pub async fn update_document(
     mongodb_state: State<'_, MongoDbState>,
+    schema_cache: State<'_, SchemaCache>,
+    event_bus: State<'_, EventBus>,
+    timezone: State<'_, InstitutionTimezone>,
     collection_name: String,
     id: String,
-    update: Document, // Use concrete Document type
+    mut update: Document, // Use concrete Document type
 ) -> Result<bool, String> {
     let db = mongodb_state.get_database().await?;
     let collection = db.collection::<Document>(&collection_name);
@@ -377,13 +262,21 @@ pub async fn update_document(
     let object_id = mongodb::bson::oid::ObjectId::parse_str(&id)
         .map_err(|e| format!("Invalid ObjectId: {}", e))?;
     
+    strip_server_managed(&mut update);
+    coerce_for_collection(&schema_cache, &db, &collection_name, &mut update, &timezone)
+        .await
+        .map_err(|errors| describe_errors(&errors))?;
+    
     let filter = mongodb::bson::doc! { "_id": object_id };
     let update_doc = mongodb::bson::doc! { "$set": update };
     
     let result = collection.update_one(filter, update_doc, None)
         .await
-        .map_err(|e| format!("Failed to update document: {}", e))?;
+        .map_err(|e| format!("Failed to update document: {}", describe_write_error(&e)))?;
     
+    if result.modified_count > 0 {
+        event_bus.publish(&collection_name, ChangeAction::Update, vec![id], None);
+    }
     Ok(result.modified_count > 0)
 }
 
@@ -391,6 +284,7 @@ pub async fn update_document(
 #[tauri::command]
 pub async fn delete_document(
     mongodb_state: State<'_, MongoDbState>,
+    event_bus: State<'_, EventBus>,
     collection_name: String,
     id: String,
 ) -> Result<bool, String> {
@@ -406,11 +300,14 @@ pub async fn delete_document(
         .await
         .map_err(|e| format!("Failed to delete document: {}", e))?;
     
+    if result.deleted_count > 0 {
+        event_bus.publish(&collection_name, ChangeAction::Delete, vec![id], None);
+    }
     Ok(result.deleted_count > 0)
 }
 
 pub async fn auto_connect(mongodb_state: &MongoDbState) -> Result<(), String> {
-    let connection_string = "mongodb://localhost:27017";
+    let connection_string = DEFAULT_CONNECTION_STRING;
     let mut client_guard = mongodb_state.client.lock().await;
     
     if client_guard.is_some() {
@@ -431,11 +328,13 @@ pub async fn auto_connect(mongodb_state: &MongoDbState) -> Result<(), String> {
         .await
         .map_err(|e| format!("Failed to connect to MongoDB: {}", e))?;
 
-    // Initialize database schema
+    // Initialize database schema, unless a newer app already migrated it
     let db = client.database(&mongodb_state.database_name);
+    migrations::check_compatible(&db).await?;
     mongodb_schema::initialize_database(&db)
         .await
         .map_err(|e| format!("Failed to initialize database: {}", e))?;
+    migrations::run_pending(&db).await?;
 
     *client_guard = Some(client);
     Ok(())
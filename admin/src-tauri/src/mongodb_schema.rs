@@ -5,7 +5,7 @@ use mongodb::{
     IndexModel,
 };
 use std::time::Duration;
-use mongodb::bson::{doc, Document};
+use mongodb::bson::{doc, Bson, Document};
 use anyhow::Result;
 use crate::lib_mongodb_schema;
 
@@ -89,11 +89,25 @@ pub fn get_row_height_properties() -> Document {
     doc! {
         "row_height": { 
             "bsonType": "int", 
-            "description": "Maximum usage limit for this document, initially set to 20" 
+            "description": "Height of this document's table row in pixels" 
         }
     }
 }
 
+// Row height new documents start with
+pub const DEFAULT_ROW_HEIGHT: i32 = 40;
+
+// Values the archive, pin and row height fields start with on insert
+pub fn standard_field_defaults() -> Vec<(String, Bson)> {
+    vec![
+        ("is_archive".to_string(), Bson::Boolean(false)),
+        ("archive_history".to_string(), Bson::Array(Vec::new())),
+        ("pinned_by".to_string(), Bson::Array(Vec::new())),
+        ("pinned_history".to_string(), Bson::Array(Vec::new())),
+        ("row_height".to_string(), Bson::Int32(DEFAULT_ROW_HEIGHT)),
+    ]
+}
+
 // Helper function to create archive index - made public
 pub fn create_archive_index() -> IndexModel {
     IndexModel::builder()
@@ -339,6 +353,7 @@ async fn create_ui_metadata_collection(db: &Database) -> Result<()> {
                                 "short_names": { "bsonType": "object", "description": "Short display names for columns" }
                             }
                         },
+                        "defaults": { "bsonType": "array", "description": "Values missing fields get on insert, as { field, value }" },
                         "created_at": { "bsonType": "date", "description": "Creation timestamp" },
                         "updated_at": { "bsonType": "date", "description": "Last update timestamp" }
                     }
@@ -374,7 +389,7 @@ async fn create_ui_metadata_collection(db: &Database) -> Result<()> {
 
 // metadata for collections start here
 // Keep the UI metadata helper functions as is, but update collection list to include only essential collections
-const DEFAULT_COLUMN_WIDTH: i32 = 200;
+pub const DEFAULT_COLUMN_WIDTH: i32 = 200;
 
 fn create_default_ui_settings() -> Vec<Document> {
     // Include only essential collections
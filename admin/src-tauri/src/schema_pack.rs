@@ -0,0 +1,133 @@
+// src/schema_pack.rs
+
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::api_server::services::collection_service::{ensure_collection, CollectionDefinition};
+
+// Directory with extra or overriding schema packs (*.json / *.toml)
+pub const SCHEMA_PACK_DIR_ENV: &str = "SCHEMA_PACK_DIR";
+
+// Packs shipped with the app, as (file name, contents)
+const BUNDLED_PACKS: [(&str, &str); 1] = [
+    ("library.json", include_str!("../schema_packs/library.json")),
+];
+
+// A named set of collection definitions that are installed together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaPack {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub collections: Vec<CollectionDefinition>,
+}
+
+impl SchemaPack {
+    // Parse a pack file; the format follows the extension, JSON unless it is .toml
+    pub fn parse(file_name: &str, text: &str) -> Result<Self, String> {
+        let pack: SchemaPack = if file_name.ends_with(".toml") {
+            toml::from_str(text).map_err(|e| format!("Invalid schema pack {}: {}", file_name, e))?
+        } else {
+            serde_json::from_str(text).map_err(|e| format!("Invalid schema pack {}: {}", file_name, e))?
+        };
+        for definition in &pack.collections {
+            definition.check()
+                .map_err(|e| format!("Schema pack {}, collection '{}': {}", file_name, definition.name, e))?;
+        }
+        Ok(pack)
+    }
+}
+
+// The packs shipped with the app. They are part of the build, so one that fails
+// to parse is an error rather than a pack to skip.
+fn bundled_packs() -> Result<Vec<SchemaPack>, String> {
+    BUNDLED_PACKS.iter()
+        .map(|(file_name, text)| SchemaPack::parse(file_name, text).map_err(|e| format!("Bundled {}", e)))
+        .collect()
+}
+
+// Bundled packs followed by the ones in SCHEMA_PACK_DIR. A user pack with the same
+// name as a bundled one replaces it. Unreadable user files are skipped with a warning.
+pub fn available_packs() -> Result<Vec<SchemaPack>, String> {
+    let mut packs = bundled_packs()?;
+
+    if let Ok(dir) = std::env::var(SCHEMA_PACK_DIR_ENV) {
+        for pack in load_dir(Path::new(&dir)) {
+            packs.retain(|p| p.name != pack.name);
+            packs.push(pack);
+        }
+    }
+    Ok(packs)
+}
+
+pub fn find_pack(name: &str) -> Result<Option<SchemaPack>, String> {
+    Ok(available_packs()?.into_iter().find(|pack| pack.name == name))
+}
+
+fn load_dir(dir: &Path) -> Vec<SchemaPack> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Can't read schema pack directory {}: {}", dir.display(), e);
+            return Vec::new();
+        },
+    };
+
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("json" | "toml")))
+        .collect();
+    files.sort();
+
+    files.iter()
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_string_lossy().to_string();
+            let parsed = std::fs::read_to_string(path)
+                .map_err(|e| format!("Can't read schema pack {}: {}", path.display(), e))
+                .and_then(|text| SchemaPack::parse(&file_name, &text));
+            match parsed {
+                Ok(pack) => Some(pack),
+                Err(e) => {
+                    warn!("{}", e);
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+// Create the collections of the pack that don't exist yet, with their missing indexes
+// and UI metadata. Existing validators are kept; the names of the collections whose
+// validator no longer matches the pack are returned.
+pub async fn apply_pack(db: &Database, pack: &SchemaPack) -> Result<Vec<String>, String> {
+    let mut drifted = Vec::new();
+    for definition in &pack.collections {
+        if ensure_collection(db, definition).await? {
+            drifted.push(definition.name.clone());
+        }
+    }
+    if !drifted.is_empty() {
+        warn!("Schema pack '{}': the validators of {} differ from the pack and were kept", pack.name, drifted.join(", "));
+    }
+    info!("Applied schema pack '{}' ({} collections)", pack.name, pack.collections.len());
+    Ok(drifted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bundled_packs() {
+        let packs = bundled_packs().unwrap();
+        assert_eq!(packs.len(), BUNDLED_PACKS.len());
+
+        let library = packs.iter().find(|pack| pack.name == "library").expect("library pack");
+        let names: Vec<&str> = library.collections.iter().map(|c| c.name.as_str()).collect();
+        for expected in ["school_accounts", "attendance", "purposes", "semesters", "settings_styles"] {
+            assert!(names.contains(&expected), "collection {} in {:?}", expected, names);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+// src/timezone.rs
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use mongodb::bson::{Bson, Document};
+use tracing::warn;
+
+// Environment variable holding the institution timezone, e.g. "Asia/Manila", "+08:00", "UTC" or "local"
+pub const TIMEZONE_ENV: &str = "INSTITUTION_TIMEZONE";
+
+// Query parameter that overrides the institution timezone for one request
+pub const TIMEZONE_PARAM: &str = "tz";
+
+// Range of offsets in use anywhere, in minutes from UTC
+const MIN_OFFSET_MINUTES: i32 = -12 * 60;
+const MAX_OFFSET_MINUTES: i32 = 14 * 60;
+
+// Date-time layouts accepted without an explicit offset; they are read as wall-clock
+// time in the institution timezone
+const LOCAL_DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+];
+
+// Timezone used to show dates and to read dates typed without an offset.
+// `Local` follows the machine's zone and `Named` an IANA zone, both including
+// daylight saving; a fixed offset pins it regardless of where the app runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InstitutionTimezone {
+    #[default]
+    Local,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl InstitutionTimezone {
+    // Accepts "local", "UTC"/"Z", IANA names like "Europe/Oslo", and offsets like
+    // "+08:00", "+0800", "+8" or "UTC+8". An offset without a sign is east of UTC,
+    // which is what "+08:00" becomes when a query string leaves the plus unencoded.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let trimmed = value.trim();
+        let lower = trimmed.to_lowercase();
+        if lower == "local" {
+            return Ok(Self::Local);
+        }
+        if matches!(lower.as_str(), "utc" | "gmt" | "z") {
+            return Ok(Self::Fixed(FixedOffset::east_opt(0).unwrap()));
+        }
+        if let Ok(zone) = trimmed.parse::<Tz>() {
+            return Ok(Self::Named(zone));
+        }
+
+        let offset = lower.strip_prefix("utc").or_else(|| lower.strip_prefix("gmt")).unwrap_or(&lower);
+        let (sign, rest) = match offset.chars().next() {
+            Some('+') => (1, &offset[1..]),
+            Some('-') => (-1, &offset[1..]),
+            Some(c) if c.is_ascii_digit() => (1, offset),
+            _ => return Err(format!(
+                "Invalid timezone '{}': expected local, UTC, an IANA name like Asia/Manila or an offset like +08:00",
+                trimmed
+            )),
+        };
+
+        let (hours, minutes) = match rest.split_once(':') {
+            Some((h, m)) => (h, m),
+            None if rest.len() == 4 => rest.split_at(2),
+            None => (rest, "0"),
+        };
+        let hours: i32 = hours.parse().map_err(|_| format!("Invalid timezone '{}'", trimmed))?;
+        let minutes: i32 = minutes.parse().map_err(|_| format!("Invalid timezone '{}'", trimmed))?;
+        // Real zones run from UTC-12:00 to UTC+14:00
+        let total_minutes = sign * (hours * 60 + minutes);
+        if minutes > 59 || !(MIN_OFFSET_MINUTES..=MAX_OFFSET_MINUTES).contains(&total_minutes) {
+            return Err(format!("Invalid timezone '{}': offset must be between -12:00 and +14:00", trimmed));
+        }
+
+        FixedOffset::east_opt(total_minutes * 60)
+            .map(Self::Fixed)
+            .ok_or_else(|| format!("Invalid timezone '{}'", trimmed))
+    }
+
+    // The configured institution timezone, falling back to the machine's zone
+    pub fn from_env() -> Self {
+        match std::env::var(TIMEZONE_ENV) {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|e| {
+                warn!("{}; using the local timezone", e);
+                Self::Local
+            }),
+            Err(_) => Self::Local,
+        }
+    }
+
+    // Apply the per-request override from the `tz` query parameter, if any
+    pub fn with_override(&self, params: &std::collections::HashMap<String, String>) -> Result<Self, String> {
+        match params.get(TIMEZONE_PARAM) {
+            Some(value) if !value.trim().is_empty() => Self::parse(value),
+            _ => Ok(*self),
+        }
+    }
+
+    fn offset_at(&self, utc: &DateTime<Utc>) -> FixedOffset {
+        match self {
+            Self::Local => Local.offset_from_utc_datetime(&utc.naive_utc()).fix(),
+            Self::Named(zone) => zone.offset_from_utc_datetime(&utc.naive_utc()).fix(),
+            Self::Fixed(offset) => *offset,
+        }
+    }
+
+    fn zoned(&self, date_time: &mongodb::bson::DateTime) -> DateTime<FixedOffset> {
+        let utc = DateTime::from_timestamp_millis(date_time.timestamp_millis()).unwrap_or_default();
+        utc.with_timezone(&self.offset_at(&utc))
+    }
+
+    // ISO 8601 with offset, e.g. "2025-03-31T09:00:00+08:00"
+    pub fn format_iso(&self, date_time: &mongodb::bson::DateTime) -> String {
+        self.zoned(date_time).to_rfc3339_opts(SecondsFormat::AutoSi, false)
+    }
+
+    // Wall-clock time without offset, for spreadsheet-style output
+    pub fn format_wall_clock(&self, date_time: &mongodb::bson::DateTime) -> String {
+        self.zoned(date_time).format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    // Milliseconds to add to a UTC timestamp to get wall-clock time in this zone
+    pub fn offset_millis(&self, date_time: &mongodb::bson::DateTime) -> i64 {
+        self.zoned(date_time).offset().local_minus_utc() as i64 * 1000
+    }
+
+    fn wall_clock_to_utc(&self, naive: NaiveDateTime) -> Result<mongodb::bson::DateTime, String> {
+        let utc = match self {
+            // In a daylight saving overlap take the earlier instant; a gap has no valid instant
+            Self::Local => Local.from_local_datetime(&naive).earliest().map(|dt| dt.with_timezone(&Utc)),
+            Self::Named(zone) => zone.from_local_datetime(&naive).earliest().map(|dt| dt.with_timezone(&Utc)),
+            Self::Fixed(offset) => offset.from_local_datetime(&naive).single().map(|dt| dt.with_timezone(&Utc)),
+        };
+        utc.map(|dt| mongodb::bson::DateTime::from_millis(dt.timestamp_millis()))
+            .ok_or_else(|| format!("{} does not exist in the institution timezone", naive))
+    }
+
+    // Parse user input: RFC 3339 keeps its own offset, anything without an offset
+    // (including a bare date, read as midnight) is wall-clock time in this zone
+    pub fn parse_datetime(&self, value: &str) -> Result<mongodb::bson::DateTime, String> {
+        let value = value.trim();
+        if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+            return Ok(mongodb::bson::DateTime::from_millis(dt.timestamp_millis()));
+        }
+        for format in LOCAL_DATETIME_FORMATS {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+                return self.wall_clock_to_utc(naive);
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return self.wall_clock_to_utc(date.and_hms_opt(0, 0, 0).unwrap());
+        }
+        Err(format!("Invalid date '{}': expected ISO 8601, e.g. 2025-03-31 or 2025-03-31T09:00", value))
+    }
+
+    // Start (inclusive) and end (exclusive) of a calendar day in this zone
+    pub fn day_bounds(&self, date: NaiveDate) -> Result<(mongodb::bson::DateTime, mongodb::bson::DateTime), String> {
+        let start = self.wall_clock_to_utc(date.and_hms_opt(0, 0, 0).unwrap())?;
+        let next = date.succ_opt().ok_or_else(|| format!("Date out of range: {}", date))?;
+        let end = self.wall_clock_to_utc(next.and_hms_opt(0, 0, 0).unwrap())?;
+        Ok((start, end))
+    }
+}
+
+// Prepare a document for JSON output, at any depth: dates become ISO 8601 strings
+// carrying the timezone offset, decimals become their exact string form
+pub fn format_output_fields(doc: &mut Document, tz: &InstitutionTimezone) {
+    for (_, value) in doc.iter_mut() {
+        format_output_value(value, tz);
+    }
+}
+
+fn format_output_value(value: &mut Bson, tz: &InstitutionTimezone) {
+    match value {
+        Bson::DateTime(date_time) => *value = Bson::String(tz.format_iso(date_time)),
+        // serde would otherwise write the raw 16 bytes
+        Bson::Decimal128(decimal) => *value = Bson::String(decimal.to_string()),
+        Bson::Document(doc) => format_output_fields(doc, tz),
+        Bson::Array(items) => items.iter_mut().for_each(|item| format_output_value(item, tz)),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    fn fixed(minutes: i32) -> InstitutionTimezone {
+        InstitutionTimezone::Fixed(FixedOffset::east_opt(minutes * 60).unwrap())
+    }
+
+    fn utc(rfc3339: &str) -> mongodb::bson::DateTime {
+        mongodb::bson::DateTime::parse_rfc3339_str(rfc3339).unwrap()
+    }
+
+    #[test]
+    fn parses_timezones() {
+        let cases = [
+            ("local", InstitutionTimezone::Local),
+            (" Local ", InstitutionTimezone::Local),
+            ("UTC", fixed(0)),
+            ("gmt", fixed(0)),
+            ("Z", fixed(0)),
+            ("+08:00", fixed(8 * 60)),
+            ("+0800", fixed(8 * 60)),
+            ("+8", fixed(8 * 60)),
+            ("UTC+8", fixed(8 * 60)),
+            ("GMT-03:30", fixed(-(3 * 60 + 30))),
+            ("+05:45", fixed(5 * 60 + 45)),
+            ("-12:00", fixed(-12 * 60)),
+            ("+14:00", fixed(14 * 60)),
+            // An unencoded "+" in a query string arrives as a space
+            (" 08:00", fixed(8 * 60)),
+            ("0800", fixed(8 * 60)),
+            ("Europe/Oslo", InstitutionTimezone::Named(chrono_tz::Europe::Oslo)),
+            ("Asia/Manila", InstitutionTimezone::Named(chrono_tz::Asia::Manila)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(InstitutionTimezone::parse(input), Ok(expected), "timezone {:?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_timezones() {
+        let cases = ["", "Europe/Atlantis", "europe/oslo", "+ab", "+08:xx", "+08:60", "+14:01", "+14:59", "-12:30", "-23:59", "+24"];
+        for input in cases {
+            assert!(InstitutionTimezone::parse(input).is_err(), "timezone {:?}", input);
+        }
+    }
+
+    #[test]
+    fn reads_wall_clock_input_in_the_zone() {
+        let tz = fixed(8 * 60);
+        let cases = [
+            // Explicit offsets win over the institution zone
+            ("2025-03-31T09:00:00Z", "2025-03-31T09:00:00Z"),
+            ("2025-03-31T09:00:00-02:00", "2025-03-31T11:00:00Z"),
+            ("2025-03-31T09:00:00", "2025-03-31T01:00:00Z"),
+            ("2025-03-31T09:00", "2025-03-31T01:00:00Z"),
+            ("2025-03-31 09:00:00.250", "2025-03-31T01:00:00.250Z"),
+            (" 2025-03-31 09:00 ", "2025-03-31T01:00:00Z"),
+            ("2025-03-31", "2025-03-30T16:00:00Z"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(tz.parse_datetime(input), Ok(utc(expected)), "date {:?}", input);
+        }
+        for input in ["31/03/2025", "2025-02-30", "yesterday"] {
+            assert!(tz.parse_datetime(input).is_err(), "date {:?}", input);
+        }
+    }
+
+    #[test]
+    fn formats_and_bounds_days_in_the_zone() {
+        let tz = fixed(8 * 60);
+        let instant = utc("2025-03-31T01:00:00Z");
+        assert_eq!(tz.format_iso(&instant), "2025-03-31T09:00:00+08:00");
+        assert_eq!(tz.format_wall_clock(&instant), "2025-03-31 09:00:00");
+        assert_eq!(tz.offset_millis(&instant), 8 * 3_600_000);
+
+        let day = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+        assert_eq!(tz.day_bounds(day), Ok((utc("2025-03-30T16:00:00Z"), utc("2025-03-31T16:00:00Z"))));
+    }
+
+    #[test]
+    fn follows_daylight_saving_in_named_zones() {
+        let tz = InstitutionTimezone::Named(chrono_tz::Europe::Oslo);
+        assert_eq!(tz.format_iso(&utc("2025-01-15T12:00:00Z")), "2025-01-15T13:00:00+01:00");
+        assert_eq!(tz.format_iso(&utc("2025-07-15T12:00:00Z")), "2025-07-15T14:00:00+02:00");
+        assert_eq!(tz.parse_datetime("2025-07-15 14:00"), Ok(utc("2025-07-15T12:00:00Z")));
+        // Clocks skip 02:00-03:00 on the last Sunday of March
+        assert!(tz.parse_datetime("2025-03-30 02:30").is_err());
+
+        let day = NaiveDate::from_ymd_opt(2025, 3, 30).unwrap();
+        assert_eq!(tz.day_bounds(day), Ok((utc("2025-03-29T23:00:00Z"), utc("2025-03-30T22:00:00Z"))));
+    }
+
+    #[test]
+    fn formats_nested_output_fields() {
+        let mut doc = doc! {
+            "added": utc("2025-03-31T01:00:00Z"),
+            "loans": [{ "due": utc("2025-04-01T00:00:00Z") }],
+            "title": "x",
+        };
+        format_output_fields(&mut doc, &fixed(0));
+        assert_eq!(doc, doc! {
+            "added": "2025-03-31T01:00:00+00:00",
+            "loans": [{ "due": "2025-04-01T00:00:00+00:00" }],
+            "title": "x",
+        });
+    }
+}
@@ -357,9 +357,12 @@ export const useDataTableStore = defineStore('dataTable', () => {
 
       let labelField = '_id' // Default label
       const properties = schemaData.properties || {}
-      const uniqueStringFields = Object.keys(properties).filter(
-        (field) => properties[field].bsonType === 'string' && properties[field].unique === true
-      )
+      // Single-field unique indexes, as reported by the server
+      const uniqueKeys: string[][] = schemaData.uniqueKeys || []
+      const uniqueStringFields = uniqueKeys
+        .filter((fields) => fields.length === 1)
+        .map((fields) => fields[0])
+        .filter((field) => properties[field]?.bsonType === 'string')
       if (uniqueStringFields.length > 0) {
         labelField = uniqueStringFields[0] // Prefer unique string fields
       } else {
@@ -583,8 +586,9 @@ export const useDataTableStore = defineStore('dataTable', () => {
     if (bsonType === 'bool') {
       editValue.value = !!currentValue // Ensure boolean
     } else if (bsonType === 'date') {
-      // Ensure date is in YYYY-MM-DDTHH:mm format for datetime-local input
-      editValue.value = currentValue ? new Date(currentValue).toISOString().slice(0, 16) : ''
+      // Dates arrive as ISO 8601 in the institution timezone; keep that wall-clock
+      // time (YYYY-MM-DDTHH:mm) for the datetime-local input
+      editValue.value = currentValue ? String(currentValue).replace(' ', 'T').slice(0, 16) : ''
     } else if (isReferenceField(header)) {
       editValue.value = currentValue || '' // Store the ID for the select
       // Ensure options are loaded for the reference field
@@ -649,11 +653,16 @@ export const useDataTableStore = defineStore('dataTable', () => {
       } else if (bsonType === 'bool') {
         valueToSave = Boolean(editValue.value)
       } else if (bsonType === 'date') {
-        valueToSave = editValue.value ? new Date(editValue.value).toISOString() : null
+        // Sent without an offset so the server reads it in the institution timezone
+        valueToSave = editValue.value || null
       } else if (['int', 'long'].includes(bsonType)) {
         valueToSave = parseInt(editValue.value, 10)
         if (isNaN(valueToSave)) throw new Error('Invalid integer value')
-      } else if (['double', 'decimal'].includes(bsonType)) {
+      } else if (bsonType === 'decimal') {
+        // Sent as text so the server stores the exact Decimal128 value
+        valueToSave = String(editValue.value).trim()
+        if (!/^[-+]?(\d+\.?\d*|\.\d+)$/.test(valueToSave)) throw new Error('Invalid decimal value')
+      } else if (bsonType === 'double') {
         valueToSave = parseFloat(editValue.value)
         if (isNaN(valueToSave)) throw new Error('Invalid number value')
       } else if (bsonType === 'string') {